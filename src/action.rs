@@ -6,6 +6,8 @@ use serde::{
 };
 use strum::Display;
 
+use crate::{cheats::Cheat, emulator::{CpuState, EmulationFault}, isa::InstructionSet, mode::Mode, palette::Palette, persistence::Persistence, romdb::RomMetadata, sprites::Sprite, symbols::SymbolMap};
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Display, Deserialize)]
 pub enum Action {
   Tick,
@@ -16,6 +18,40 @@ pub enum Action {
   Quit,
   Refresh,
   Error(String),
+  Warning(String),
+  Fault(EmulationFault),
+  ContinueAfterError,
+  SkipInstruction,
+  Reset,
+  ReloadRom,
+  RomFileChanged,
+  DismissError,
+  ToggleErrorLog,
+  ScrollErrorLogUp,
+  ScrollErrorLogDown,
+  ToggleCallGraph,
+  ToggleSpriteViewer,
+  SpriteViewerUp,
+  SpriteViewerDown,
+  ToggleSpriteWidth,
+  FollowIndexRegister,
+  ExportSprites,
+  ToggleHeatMap,
+  ToggleCoverage,
+  ExportProfile,
+  FocusCheatSearch,
+  CloseCheatSearch,
+  CheatNewSearch,
+  CheatFilterEqual,
+  CheatFilterChanged,
+  CheatFilterIncreased,
+  CheatFilterDecreased,
+  CheatFilterValue,
+  ToggleFreeze,
+  MoveCheatSelectionUp,
+  MoveCheatSelectionDown,
+  SetCheats(Vec<Cheat>),
+  LoadCheats(Vec<Cheat>),
   Help,
   Redraw(Vec<u8>),
   StartEmulation,
@@ -24,8 +60,44 @@ pub enum Action {
   LoadOpcodesList(Vec<u16>),
   SelectOpcode(u16),
   FocusFileSelector,
+  CloseFileSelector,
   MoveFileSelectorUp,
   MoveFileSelectorDown,
   SelectFile,
   LoadFile(String),
+  SetRomInfo(Option<RomMetadata>),
+  SetSymbols(Option<SymbolMap>),
+  SetProgram(Vec<u8>, InstructionSet),
+  UpdateKeys(Vec<bool>),
+  UpdateCpuState(CpuState),
+  UpdateMemory(Vec<u8>),
+  UpdateSprites(Vec<Sprite>),
+  UpdateHeatMap(Vec<u64>),
+  UpdateCoverage(Vec<bool>),
+  CyclePalette,
+  SetPalette(Palette),
+  SetPersistence(Persistence),
+  SaveRomProfile,
+  PressKey(u8),
+  ReleaseKey(u8),
+  ModeChanged(Mode),
+  SetKeypadLabels(Vec<String>),
+  ScrollHelpUp,
+  ScrollHelpDown,
+}
+
+impl Action {
+  /// Human readable name of the action, e.g. `Focus file selector` for `FocusFileSelector`
+  pub fn description(&self) -> String {
+    let mut description = String::new();
+    for (i, c) in self.to_string().chars().enumerate() {
+      if i > 0 && c.is_uppercase() {
+        description.push(' ');
+        description.extend(c.to_lowercase());
+      } else {
+        description.push(c);
+      }
+    }
+    description
+  }
 }
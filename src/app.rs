@@ -1,8 +1,10 @@
 use std::collections::HashMap;
-use std::path::Components;
-use std::time::Instant;
-use color_eyre::eyre::Result;
-use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use std::fs;
+use std::path::{Components, Path, PathBuf};
+use std::time::{Duration, Instant};
+use color_eyre::eyre::{eyre, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use ratatui::prelude::Rect;
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
@@ -10,42 +12,61 @@ use tracing_subscriber::fmt::format;
 
 use crate::{
   action::Action,
+  cli::Cli,
   components::{Component, screen::Screen},
-  config::Config,
+  config::{key_event_to_string, Config},
   mode::Mode,
   tui,
 };
+use crate::cheats::{Cheat, CheatList};
+use crate::components::call_graph::CallGraph;
+use crate::components::cheat_search::CheatSearchPanel;
+use crate::components::error_log::ErrorLog;
+use crate::components::error_popup::ErrorPopup;
 use crate::components::file_selector::FileSelector;
+use crate::components::help::Help;
+use crate::components::info::RomInfo;
+use crate::components::keypad::Keypad;
+use crate::components::registers::Registers;
 use crate::components::opcodes_list::OpcodesList;
+use crate::components::sprite_viewer::SpriteViewer;
 use crate::components::status::StatusBar;
-use crate::emulator::Chip8Emu;
-
-const KEYBOARD: [KeyCode; 16] = [
-  KeyCode::Char('1'), KeyCode::Char('2'), KeyCode::Char('3'), KeyCode::Char('4'),
-  KeyCode::Char('q'), KeyCode::Char('w'), KeyCode::Char('e'), KeyCode::Char('r'),
-  KeyCode::Char('a'), KeyCode::Char('s'), KeyCode::Char('d'), KeyCode::Char('f'),
-  KeyCode::Char('z'), KeyCode::Char('x'), KeyCode::Char('c'), KeyCode::Char('v'),
-];
-
-fn get_key_from_char(c: &char) -> u8 {
-  match c {
-    '1' => 1,
-    '2' => 2,
-    '3' => 3,
-    '4' => 12,
-    'q' => 4,
-    'w' => 5,
-    'e' => 6,
-    'r' => 13,
-    'a' => 7,
-    's' => 8,
-    'd' => 9,
-    'f' => 14,
-    'z' => 10,
-    'x' => 0,
-    'c' => 11,
-    'v' => 15,
-    _ => u8::MAX,
+use crate::asm::PROGRAM_START;
+use crate::disasm::Disassembly;
+use crate::emulator::{rom_hash, Chip8Emu};
+use crate::isa::InstructionSet;
+use crate::keypad::KeypadMap;
+use crate::layout::{AppLayout, Region};
+use crate::movie::{Movie, MoviePlayer};
+use crate::palette::Palette;
+use crate::persistence::Persistence;
+use crate::profile::RomProfile;
+use crate::profiler::{FLAT_PROFILE_EXTENSION, FOLDED_PROFILE_EXTENSION};
+use crate::rom::{self, LoadedProgram};
+use crate::romdb::{RomDatabase, RomMetadata};
+use crate::sprites::{SPRITES_PNG_EXTENSION, SPRITES_SOURCE_EXTENSION};
+use crate::symbols::SymbolMap;
+
+/// Time to wait after a change of the watched ROM before reloading it, so that a file written in
+/// several steps is read once complete
+const RELOAD_DELAY: Duration = Duration::from_millis(200);
+
+/// Frames per second, the rate of the delay and sound timers. The event loop ticks once a frame
+const TIMER_RATE: f64 = 60.0;
+
+/// Emulator cycles per 60Hz frame for `tick_rate` cycles per second
+fn cycles_per_frame(tick_rate: f64) -> u32 {
+  (tick_rate / TIMER_RATE).round().max(1.0) as u32
+}
+
+/// Keyboard keys of the controls named by the ROM database
+fn control_key_code(name: &str) -> Option<KeyCode> {
+  match name {
+    "up" => Some(KeyCode::Up),
+    "down" => Some(KeyCode::Down),
+    "left" => Some(KeyCode::Left),
+    "right" => Some(KeyCode::Right),
+    _ => None,
   }
 }
 
@@ -57,45 +78,648 @@ pub struct App {
   pub should_quit: bool,
   pub should_suspend: bool,
   pub mode: Mode,
+  /// Mode to return to when the help is closed
+  mode_before_help: Mode,
   pub last_tick_key_events: Vec<KeyEvent>,
   pub emulator: Chip8Emu,
+  pub keypad: KeypadMap,
   pub running: bool,
-  last_timer_tick: Option<Instant>,
+  pub database: RomDatabase,
+  /// Profile of the loaded ROM
+  profile: Option<RomProfile>,
+  /// SHA-1 of the loaded ROM before the patches of its profile, which its profile is saved under
+  profile_hash: String,
+  palette: Palette,
+  persistence: Persistence,
   emu_ready: bool,
+  /// Set by continuing after an error, so that later errors don't pause the emulation
+  ignore_errors: bool,
+  show_error_log: bool,
+  show_call_graph: bool,
+  show_sprites: bool,
+  show_heat_map: bool,
+  show_coverage: bool,
+  /// Tick rate given on the command line, used by ROMs the database doesn't know
+  base_tick_rate: f64,
   script_filename: String,
+  /// Path of the loaded ROM, as it was given
+  rom_path: Option<String>,
+  /// Symbols of the loaded ROM, as they were read with it
+  symbols: Option<SymbolMap>,
+  watch_rom: bool,
+  watcher: Option<RecommendedWatcher>,
+  reload_requested: Option<Instant>,
+  cycles_per_frame: u32,
+  cycles_in_frame: u32,
+  pending_inputs: Vec<(u8, bool)>,
+  /// Cheats set during a recording, frozen from the next frame on so that the movie reproduces them
+  pending_cheats: Option<Vec<Cheat>>,
+  held_keys: [bool; 16],
+  keyboard_enhancement: bool,
+  key_release_deadlines: [Option<Instant>; 16],
+  record_path: Option<PathBuf>,
+  coverage_path: Option<PathBuf>,
+  recording: Option<Movie>,
+  replay_path: Option<PathBuf>,
+  replay: Option<MoviePlayer>,
 }
 
 impl App {
-  pub fn new(tick_rate: f64, frame_rate: f64) -> Result<Self> {
+  pub fn new(args: Cli) -> Result<Self> {
     let config = Config::new()?;
+    let mut emulator = Chip8Emu::new();
+    if let Some(seed) = args.seed.or(config.emulator.seed) {
+      emulator.set_seed(seed);
+    }
+    emulator.set_random_kind(args.random.unwrap_or(config.emulator.random));
     let screen = Screen::new();
     let status = StatusBar::new();
     let opcode_list = OpcodesList::new();
     let file_selector = FileSelector::new();
+    let keypad_widget = Keypad::new();
+    let registers = Registers::new();
+    let rom_info = RomInfo::new();
+    let error_log = ErrorLog::new();
+    let call_graph = CallGraph::new();
+    let sprite_viewer = SpriteViewer::new();
+    let cheat_search = CheatSearchPanel::new();
+    let error_popup = ErrorPopup::new();
+    let help = Help::new();
     let mode = Mode::Home;
+    let keypad = config.keypad.resolve(None, None);
+    let palette = config.display.palette;
+    let persistence = config.display.persistence;
+    let watch_rom = args.watch || config.emulator.watch_rom;
+    let database = RomDatabase::load(&config.config._config_dir).unwrap_or_else(|err| {
+      log::error!("Failed to read the user ROM database: {}", err);
+      RomDatabase::bundled()
+    });
     Ok(Self {
-      tick_rate,
-      frame_rate,
-      components: vec![Box::new(screen), Box::new(status), Box::new(opcode_list), Box::new(file_selector)],
+      tick_rate: args.tick_rate,
+      frame_rate: args.frame_rate,
+      components: vec![
+        Box::new(screen),
+        Box::new(status),
+        Box::new(opcode_list),
+        Box::new(file_selector),
+        Box::new(keypad_widget),
+        Box::new(registers),
+        Box::new(rom_info),
+        Box::new(error_log),
+        Box::new(call_graph),
+        Box::new(sprite_viewer),
+        Box::new(cheat_search),
+        // Last, so that they are drawn over the other components
+        Box::new(error_popup),
+        Box::new(help),
+      ],
       should_quit: false,
       should_suspend: false,
       config,
       mode,
+      mode_before_help: mode,
       last_tick_key_events: Vec::new(),
-      emulator: Chip8Emu::new(),
+      emulator,
+      keypad,
       running: false,
-      last_timer_tick: None,
+      database,
+      profile: None,
+      profile_hash: String::new(),
+      palette,
+      persistence,
       emu_ready: false,
+      ignore_errors: false,
+      show_error_log: false,
+      show_call_graph: false,
+      show_sprites: false,
+      show_heat_map: false,
+      show_coverage: false,
+      base_tick_rate: args.tick_rate,
       script_filename: "".to_string(),
+      rom_path: None,
+      symbols: None,
+      watch_rom,
+      watcher: None,
+      reload_requested: None,
+      cycles_per_frame: cycles_per_frame(args.tick_rate),
+      cycles_in_frame: 0,
+      pending_inputs: Vec::new(),
+      pending_cheats: None,
+      held_keys: [false; 16],
+      keyboard_enhancement: false,
+      key_release_deadlines: [None; 16],
+      record_path: args.record,
+      coverage_path: args.coverage,
+      recording: None,
+      replay_path: args.replay,
+      replay: None,
     })
   }
 
+  /// Runs the cycles left in the current frame, then finishes it unless an error paused the
+  /// emulation
+  fn run_frame(&mut self, action_tx: &mpsc::UnboundedSender<Action>) -> Result<()> {
+    while self.running && self.cycles_in_frame < self.cycles_per_frame {
+      if let Err(emu_err) = self.emulator.emulate_cycle() {
+        let fault = self.emulator.fault(emu_err);
+        log::error!("{} at 0x{:0>3X}", fault.message, fault.state.program_counter);
+        if self.config.emulator.pause_on_error && !self.ignore_errors {
+          self.running = false;
+          self.mode = Mode::Error;
+          action_tx.send(Action::StopEmulation)?;
+          action_tx.send(Action::UpdateCpuState(fault.state.clone()))?;
+          action_tx.send(Action::Fault(fault))?;
+        } else {
+          self.emulator.skip_instruction();
+          action_tx.send(Action::Error(fault.message))?;
+        }
+      }
+      self.cycles_in_frame += 1;
+    }
+    action_tx.send(Action::UpdateOpcode(self.emulator.get_opcode()))?;
+    action_tx.send(Action::SelectOpcode(self.emulator.get_program_counter() - 512))?;
+
+    if self.cycles_in_frame >= self.cycles_per_frame {
+      self.end_frame(action_tx)?;
+    }
+    Ok(())
+  }
+
+  /// Finishes the current frame and applies the keypad input for the next one.
+  /// Live input is only applied at frame boundaries so that a replay reproduces it exactly
+  fn end_frame(&mut self, action_tx: &mpsc::UnboundedSender<Action>) -> Result<()> {
+    self.cycles_in_frame = 0;
+    action_tx.send(Action::Redraw(self.emulator.screen()))?;
+    action_tx.send(Action::UpdateKeys(self.emulator.get_keys()))?;
+    action_tx.send(Action::UpdateCpuState(self.emulator.get_cpu_state()))?;
+    self.update_sprite_viewer(action_tx)?;
+    self.update_heat_map(action_tx)?;
+    self.update_coverage(action_tx)?;
+    if self.emulator.advance_frame() {
+      // BEEP!!!
+    }
+
+    let frame = self.emulator.get_frame();
+    if let Some(player) = self.replay.as_mut() {
+      if let Err(err) = player.apply(&mut self.emulator) {
+        let message = format!("Error while replaying input: {}", String::from(err));
+        log::error!("{}", message);
+        action_tx.send(Action::Warning(message))?;
+      }
+      if player.is_finished() {
+        // Hand the keypad back to the player
+        self.replay = None;
+        log::info!("Replay finished");
+        action_tx.send(Action::Warning("The replay finished, live input is back".to_string()))?;
+      }
+    } else {
+      if let Some(cheats) = self.pending_cheats.take() {
+        self.emulator.set_cheats(cheats.clone());
+        if let Some(movie) = self.recording.as_mut() {
+          movie.record_cheats(frame, cheats);
+        }
+      }
+      for (key, pressed) in self.pending_inputs.drain(..) {
+        if let Err(err) = self.emulator.set_key(key, pressed) {
+          let message = format!("Error while capturing key press: {}", String::from(err));
+          log::error!("{}", message);
+          action_tx.send(Action::Warning(message))?;
+        } else if let Some(movie) = self.recording.as_mut() {
+          movie.record(frame, key, pressed);
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Holds the keypad `key` from the next frame on. With `auto_release` the key is released after
+  /// the configured timeout unless pressed again
+  fn press_key(&mut self, key: u8, auto_release: bool) {
+    if auto_release {
+      let timeout = self.config.emulator.key_release_timeout(key);
+      self.key_release_deadlines[key as usize] = Some(Instant::now() + timeout);
+    }
+    if !self.held_keys[key as usize] {
+      self.held_keys[key as usize] = true;
+      self.pending_inputs.push((key, true));
+    }
+  }
+
+  fn release_key(&mut self, key: u8) {
+    self.key_release_deadlines[key as usize] = None;
+    if self.held_keys[key as usize] {
+      self.held_keys[key as usize] = false;
+      self.pending_inputs.push((key, false));
+    }
+  }
+
+  /// Restarts the loaded ROM, or `rom` in its place. Like a soft reset it keeps the RPL flags
+  fn restart_rom(&mut self, rom: Option<Vec<u8>>, action_tx: &mpsc::UnboundedSender<Action>) -> Result<()> {
+    if self.mode == Mode::Error {
+      self.mode = Mode::Home;
+    }
+    self.ignore_errors = false;
+    if let Some(cheats) = self.pending_cheats.take() {
+      self.emulator.set_cheats(cheats);
+    }
+    let is_reload = rom.is_some();
+    match rom {
+      Some(bytes) => {
+        if let Err(err) = self.emulator.load_rom(bytes) {
+          let message = String::from(err);
+          log::error!("{}", message);
+          action_tx.send(Action::Error(message))?;
+          return Ok(());
+        }
+      },
+      None => self.emulator.restart(),
+    }
+    self.reset_input();
+
+    if let (Some(path), Some(_)) = (self.rom_path.as_ref(), self.recording.as_ref()) {
+      self.recording = Some(Movie::new(&self.emulator, path, self.cycles_per_frame));
+    }
+    if let Some(player) = self.replay.take() {
+      if is_reload {
+        action_tx.send(Action::Warning("Stopped the replay, the ROM changed".to_string()))?;
+      } else {
+        self.replay = Some(MoviePlayer::new(player.movie().clone()));
+      }
+    }
+
+    action_tx.send(Action::Redraw(self.emulator.screen()))?;
+    action_tx.send(Action::UpdateCpuState(self.emulator.get_cpu_state()))?;
+    if is_reload {
+      action_tx.send(Action::LoadOpcodesList(self.emulator.get_opcodes()))?;
+    }
+    action_tx.send(Action::SelectOpcode(0))?;
+    Ok(())
+  }
+
+  /// Starts the program read from `rom_path`, applying the settings of the ROM
+  fn load_program(
+    &mut self,
+    rom_path: &String,
+    program: LoadedProgram,
+    action_tx: &mpsc::UnboundedSender<Action>,
+  ) -> Result<()> {
+    let LoadedProgram { rom, symbols, hash, profile, warnings } = program;
+    for message in warnings {
+      log::warn!("{}", message);
+      action_tx.send(Action::Warning(message))?;
+    }
+    if let Err(err) = self.emulator.load_rom(rom.clone()) {
+      let message = String::from(err);
+      log::error!("{}", message);
+      action_tx.send(Action::Error(message))?;
+      return Ok(());
+    }
+    self.emu_ready = true;
+    // Per-ROM settings are keyed by filename
+    let filename = Path::new(rom_path).file_name().map_or_else(
+      || rom_path.clone(),
+      |name| name.to_string_lossy().into_owned(),
+    );
+    self.script_filename = filename.clone();
+
+    // The database and the profile describe the ROM before its patches
+    let metadata = self.database.lookup(&hash);
+    self.profile = profile;
+    self.profile_hash = hash;
+    log::info!("ROM loaded from file {}", rom_path);
+    self.apply_rom_settings(&filename, metadata.as_ref(), action_tx)?;
+    self.load_cheats(action_tx)?;
+    action_tx.send(Action::SetRomInfo(metadata))?;
+    self.replay = None;
+    self.ignore_errors = false;
+    self.reset_input();
+    if self.record_path.is_some() {
+      self.recording = Some(Movie::new(&self.emulator, rom_path, self.cycles_per_frame));
+    }
+    self.rom_path = Some(rom_path.clone());
+    self.watcher = None;
+    if self.watch_rom {
+      if let Err(err) = self.watch_rom(rom_path, action_tx) {
+        let message = format!("Failed to watch {}: {}", rom_path, err);
+        log::error!("{}", message);
+        action_tx.send(Action::Warning(message))?;
+      }
+    }
+    self.symbols = symbols;
+    action_tx.send(Action::SetProgram(rom, self.instruction_set()))?;
+    action_tx.send(Action::SetSymbols(self.symbols.clone()))?;
+    action_tx.send(Action::LoadOpcodesList(self.emulator.get_opcodes()))?;
+    action_tx.send(Action::SelectOpcode(0))?;
+    Ok(())
+  }
+
+  /// Instruction set the loaded program is analyzed with
+  fn instruction_set(&self) -> InstructionSet {
+    if self.emulator.get_quirks().superchip_opcodes { InstructionSet::Schip } else { InstructionSet::Chip8 }
+  }
+
+  /// Reads the loaded ROM again from disk and restarts it, keeping the settings it was loaded with
+  fn reload_rom(&mut self, action_tx: &mpsc::UnboundedSender<Action>) -> Result<()> {
+    let Some(path) = self.rom_path.clone() else { return Ok(()) };
+    match rom::load_program(Path::new(&path), &self.config.config._config_dir, &self.database) {
+      Ok(program) => {
+        log::info!("Reloaded the ROM {}", path);
+        for message in program.warnings {
+          log::warn!("{}", message);
+          action_tx.send(Action::Warning(message))?;
+        }
+        self.restart_rom(Some(program.rom.clone()), action_tx)?;
+        self.load_cheats(action_tx)?;
+        self.symbols = program.symbols;
+        action_tx.send(Action::SetProgram(program.rom, self.instruction_set()))?;
+        action_tx.send(Action::SetSymbols(self.symbols.clone()))?;
+      },
+      Err(err) => {
+        let message = format!("Failed to reload {}: {}", path, err);
+        log::error!("{}", message);
+        action_tx.send(Action::Warning(message))?;
+      },
+    }
+    Ok(())
+  }
+
+  /// Reloads the ROM at `path` whenever it changes on disk, e.g. when an assembler rebuilds it
+  fn watch_rom(&mut self, path: &str, action_tx: &mpsc::UnboundedSender<Action>) -> Result<()> {
+    let rom = Path::new(path).canonicalize()?;
+    let watched = rom.clone();
+    let tx = action_tx.clone();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+      if let Ok(event) = event {
+        if (event.kind.is_create() || event.kind.is_modify()) && event.paths.contains(&watched) {
+          // The app may be quitting
+          let _ = tx.send(Action::RomFileChanged);
+        }
+      }
+    })?;
+    // Tools often replace the file rather than writing into it, which a watch on the file misses
+    watcher.watch(rom.parent().unwrap_or(&rom), RecursiveMode::NonRecursive)?;
+    self.watcher = Some(watcher);
+    Ok(())
+  }
+
+  /// Forgets the keypad input, e.g. when the emulator restarts
+  fn reset_input(&mut self) {
+    self.cycles_in_frame = 0;
+    self.pending_inputs.clear();
+    self.pending_cheats = None;
+    self.held_keys = [false; 16];
+    self.key_release_deadlines = [None; 16];
+  }
+
+  fn release_expired_keys(&mut self) {
+    let now = Instant::now();
+    for key in 0..16 {
+      if self.key_release_deadlines[key as usize].is_some_and(|deadline| deadline <= now) {
+        self.release_key(key);
+      }
+    }
+  }
+
+  /// Applies the settings of the loaded ROM `filename`. Its profile takes precedence over the ROM
+  /// database, which takes precedence over the config
+  fn apply_rom_settings(
+    &mut self,
+    filename: &str,
+    metadata: Option<&RomMetadata>,
+    action_tx: &mpsc::UnboundedSender<Action>,
+  ) -> Result<()> {
+    let profile = self.profile.clone().unwrap_or_default();
+    let quirks = profile.quirks.or(metadata.and_then(|metadata| metadata.quirks));
+    self.emulator.set_quirks(quirks.unwrap_or_default());
+
+    let tickrate = profile.cycles_per_frame.or(metadata.and_then(|metadata| metadata.tickrate));
+    let tick_rate = match tickrate {
+      Some(tickrate) => tickrate as f64 * 60.0,
+      None => self.base_tick_rate,
+    };
+    self.tick_rate = tick_rate;
+    self.cycles_per_frame = cycles_per_frame(tick_rate);
+
+    self.palette = profile.palette
+      .or(metadata.and_then(|metadata| metadata.palette))
+      .unwrap_or(self.config.display.palette);
+    action_tx.send(Action::SetPalette(self.palette))?;
+    self.persistence = profile.persistence.unwrap_or(self.config.display.persistence_for(filename));
+    action_tx.send(Action::SetPersistence(self.persistence))?;
+
+    self.keypad = self.config.keypad.resolve(Some(filename), profile.keypad.as_ref());
+    for (name, key) in metadata.map(|metadata| metadata.keys.as_slice()).unwrap_or_default() {
+      match control_key_code(name) {
+        // Configured bindings win over the controls of the database
+        Some(code) if self.keypad.get(code).is_some() => {},
+        Some(code) if *key < 16 => self.keypad.add(*key, code),
+        _ => {
+          let message = format!("Ignoring the control `{}` of the ROM database", name);
+          log::warn!("{}", message);
+          action_tx.send(Action::Warning(message))?;
+        },
+      }
+    }
+    action_tx.send(Action::SetKeypadLabels(self.keypad_labels()))?;
+    Ok(())
+  }
+
+  /// Keyboard keys bound to each keypad key, e.g. `w/up`
+  fn keypad_labels(&self) -> Vec<String> {
+    (0..16)
+      .map(|key| {
+        let mut codes: Vec<String> =
+          self.keypad.codes_for(key).into_iter().map(|code| key_event_to_string(&KeyEvent::from(code))).collect();
+        codes.sort();
+        codes.join("/")
+      })
+      .collect()
+  }
+
+  /// Writes the active settings of the loaded ROM to its profile
+  fn save_rom_profile(&mut self) -> Result<()> {
+    let keypad = self.profile.as_ref().and_then(|profile| profile.keypad.as_ref());
+    let profile = RomProfile {
+      quirks: Some(self.emulator.get_quirks()),
+      cycles_per_frame: Some(self.cycles_per_frame),
+      palette: Some(self.palette),
+      persistence: Some(self.persistence),
+      keypad: Some(self.config.keypad.effective(Some(&self.script_filename), keypad)),
+      patches: self.profile.as_ref().and_then(|profile| profile.patches.clone()),
+    };
+    let path = profile.save(&self.config.config._config_dir, &self.profile_hash)?;
+    log::info!("Saved the ROM profile {}", path.display());
+    self.profile = Some(profile);
+    Ok(())
+  }
+
+  fn draw(&mut self, tui: &mut tui::Tui, action_tx: &mpsc::UnboundedSender<Action>) -> Result<()> {
+    let (width, height) = self.emulator.get_screen_size();
+    let renderer = self.config.display.renderer;
+    let mode = self.mode;
+    let show_error_log = self.show_error_log;
+    let show_call_graph = self.show_call_graph;
+    let show_sprites = self.show_sprites;
+    tui.draw(|f| {
+      let mut layout = AppLayout::compute(f.size(), renderer, width, height);
+      if mode == Mode::SelectingFile {
+        layout.show_instead_of(Region::Files, Region::Screen);
+      }
+      if show_error_log {
+        layout.show_instead_of(Region::Log, Region::Info);
+      }
+      if show_call_graph {
+        layout.show_instead_of(Region::CallGraph, Region::Screen);
+      }
+      if show_sprites {
+        layout.show_instead_of(Region::Sprites, Region::Screen);
+      }
+      if mode == Mode::CheatSearch {
+        layout.show_instead_of(Region::Cheats, Region::Files);
+      }
+      for component in self.components.iter_mut() {
+        let area = match component.region() {
+          Some(region) => layout.get(region).unwrap_or_default(),
+          None => f.size(),
+        };
+        let r = component.draw(f, area);
+        if let Err(e) = r {
+          action_tx.send(Action::Error(format!("Failed to draw: {:?}", e))).unwrap();
+        }
+      }
+    })?;
+    Ok(())
+  }
+
+  fn start_replay(&mut self, path: PathBuf, action_tx: &mpsc::UnboundedSender<Action>) -> Result<()> {
+    let movie = Movie::load(&path)?;
+    let program = movie.prepare(&mut self.emulator, &self.config.config._config_dir, &self.database)?;
+    log::info!("Replaying {} from {}", movie.rom_path, path.display());
+    self.cycles_per_frame = movie.cycles_per_frame;
+    self.script_filename = movie.rom_path.clone();
+    self.rom_path = Some(movie.rom_path.clone());
+    self.emu_ready = true;
+    self.replay = Some(MoviePlayer::new(movie));
+    // The movie decides the cheats
+    self.pending_cheats = None;
+    action_tx.send(Action::LoadCheats(self.emulator.get_cheats().to_vec()))?;
+    // The movie decides the quirks and speed, so the database is only used for display
+    action_tx.send(Action::SetRomInfo(self.database.lookup(&self.emulator.get_rom_hash())))?;
+    self.symbols = program.symbols;
+    action_tx.send(Action::SetProgram(self.emulator.get_rom().to_vec(), self.instruction_set()))?;
+    action_tx.send(Action::SetSymbols(self.symbols.clone()))?;
+    action_tx.send(Action::LoadOpcodesList(self.emulator.get_opcodes()))?;
+    action_tx.send(Action::SelectOpcode(0))?;
+    action_tx.send(Action::StartEmulation)?;
+    Ok(())
+  }
+
+  /// Sends the memory and the recent sprites to the sprite viewer and the cheat search, if
+  /// they are shown
+  fn update_sprite_viewer(&self, action_tx: &mpsc::UnboundedSender<Action>) -> Result<()> {
+    if self.show_sprites || self.mode == Mode::CheatSearch {
+      action_tx.send(Action::UpdateMemory(self.emulator.get_memory()))?;
+    }
+    if self.show_sprites {
+      action_tx.send(Action::UpdateSprites(self.emulator.get_sprites().recent()))?;
+    }
+    Ok(())
+  }
+
+  /// Freezes the cheats saved for the loaded ROM
+  fn load_cheats(&mut self, action_tx: &mpsc::UnboundedSender<Action>) -> Result<()> {
+    let cheats = match CheatList::load(&self.config.config._config_dir, &self.emulator.get_rom_hash()) {
+      Ok(list) => list.cheats,
+      Err(err) => {
+        let message = format!("Failed to read the cheats: {}", err);
+        log::error!("{}", message);
+        action_tx.send(Action::Warning(message))?;
+        vec![]
+      },
+    };
+    self.emulator.set_cheats(cheats.clone());
+    action_tx.send(Action::LoadCheats(cheats))?;
+    Ok(())
+  }
+
+  /// Sends the executions of every address to the disassembly, if it shows the heat map
+  fn update_heat_map(&self, action_tx: &mpsc::UnboundedSender<Action>) -> Result<()> {
+    if self.show_heat_map {
+      action_tx.send(Action::UpdateHeatMap(self.emulator.get_profiler().executions().to_vec()))?;
+    }
+    Ok(())
+  }
+
+  /// Writes the profile of the run next to the ROM, as a flat profile and as folded call stacks
+  /// for flamegraph tools. Subroutines are named after the symbols loaded with the ROM, if it has any
+  fn export_profile(&self) -> Result<()> {
+    let Some(rom_path) = self.rom_path.as_ref() else { return Ok(()) };
+    let labels = self.symbols.as_ref().map(SymbolMap::labels_by_address).unwrap_or_default();
+    let name = |address: u16| match labels.get(&address) {
+      Some(label) => label.clone(),
+      None if address == PROGRAM_START => "start".to_string(),
+      None => format!("sub_{:03X}", address),
+    };
+    let profiler = self.emulator.get_profiler();
+    let flat_path = Path::new(rom_path).with_extension(FLAT_PROFILE_EXTENSION);
+    let folded_path = Path::new(rom_path).with_extension(FOLDED_PROFILE_EXTENSION);
+    fs::write(&flat_path, profiler.to_flat(name))?;
+    fs::write(&folded_path, profiler.to_folded(name))?;
+    log::info!("Exported the profile to {} and {}", flat_path.display(), folded_path.display());
+    Ok(())
+  }
+
+  /// Writes the sprites drawn since the ROM was loaded next to it, as a PNG tile sheet and as
+  /// assembler source
+  fn export_sprites(&self) -> Result<()> {
+    let Some(rom_path) = self.rom_path.as_ref() else { return Ok(()) };
+    let sprites = self.emulator.get_sprites();
+    if sprites.is_empty() {
+      return Err(eyre!("no sprites were drawn yet"));
+    }
+    let png_path = Path::new(rom_path).with_extension(SPRITES_PNG_EXTENSION);
+    let source_path = Path::new(rom_path).with_extension(SPRITES_SOURCE_EXTENSION);
+    sprites.save_png(&png_path)?;
+    fs::write(&source_path, sprites.to_source())?;
+    log::info!("Exported the sprites to {} and {}", png_path.display(), source_path.display());
+    Ok(())
+  }
+
+  /// Sends the executed addresses to the disassembly, if it shows the coverage
+  fn update_coverage(&self, action_tx: &mpsc::UnboundedSender<Action>) -> Result<()> {
+    if self.show_coverage {
+      action_tx.send(Action::UpdateCoverage(self.emulator.get_coverage().executed().to_vec()))?;
+    }
+    Ok(())
+  }
+
+  /// Writes the lcov report of the run, mapped to the source lines of the symbols loaded with the
+  /// ROM
+  fn save_coverage(&self) -> Result<()> {
+    let (Some(path), Some(rom_path)) = (self.coverage_path.as_ref(), self.rom_path.as_ref()) else { return Ok(()) };
+    let Some(symbols) = self.symbols.as_ref() else {
+      return Err(eyre!("{} has no symbol map to map the coverage to source lines", rom_path));
+    };
+    let disassembly = Disassembly::analyze(self.emulator.get_rom(), self.instruction_set(), Some(symbols));
+    fs::write(path, self.emulator.get_coverage().to_lcov(&disassembly, symbols))?;
+    Ok(())
+  }
+
+  fn save_recording(&self) -> Result<()> {
+    if let (Some(path), Some(movie)) = (self.record_path.as_ref(), self.recording.as_ref()) {
+      movie.save(path)?;
+    }
+    Ok(())
+  }
+
   pub async fn run(&mut self) -> Result<()> {
     let (action_tx, mut action_rx) = mpsc::unbounded_channel();
 
-    let mut tui = tui::Tui::new()?.tick_rate(self.tick_rate).frame_rate(self.frame_rate);
-    // tui.mouse(true);
+    let mut tui = tui::Tui::new()?.tick_rate(TIMER_RATE).frame_rate(self.frame_rate).mouse(true);
     tui.enter()?;
+    // Shown once the terminal is restored
+    let mut exit_warning = None;
+    self.keyboard_enhancement = tui.keyboard_enhancement;
 
     for component in self.components.iter_mut() {
       component.register_action_handler(action_tx.clone())?;
@@ -109,7 +733,11 @@ impl App {
       component.init(tui.size()?)?;
     }
 
+    action_tx.send(Action::SetKeypadLabels(self.keypad_labels()))?;
 
+    if let Some(path) = self.replay_path.take() {
+      self.start_replay(path, &action_tx)?;
+    }
 
     loop {
       if let Some(e) = tui.next().await {
@@ -119,21 +747,11 @@ impl App {
           tui::Event::Render => action_tx.send(Action::Render)?,
           tui::Event::Resize(x, y) => action_tx.send(Action::Resize(x, y))?,
           tui::Event::Key(key) => {
-            if let KeyCode::Char(keycode) = key.code {
-              if KEYBOARD.contains(&key.code) {
-                log::info!("CAPTURED KEY PRESS");
-                let r = self.emulator.press(
-                  &get_key_from_char(&keycode)
-                );
-                if let Err(err) = r {
-                  log::error!("Error while capturing key press: {}", String::from(err))
-                }
-              }
-            }
-
+            let mut is_bound = false;
             if let Some(keymap) = self.config.keybindings.get(&self.mode) {
               if let Some(action) = keymap.get(&vec![key]) {
                 log::info!("Got action: {action:?}");
+                is_bound = true;
                 action_tx.send(action.clone())?;
               } else {
                 // If the key was not handled as a single key action,
@@ -143,10 +761,30 @@ impl App {
                 // Check for multi-key combinations
                 if let Some(action) = keymap.get(&self.last_tick_key_events) {
                   log::info!("Got action: {action:?}");
+                  is_bound = true;
                   action_tx.send(action.clone())?;
                 }
               }
             };
+
+            // Keybindings take precedence over the keypad, which is only played on the home screen
+            if !is_bound && self.mode == Mode::Home && !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) {
+              if let Some(keypad_key) = self.keypad.get(key.code) {
+                if self.replay.is_none() {
+                  log::info!("CAPTURED KEY PRESS");
+                  // Without the keyboard protocol the release is never reported, so it is
+                  // simulated. Key repeat keeps extending it
+                  self.press_key(keypad_key, !self.keyboard_enhancement);
+                }
+              }
+            }
+          },
+          tui::Event::KeyRelease(key) => {
+            if let Some(keypad_key) = self.keypad.get(key.code) {
+              if self.replay.is_none() {
+                self.release_key(keypad_key);
+              }
+            }
           },
           _ => {},
         }
@@ -161,30 +799,17 @@ impl App {
         if action != Action::Tick && action != Action::Render {
           log::debug!("{action:?}");
         }
+        let mode = self.mode;
         match action {
           Action::Tick => {
             self.last_tick_key_events.drain(..);
+            self.release_expired_keys();
+            if self.reload_requested.is_some_and(|requested| requested.elapsed() >= RELOAD_DELAY) {
+              self.reload_requested = None;
+              self.reload_rom(&action_tx)?;
+            }
             if self.running {
-              action_tx.send(Action::UpdateOpcode(self.emulator.get_opcode())).expect("Can send an action");
-              if let Err(emu_err) = self.emulator.emulate_cycle() {
-                action_tx.send(Action::Error(emu_err.clone().into())).expect("Can send an action");
-                log::error!("{}", String::from(emu_err));
-              }
-              action_tx.send(Action::SelectOpcode(self.emulator.get_program_counter() - 512))
-                  .expect("Can send an action");
-              
-              if let Some(last_tick) = self.last_timer_tick {
-                if Instant::now().duration_since(last_tick).as_millis() > 16 {
-                  self.last_timer_tick = Some(Instant::now());
-                  
-                  action_tx.send(Action::Redraw(self.emulator.screen()))
-                      .expect("Can send an action");
-                  self.emulator.update_delay_timer();
-                  if self.emulator.update_sound_timer() {
-                    // BEEP!!!
-                  }
-                }
-              }
+              self.run_frame(&action_tx)?;
             }
           },
           Action::Quit => self.should_quit = true,
@@ -192,39 +817,120 @@ impl App {
           Action::Resume => self.should_suspend = false,
           Action::Resize(w, h) => {
             tui.resize(Rect::new(0, 0, w, h))?;
-            tui.draw(|f| {
-              for component in self.components.iter_mut() {
-                let r = component.draw(f, f.size());
-                if let Err(e) = r {
-                  action_tx.send(Action::Error(format!("Failed to draw: {:?}", e))).unwrap();
-                }
-              }
-            })?;
+            self.draw(&mut tui, &action_tx)?;
           },
           Action::Render => {
-            tui.draw(|f| {
-              for component in self.components.iter_mut() {
-                let r = component.draw(f, f.size());
-                if let Err(e) = r {
-                  action_tx.send(Action::Error(format!("Failed to draw: {:?}", e))).unwrap();
+            self.draw(&mut tui, &action_tx)?;
+          },
+          Action::PressKey(key) if self.replay.is_none() => self.press_key(key, false),
+          Action::ReleaseKey(key) if self.replay.is_none() => self.release_key(key),
+          Action::CyclePalette => {
+            self.palette = self.palette.next();
+            action_tx.send(Action::SetPalette(self.palette))?;
+          },
+          Action::SaveRomProfile if self.emu_ready => {
+            if let Err(err) = self.save_rom_profile() {
+              let message = format!("Failed to save the ROM profile: {}", err);
+              log::error!("{}", message);
+              action_tx.send(Action::Error(message))?;
+            }
+          },
+          Action::ContinueAfterError => {
+            self.mode = Mode::Home;
+            self.ignore_errors = true;
+            self.emulator.skip_instruction();
+            action_tx.send(Action::StartEmulation)?;
+          },
+          Action::SkipInstruction => {
+            self.mode = Mode::Home;
+            self.emulator.skip_instruction();
+            action_tx.send(Action::StartEmulation)?;
+          },
+          Action::DismissError => { self.mode = Mode::Home },
+          Action::Reset if self.emu_ready => self.restart_rom(None, &action_tx)?,
+          Action::ReloadRom if self.emu_ready => self.reload_rom(&action_tx)?,
+          Action::RomFileChanged => { self.reload_requested = Some(Instant::now()) },
+          Action::Help => {
+            if self.mode == Mode::Help {
+              self.mode = self.mode_before_help;
+            } else {
+              self.mode_before_help = self.mode;
+              self.mode = Mode::Help;
+            }
+          },
+          Action::ToggleErrorLog => { self.show_error_log = !self.show_error_log },
+          Action::ToggleCallGraph => { self.show_call_graph = !self.show_call_graph },
+          Action::ToggleSpriteViewer => {
+            self.show_sprites = !self.show_sprites;
+            self.update_sprite_viewer(&action_tx)?;
+          },
+          Action::ToggleCoverage => {
+            self.show_coverage = !self.show_coverage;
+            self.update_coverage(&action_tx)?;
+          },
+          Action::ToggleHeatMap => {
+            self.show_heat_map = !self.show_heat_map;
+            self.update_heat_map(&action_tx)?;
+          },
+          Action::ExportProfile if self.emu_ready => {
+            if let Err(err) = self.export_profile() {
+              let message = format!("Failed to export the profile: {}", err);
+              log::error!("{}", message);
+              action_tx.send(Action::Error(message))?;
+            }
+          },
+          Action::ExportSprites if self.emu_ready => {
+            if let Err(err) = self.export_sprites() {
+              let message = format!("Failed to export the sprites: {}", err);
+              log::error!("{}", message);
+              action_tx.send(Action::Error(message))?;
+            }
+          },
+          Action::StartEmulation => { self.running = true },
+          Action::StopEmulation => { self.running = false },
+          Action::FocusFileSelector => { self.mode = Mode::SelectingFile },
+          Action::FocusCheatSearch => {
+            self.mode = Mode::CheatSearch;
+            action_tx.send(Action::UpdateMemory(self.emulator.get_memory()))?;
+          },
+          Action::CloseCheatSearch => { self.mode = Mode::Home },
+          Action::SetCheats(ref cheats) => {
+            if self.replay.is_some() {
+              action_tx.send(Action::Warning("The cheats of a replay are those of the movie".to_string()))?;
+              action_tx.send(Action::LoadCheats(self.emulator.get_cheats().to_vec()))?;
+            } else {
+              if self.recording.is_some() {
+                self.pending_cheats = Some(cheats.clone());
+              } else {
+                self.emulator.set_cheats(cheats.clone());
+              }
+              if self.emu_ready {
+                let list = CheatList { cheats: cheats.clone() };
+                if let Err(err) = list.save(&self.config.config._config_dir, &self.emulator.get_rom_hash()) {
+                  let message = format!("Failed to save the cheats: {}", err);
+                  log::error!("{}", message);
+                  action_tx.send(Action::Error(message))?;
                 }
               }
-            })?;
+            }
           },
-          Action::StartEmulation => { self.running = true; self.last_timer_tick = Some(Instant::now()) },
-          Action::StopEmulation => { self.running = false; self.last_timer_tick = None },
-          Action::FocusFileSelector => { self.mode = Mode::SelectingFile },
-          Action::LoadFile(ref filename) => {
+          Action::CloseFileSelector => { self.mode = Mode::Home },
+          Action::LoadFile(ref rom_path) => {
             self.mode = Mode::Home;
-            self.emu_ready = true;
-            self.script_filename = filename.clone();
-
-            self.emulator.load_rom_from_file(format!("./scripts/{}", self.script_filename).as_str()).expect("Can read file");
-            action_tx.send(Action::LoadOpcodesList(self.emulator.get_opcodes()));
-            action_tx.send(Action::SelectOpcode(0));
+            match rom::load_program(Path::new(rom_path), &self.config.config._config_dir, &self.database) {
+              Ok(program) => self.load_program(rom_path, program, &action_tx)?,
+              Err(err) => {
+                let message = format!("Failed to load {}: {}", rom_path, err);
+                log::error!("{}", message);
+                action_tx.send(Action::Error(message))?;
+              },
+            }
           }
           _ => {},
         }
+        if self.mode != mode {
+          action_tx.send(Action::ModeChanged(self.mode))?;
+        }
         for component in self.components.iter_mut() {
           if let Some(action) = component.update(action.clone())? {
             action_tx.send(action)?
@@ -234,15 +940,25 @@ impl App {
       if self.should_suspend {
         tui.suspend()?;
         action_tx.send(Action::Resume)?;
-        tui = tui::Tui::new()?.tick_rate(self.tick_rate).frame_rate(self.frame_rate);
-        // tui.mouse(true);
+        tui = tui::Tui::new()?.tick_rate(TIMER_RATE).frame_rate(self.frame_rate).mouse(true);
         tui.enter()?;
+        self.keyboard_enhancement = tui.keyboard_enhancement;
       } else if self.should_quit {
         tui.stop()?;
+        self.save_recording()?;
+        // Quitting goes on without the report
+        if let Err(err) = self.save_coverage() {
+          let message = format!("Failed to write the coverage report: {}", err);
+          log::warn!("{}", message);
+          exit_warning = Some(message);
+        }
         break;
       }
     }
     tui.exit()?;
+    if let Some(message) = exit_warning {
+      eprintln!("{}", message);
+    }
     Ok(())
   }
 }
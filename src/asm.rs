@@ -0,0 +1,984 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::eyre;
+
+use crate::cli::AsmArgs;
+use crate::isa::{Instruction, InstructionSet};
+use crate::symbols::{SourceLine, SymbolMap};
+
+/// Address ROMs are loaded at
+pub const PROGRAM_START: u16 = 0x200;
+const MAX_INCLUDE_DEPTH: usize = 16;
+/// Names that can't be used for labels and constants
+const RESERVED: [&str; 9] = ["I", "DT", "ST", "K", "F", "HF", "B", "R", "LONG"];
+
+/// An error at a position of a source file. Line and column count from 1, a line of 0 is an error
+/// about the whole file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file.display(), self.message)
+        } else {
+            write!(f, "{}:{}:{}: {}", self.file.display(), self.line, self.column, self.message)
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// An assembled ROM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assembly {
+    pub rom: Vec<u8>,
+    pub symbols: SymbolMap,
+}
+
+/// An error at a column of the line being parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LineError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl LineError {
+    pub fn new(column: usize, message: impl Into<String>) -> Self {
+        Self { column, message: message.into() }
+    }
+}
+
+type LineResult<T> = Result<T, LineError>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Tok {
+    Ident(String),
+    Number(i64),
+    Str(String),
+    Punct(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Token {
+    pub tok: Tok,
+    pub column: usize,
+}
+
+/// Longest first, so that `<<` isn't read as two `<`
+const PUNCTUATION: [&str; 19] = [
+    "<<", ">>", ",", ":", "(", ")", "[", "]", "+", "-", "*", "/", "%", "&", "|", "^", "~", "=", "$",
+];
+
+/// Parses `0x1F`, `$1F`, `0b1010` and decimal numbers, with optional `_` separators
+pub(crate) fn parse_number(text: &str) -> Option<i64> {
+    let text = text.replace('_', "");
+    let (digits, radix) = if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        (hex, 16)
+    } else if let Some(hex) = text.strip_prefix('$') {
+        (hex, 16)
+    } else if let Some(binary) = text.strip_prefix("0b").or_else(|| text.strip_prefix("0B")) {
+        (binary, 2)
+    } else {
+        (text.as_str(), 10)
+    };
+    if digits.is_empty() {
+        return None
+    }
+    i64::from_str_radix(digits, radix).ok()
+}
+
+/// Splits a line into tokens, stopping at a `;` comment
+pub(crate) fn tokenize(line: &str) -> LineResult<Vec<Token>> {
+    let chars: Vec<char> = line.chars().collect();
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        let next = chars.get(i + 1).copied();
+        if c == ';' {
+            break
+        }
+        if c.is_whitespace() {
+            i += 1;
+            continue
+        }
+
+        let tok = if c.is_ascii_alphabetic() || c == '_' || c == '.' {
+            let start = i;
+            while i < chars.len() && is_word(chars[i]) {
+                i += 1;
+            }
+            Tok::Ident(chars[start..i].iter().collect())
+        } else if c.is_ascii_digit()
+            || (c == '$' && next.is_some_and(|c| c.is_ascii_hexdigit())) {
+            let start = i;
+            i += 1;
+            while i < chars.len() && is_word(chars[i]) {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            Tok::Number(parse_number(&text).ok_or_else(|| LineError::new(column, format!("invalid number `{}`", text)))?)
+        } else if c == '"' {
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(LineError::new(column, "unterminated string")),
+                    Some('"') => break,
+                    Some('\\') => {
+                        text.push(match chars.get(i + 1) {
+                            Some('n') => '\n',
+                            Some('0') => '\0',
+                            Some(&c @ ('\\' | '"')) => c,
+                            _ => return Err(LineError::new(i + 1, "invalid escape")),
+                        });
+                        i += 2;
+                    }
+                    Some(&c) => {
+                        text.push(c);
+                        i += 1;
+                    }
+                }
+            }
+            i += 1;
+            Tok::Str(text)
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let punct = PUNCTUATION.iter()
+                .find(|punct| rest.starts_with(**punct))
+                .ok_or_else(|| LineError::new(column, format!("unexpected character `{}`", c)))?;
+            i += punct.len();
+            Tok::Punct(punct)
+        };
+        tokens.push(Token { tok, column });
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Expr {
+    Number(i64),
+    Symbol { name: String, column: usize },
+    /// `$`, the address of the current line
+    Here,
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary { op: &'static str, column: usize, lhs: Box<Expr>, rhs: Box<Expr> },
+}
+
+fn binding_power(op: &str) -> Option<u8> {
+    match op {
+        "|" => Some(1),
+        "^" => Some(2),
+        "&" => Some(3),
+        "<<" | ">>" => Some(4),
+        "+" | "-" => Some(5),
+        "*" | "/" | "%" => Some(6),
+        _ => None,
+    }
+}
+
+/// Evaluates `expr`, looking symbols up with `symbol`
+pub(crate) fn evaluate<E>(
+    expr: &Expr,
+    here: i64,
+    symbol: &mut impl FnMut(&str, usize) -> Result<i64, E>,
+    error: &impl Fn(usize, String) -> E,
+) -> Result<i64, E> {
+    Ok(match expr {
+        Expr::Number(value) => *value,
+        Expr::Symbol { name, column } => symbol(name, *column)?,
+        Expr::Here => here,
+        Expr::Neg(expr) => evaluate(expr, here, symbol, error)?.wrapping_neg(),
+        Expr::Not(expr) => !evaluate(expr, here, symbol, error)?,
+        Expr::Binary { op, column, lhs, rhs } => {
+            let lhs = evaluate(lhs, here, symbol, error)?;
+            let rhs = evaluate(rhs, here, symbol, error)?;
+            match *op {
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                "&" => lhs & rhs,
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                "*" => lhs.wrapping_mul(rhs),
+                "<<" | ">>" => {
+                    let shift = u32::try_from(rhs).ok().filter(|shift| *shift < 64)
+                        .ok_or_else(|| error(*column, format!("invalid shift by {}", rhs)))?;
+                    if *op == "<<" { lhs << shift } else { lhs >> shift }
+                }
+                _ if rhs == 0 => return Err(error(*column, "division by zero".to_string())),
+                "/" => lhs.wrapping_div(rhs),
+                _ => lhs.wrapping_rem(rhs),
+            }
+        }
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Register(u8),
+    Range(u8, u8),
+    I,
+    /// `[I]`
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long(Expr),
+    Value(Expr),
+}
+
+fn register(name: &str) -> Option<u8> {
+    let digit = name.strip_prefix(['V', 'v'])?;
+    if digit.len() != 1 {
+        return None
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+fn is_reserved(name: &str) -> bool {
+    register(name).is_some() || RESERVED.iter().any(|reserved| reserved.eq_ignore_ascii_case(name))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum DataItem {
+    Value(Expr),
+    Bytes(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Statement {
+    Constant { name: String, value: Expr },
+    Include(String),
+    Data { width: usize, items: Vec<(usize, DataItem)> },
+    Instruction { mnemonic: String, operands: Vec<(usize, Operand)> },
+}
+
+impl Statement {
+    fn size(&self) -> usize {
+        match self {
+            Statement::Data { width, items } => items.iter().map(|(_, item)| match item {
+                DataItem::Value(_) => *width,
+                DataItem::Bytes(bytes) => bytes.len(),
+            }).sum(),
+            Statement::Instruction { operands, .. } if operands.iter().any(|(_, operand)| matches!(operand, Operand::Long(_))) => 4,
+            Statement::Instruction { .. } => 2,
+            _ => 0,
+        }
+    }
+}
+
+/// Labels and the statement of a line
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Line {
+    labels: Vec<(usize, String)>,
+    statement: Option<(usize, Statement)>,
+}
+
+pub(crate) struct LineParser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    end_column: usize,
+}
+
+impl<'a> LineParser<'a> {
+    pub fn new(tokens: &'a [Token], line: &str) -> Self {
+        Self { tokens, position: 0, end_column: line.chars().count() + 1 }
+    }
+
+    pub fn peek(&self) -> Option<&'a Tok> {
+        self.tokens.get(self.position).map(|token| &token.tok)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&'a Tok> {
+        self.tokens.get(self.position + offset).map(|token| &token.tok)
+    }
+
+    /// Column of the next token, or the end of the line
+    pub fn column(&self) -> usize {
+        self.tokens.get(self.position).map_or(self.end_column, |token| token.column)
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.position >= self.tokens.len()
+    }
+
+    pub fn advance(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn is_punct(&self, punct: &str) -> bool {
+        matches!(self.peek(), Some(Tok::Punct(p)) if *p == punct)
+    }
+
+    fn eat_punct(&mut self, punct: &str) -> bool {
+        let found = self.is_punct(punct);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect_punct(&mut self, punct: &str) -> LineResult<()> {
+        if self.eat_punct(punct) {
+            Ok(())
+        } else {
+            Err(LineError::new(self.column(), format!("expected `{}`", punct)))
+        }
+    }
+
+    pub fn expect_end(&self) -> LineResult<()> {
+        if self.is_at_end() {
+            Ok(())
+        } else {
+            Err(LineError::new(self.column(), "unexpected text at the end of the line"))
+        }
+    }
+
+    pub fn expression(&mut self) -> LineResult<Expr> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, min_power: u8) -> LineResult<Expr> {
+        let mut lhs = self.unary()?;
+        while let Some(Tok::Punct(op)) = self.peek() {
+            let Some(power) = binding_power(op).filter(|power| *power > min_power) else { break };
+            let column = self.column();
+            self.position += 1;
+            let rhs = self.binary(power)?;
+            lhs = Expr::Binary { op, column, lhs: Box::new(lhs), rhs: Box::new(rhs) };
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> LineResult<Expr> {
+        let column = self.column();
+        let Some(token) = self.advance() else {
+            return Err(LineError::new(column, "expected an expression"))
+        };
+        match &token.tok {
+            Tok::Number(value) => Ok(Expr::Number(*value)),
+            Tok::Ident(name) if is_reserved(name) => Err(LineError::new(column, format!("`{}` can't be used in an expression", name))),
+            Tok::Ident(name) => Ok(Expr::Symbol { name: name.clone(), column }),
+            Tok::Punct("$") => Ok(Expr::Here),
+            Tok::Punct("-") => Ok(Expr::Neg(Box::new(self.unary()?))),
+            Tok::Punct("+") => self.unary(),
+            Tok::Punct("~") => Ok(Expr::Not(Box::new(self.unary()?))),
+            Tok::Punct("(") => {
+                let expr = self.expression()?;
+                self.expect_punct(")")?;
+                Ok(expr)
+            }
+            _ => Err(LineError::new(column, "expected an expression")),
+        }
+    }
+
+    fn operand(&mut self) -> LineResult<Operand> {
+        if self.eat_punct("[") {
+            match self.advance().map(|token| &token.tok) {
+                Some(Tok::Ident(name)) if name.eq_ignore_ascii_case("I") => {}
+                _ => return Err(LineError::new(self.column(), "expected `[I]`")),
+            }
+            self.expect_punct("]")?;
+            return Ok(Operand::IndirectI)
+        }
+        let Some(Tok::Ident(name)) = self.peek() else {
+            return Ok(Operand::Value(self.expression()?))
+        };
+        if let Some(x) = register(name) {
+            self.position += 1;
+            if !self.eat_punct("-") {
+                return Ok(Operand::Register(x))
+            }
+            let column = self.column();
+            return match self.advance().map(|token| &token.tok) {
+                Some(Tok::Ident(name)) if register(name).is_some() => Ok(Operand::Range(x, register(name).unwrap())),
+                _ => Err(LineError::new(column, "expected a register")),
+            }
+        }
+        let operand = match name.to_uppercase().as_str() {
+            "I" => Operand::I,
+            "DT" => Operand::Dt,
+            "ST" => Operand::St,
+            "K" => Operand::K,
+            "F" => Operand::F,
+            "HF" => Operand::Hf,
+            "B" => Operand::B,
+            "R" => Operand::R,
+            "LONG" => {
+                self.position += 1;
+                return Ok(Operand::Long(self.expression()?))
+            }
+            _ => return Ok(Operand::Value(self.expression()?)),
+        };
+        self.position += 1;
+        Ok(operand)
+    }
+
+    fn line(&mut self) -> LineResult<Line> {
+        let mut line = Line::default();
+        while let (Some(Tok::Ident(name)), Some(Tok::Punct(":"))) = (self.peek(), self.peek_at(1)) {
+            line.labels.push((self.column(), name.clone()));
+            self.position += 2;
+        }
+        let column = self.column();
+        let Some(Tok::Ident(word)) = self.peek() else {
+            if self.is_at_end() {
+                return Ok(line)
+            }
+            return Err(LineError::new(column, "expected a label, instruction or directive"))
+        };
+        self.position += 1;
+
+        let is_equ = matches!(self.peek(), Some(Tok::Ident(equ)) if equ.eq_ignore_ascii_case("equ"));
+        let statement = if self.is_punct("=") || is_equ {
+            self.position += 1;
+            Statement::Constant { name: word.clone(), value: self.expression()? }
+        } else {
+            match word.to_lowercase().as_str() {
+                "include" | ".include" => {
+                    let column = self.column();
+                    match self.advance().map(|token| &token.tok) {
+                        Some(Tok::Str(path)) => Statement::Include(path.clone()),
+                        _ => return Err(LineError::new(column, "expected a file name in quotes")),
+                    }
+                }
+                directive @ ("db" | ".db" | "byte" | "dw" | ".dw" | "word") => {
+                    let width = if directive.contains('b') { 1 } else { 2 };
+                    let mut items = vec![];
+                    loop {
+                        let column = self.column();
+                        let item = match self.peek() {
+                            Some(Tok::Str(text)) if width == 1 => {
+                                self.position += 1;
+                                DataItem::Bytes(text.bytes().collect())
+                            }
+                            _ => DataItem::Value(self.expression()?),
+                        };
+                        items.push((column, item));
+                        if !self.eat_punct(",") {
+                            break
+                        }
+                    }
+                    Statement::Data { width, items }
+                }
+                _ => {
+                    let mut operands = vec![];
+                    if !self.is_at_end() {
+                        loop {
+                            operands.push((self.column(), self.operand()?));
+                            if !self.eat_punct(",") {
+                                break
+                            }
+                        }
+                    }
+                    Statement::Instruction { mnemonic: word.to_uppercase(), operands }
+                }
+            }
+        };
+        self.expect_end()?;
+        line.statement = Some((column, statement));
+        Ok(line)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Pos {
+    file: usize,
+    line: usize,
+}
+
+struct Placed {
+    pos: Pos,
+    column: usize,
+    address: i64,
+    statement: Statement,
+}
+
+struct Constant {
+    pos: Pos,
+    column: usize,
+    here: i64,
+    value: Expr,
+}
+
+struct Assembler<'a> {
+    target: InstructionSet,
+    read: &'a dyn Fn(&Path) -> io::Result<String>,
+    files: Vec<PathBuf>,
+    include_stack: Vec<PathBuf>,
+    statements: Vec<Placed>,
+    labels: BTreeMap<String, (i64, Pos, usize)>,
+    constants: BTreeMap<String, Constant>,
+    address: i64,
+    errors: Vec<AsmError>,
+}
+
+impl<'a> Assembler<'a> {
+    fn error(&self, pos: Pos, column: usize, message: impl Into<String>) -> AsmError {
+        AsmError { file: self.files[pos.file].clone(), line: pos.line, column, message: message.into() }
+    }
+
+    fn define(&mut self, name: &str, pos: Pos, column: usize) -> bool {
+        let previous = self.labels.get(name).map(|(_, pos, _)| *pos)
+            .or_else(|| self.constants.get(name).map(|constant| constant.pos));
+        let message = if is_reserved(name) {
+            format!("`{}` is a reserved name", name)
+        } else if let Some(previous) = previous {
+            format!("`{}` is already defined at {}:{}", name, self.files[previous.file].display(), previous.line)
+        } else {
+            return true
+        };
+        self.errors.push(self.error(pos, column, message));
+        false
+    }
+
+    /// Parses `path` and the files it includes, placing their statements
+    fn load(&mut self, path: &Path, included_at: Option<(Pos, usize)>) {
+        let read_error = |assembler: &Self, message: String| match included_at {
+            Some((pos, column)) => assembler.error(pos, column, message),
+            None => AsmError { file: path.to_path_buf(), line: 0, column: 0, message },
+        };
+        if self.include_stack.iter().any(|included| included == path) {
+            self.errors.push(read_error(self, format!("{} includes itself", path.display())));
+            return
+        }
+        if self.include_stack.len() >= MAX_INCLUDE_DEPTH {
+            self.errors.push(read_error(self, "includes are nested too deeply".to_string()));
+            return
+        }
+        let source = match (self.read)(path) {
+            Ok(source) => source,
+            Err(err) => {
+                self.errors.push(read_error(self, format!("can't read {}: {}", path.display(), err)));
+                return
+            }
+        };
+        self.include_stack.push(path.to_path_buf());
+        self.files.push(path.to_path_buf());
+        let file = self.files.len() - 1;
+
+        for (i, text) in source.lines().enumerate() {
+            let pos = Pos { file, line: i + 1 };
+            let line = match tokenize(text).and_then(|tokens| LineParser::new(&tokens, text).line()) {
+                Ok(line) => line,
+                Err(err) => {
+                    self.errors.push(self.error(pos, err.column, err.message));
+                    continue
+                }
+            };
+            for (column, name) in line.labels {
+                if self.define(&name, pos, column) {
+                    self.labels.insert(name, (self.address, pos, column));
+                }
+            }
+            let Some((column, statement)) = line.statement else { continue };
+            match statement {
+                Statement::Constant { name, value } => {
+                    if self.define(&name, pos, column) {
+                        self.constants.insert(name, Constant { pos, column, here: self.address, value });
+                    }
+                }
+                Statement::Include(include) => {
+                    let include = path.parent().unwrap_or(Path::new("")).join(include);
+                    self.load(&include, Some((pos, column)));
+                }
+                statement => {
+                    let end = self.address + statement.size() as i64;
+                    if self.address <= self.target.max_address() as i64 && end > self.target.max_address() as i64 + 1 {
+                        self.errors.push(self.error(pos, column, format!(
+                            "the program doesn't fit in the memory of {}, it ends at 0x{:X}", self.target, end - 1,
+                        )));
+                    }
+                    self.statements.push(Placed { pos, column, address: self.address, statement });
+                    self.address = end;
+                }
+            }
+        }
+        self.include_stack.pop();
+    }
+
+    fn symbol(&self, name: &str, pos: Pos, column: usize, resolving: &mut Vec<String>) -> Result<i64, AsmError> {
+        if let Some((address, _, _)) = self.labels.get(name) {
+            return Ok(*address)
+        }
+        let Some(constant) = self.constants.get(name) else {
+            return Err(self.error(pos, column, format!("undefined symbol `{}`", name)))
+        };
+        if resolving.iter().any(|resolved| resolved == name) {
+            return Err(self.error(constant.pos, constant.column, format!("`{}` is defined in terms of itself", name)))
+        }
+        resolving.push(name.to_string());
+        let value = self.evaluate(&constant.value, constant.pos, constant.here, resolving);
+        resolving.pop();
+        value
+    }
+
+    fn evaluate(&self, expr: &Expr, pos: Pos, here: i64, resolving: &mut Vec<String>) -> Result<i64, AsmError> {
+        evaluate(
+            expr,
+            here,
+            &mut |name: &str, column| self.symbol(name, pos, column, resolving),
+            &|column, message| self.error(pos, column, message),
+        )
+    }
+
+    /// Evaluates `expr` and checks that it lies in `min..=max`
+    fn ranged(&self, expr: &Expr, placed: &Placed, column: usize, min: i64, max: i64, what: &str) -> Result<i64, AsmError> {
+        let value = self.evaluate(expr, placed.pos, placed.address, &mut vec![])?;
+        if value < min || value > max {
+            return Err(self.error(placed.pos, column, format!("{} {} is out of range ({} to {})", what, value, min, max)))
+        }
+        Ok(value)
+    }
+
+    fn instruction(&self, placed: &Placed, mnemonic: &str, operands: &[(usize, Operand)]) -> Result<Instruction, AsmError> {
+        use Instruction::*;
+        use Operand::{Register as V, Value};
+
+        let address = |column: usize, expr: &Expr| self.ranged(expr, placed, column, 0, 0xFFF, "address").map(|value| value as u16);
+        let long = |column: usize, expr: &Expr| self.ranged(expr, placed, column, 0, 0xFFFF, "address").map(|value| value as u16);
+        let byte = |column: usize, expr: &Expr| self.ranged(expr, placed, column, -128, 255, "byte").map(|value| value as u8);
+        let nibble = |column: usize, expr: &Expr| self.ranged(expr, placed, column, 0, 15, "value").map(|value| value as u8);
+
+        let instruction = match (mnemonic, operands) {
+            ("CLS", []) => Cls,
+            ("RET", []) => Ret,
+            ("SCR", []) => Scr,
+            ("SCL", []) => Scl,
+            ("EXIT", []) => Exit,
+            ("LOW", []) => Low,
+            ("HIGH", []) => High,
+            ("AUDIO", []) => Audio,
+            ("SYS", [(c, Value(e))]) => Sys(address(*c, e)?),
+            ("JP", [(c, Value(e))]) => Jp(address(*c, e)?),
+            ("JP", [(_, V(0)), (c, Value(e))]) => JpV0(address(*c, e)?),
+            ("CALL", [(c, Value(e))]) => Call(address(*c, e)?),
+            ("SE", [(_, V(x)), (_, V(y))]) => SeReg(*x, *y),
+            ("SE", [(_, V(x)), (c, Value(e))]) => SeImm(*x, byte(*c, e)?),
+            ("SNE", [(_, V(x)), (_, V(y))]) => SneReg(*x, *y),
+            ("SNE", [(_, V(x)), (c, Value(e))]) => SneImm(*x, byte(*c, e)?),
+            ("LD", [(_, V(x)), (_, V(y))]) => LdReg(*x, *y),
+            ("LD", [(_, V(x)), (c, Value(e))]) => LdImm(*x, byte(*c, e)?),
+            ("LD", [(_, Operand::I), (c, Value(e))]) => LdI(address(*c, e)?),
+            ("LD", [(_, Operand::I), (c, Operand::Long(e))]) => LdILong(long(*c, e)?),
+            ("LD", [(_, V(x)), (_, Operand::Dt)]) => LdVxDt(*x),
+            ("LD", [(_, V(x)), (_, Operand::K)]) => LdVxK(*x),
+            ("LD", [(_, Operand::Dt), (_, V(x))]) => LdDtVx(*x),
+            ("LD", [(_, Operand::St), (_, V(x))]) => LdStVx(*x),
+            ("LD", [(_, Operand::F), (_, V(x))]) => LdFVx(*x),
+            ("LD", [(_, Operand::Hf), (_, V(x))]) => LdHfVx(*x),
+            ("LD", [(_, Operand::B), (_, V(x))]) => LdBVx(*x),
+            ("LD", [(_, Operand::IndirectI), (_, V(x))]) => StoreRegs(*x),
+            ("LD", [(_, V(x)), (_, Operand::IndirectI)]) => LoadRegs(*x),
+            ("LD", [(_, Operand::R), (_, V(x))]) => StoreRpl(*x),
+            ("LD", [(_, V(x)), (_, Operand::R)]) => LoadRpl(*x),
+            ("ADD", [(_, V(x)), (_, V(y))]) => AddReg(*x, *y),
+            ("ADD", [(_, V(x)), (c, Value(e))]) => AddImm(*x, byte(*c, e)?),
+            ("ADD", [(_, Operand::I), (_, V(x))]) => AddIVx(*x),
+            ("OR", [(_, V(x)), (_, V(y))]) => Or(*x, *y),
+            ("AND", [(_, V(x)), (_, V(y))]) => And(*x, *y),
+            ("XOR", [(_, V(x)), (_, V(y))]) => Xor(*x, *y),
+            ("SUB", [(_, V(x)), (_, V(y))]) => Sub(*x, *y),
+            ("SUBN", [(_, V(x)), (_, V(y))]) => Subn(*x, *y),
+            ("SHR", [(_, V(x))]) => Shr(*x, *x),
+            ("SHR", [(_, V(x)), (_, V(y))]) => Shr(*x, *y),
+            ("SHL", [(_, V(x))]) => Shl(*x, *x),
+            ("SHL", [(_, V(x)), (_, V(y))]) => Shl(*x, *y),
+            ("RND", [(_, V(x)), (c, Value(e))]) => Rnd(*x, byte(*c, e)?),
+            ("DRW", [(_, V(x)), (_, V(y)), (c, Value(e))]) => Drw(*x, *y, nibble(*c, e)?),
+            ("SKP", [(_, V(x))]) => Skp(*x),
+            ("SKNP", [(_, V(x))]) => Sknp(*x),
+            ("SCD", [(c, Value(e))]) => Scd(nibble(*c, e)?),
+            ("SCU", [(c, Value(e))]) => Scu(nibble(*c, e)?),
+            ("PLANE", [(c, Value(e))]) => Plane(nibble(*c, e)?),
+            ("PITCH", [(_, V(x))]) => Pitch(*x),
+            ("SAVE", [(_, Operand::Range(x, y))] | [(_, V(x)), (_, V(y))]) => SaveRange(*x, *y),
+            ("LOAD", [(_, Operand::Range(x, y))] | [(_, V(x)), (_, V(y))]) => LoadRange(*x, *y),
+            _ if is_mnemonic(mnemonic) => {
+                return Err(self.error(placed.pos, placed.column, format!("invalid operands for `{}`", mnemonic)))
+            }
+            _ => return Err(self.error(placed.pos, placed.column, format!("unknown instruction `{}`", mnemonic))),
+        };
+
+        if instruction.instruction_set() > self.target {
+            return Err(self.error(placed.pos, placed.column, format!(
+                "`{}` needs {}, but the target is {}", mnemonic, instruction.instruction_set(), self.target,
+            )))
+        }
+        Ok(instruction)
+    }
+
+    fn bytes(&self, placed: &Placed) -> Result<Vec<u8>, AsmError> {
+        match &placed.statement {
+            Statement::Instruction { mnemonic, operands } => Ok(self.instruction(placed, mnemonic, operands)?.to_bytes()),
+            Statement::Data { width, items } => {
+                let mut bytes = vec![];
+                for (column, item) in items {
+                    match item {
+                        DataItem::Bytes(text) => bytes.extend(text),
+                        DataItem::Value(expr) if *width == 1 => bytes.push(self.ranged(expr, placed, *column, -128, 255, "byte")? as u8),
+                        DataItem::Value(expr) => bytes.extend((self.ranged(expr, placed, *column, -32768, 65535, "word")? as u16).to_be_bytes()),
+                    }
+                }
+                Ok(bytes)
+            }
+            _ => Ok(vec![]),
+        }
+    }
+
+    fn assemble(mut self) -> Result<Assembly, Vec<AsmError>> {
+        let mut errors = std::mem::take(&mut self.errors);
+        let mut symbols = SymbolMap::default();
+        for (name, constant) in &self.constants {
+            match self.evaluate(&constant.value, constant.pos, constant.here, &mut vec![name.clone()]) {
+                Ok(value) => { symbols.constants.insert(name.clone(), value); }
+                Err(err) => errors.push(err),
+            }
+        }
+        for (name, (address, _, _)) in &self.labels {
+            symbols.labels.insert(name.clone(), *address as u16);
+        }
+
+        let mut rom = vec![];
+        for placed in &self.statements {
+            match self.bytes(placed) {
+                Ok(bytes) => rom.extend(bytes),
+                Err(err) => errors.push(err),
+            }
+            symbols.lines.push(SourceLine {
+                address: placed.address as u16,
+                file: self.files[placed.pos.file].display().to_string(),
+                line: placed.pos.line,
+            });
+        }
+
+        if !errors.is_empty() {
+            errors.sort_by_key(|err| (err.file.clone(), err.line, err.column));
+            errors.dedup();
+            return Err(errors)
+        }
+        Ok(Assembly { rom, symbols })
+    }
+}
+
+/// Whether `mnemonic`, in upper case, names an instruction of the assembler
+fn is_mnemonic(mnemonic: &str) -> bool {
+    matches!(mnemonic,
+        "CLS" | "RET" | "SCR" | "SCL" | "EXIT" | "LOW" | "HIGH" | "AUDIO" | "SYS" | "JP" | "CALL" | "SE"
+        | "SNE" | "LD" | "ADD" | "OR" | "AND" | "XOR" | "SUB" | "SUBN" | "SHR" | "SHL" | "RND" | "DRW"
+        | "SKP" | "SKNP" | "SCD" | "SCU" | "PLANE" | "PITCH" | "SAVE" | "LOAD")
+}
+
+fn assemble_with(path: &Path, target: InstructionSet, read: &dyn Fn(&Path) -> io::Result<String>) -> Result<Assembly, Vec<AsmError>> {
+    let mut assembler = Assembler {
+        target,
+        read,
+        files: vec![],
+        include_stack: vec![],
+        statements: vec![],
+        labels: BTreeMap::new(),
+        constants: BTreeMap::new(),
+        address: PROGRAM_START as i64,
+        errors: vec![],
+    };
+    assembler.load(path, None);
+    assembler.assemble()
+}
+
+/// Assembles the source file at `path` and the files it includes
+pub fn assemble_file(path: &Path, target: InstructionSet) -> Result<Assembly, Vec<AsmError>> {
+    assemble_with(path, target, &|path| fs::read_to_string(path))
+}
+
+/// Assembles `source`, named `file` in errors. Includes are read from disk
+pub fn assemble_source(file: &str, source: &str, target: InstructionSet) -> Result<Assembly, Vec<AsmError>> {
+    assemble_with(Path::new(file), target, &|path| {
+        if path == Path::new(file) { Ok(source.to_string()) } else { fs::read_to_string(path) }
+    })
+}
+
+/// Runs the `asm` subcommand
+pub fn run(args: &AsmArgs) -> color_eyre::Result<()> {
+    let assembly = match assemble_file(&args.input, args.target) {
+        Ok(assembly) => assembly,
+        Err(errors) => {
+            for err in &errors {
+                eprintln!("{}", err);
+            }
+            return Err(eyre!("{} failed to assemble with {} error(s)", args.input.display(), errors.len()))
+        }
+    };
+    let output = args.output.clone().unwrap_or_else(|| args.input.with_extension("ch8"));
+    fs::write(&output, &assembly.rom)?;
+    println!("Assembled {} bytes into {}", assembly.rom.len(), output.display());
+    if !args.no_symbols {
+        let path = args.symbols.clone().unwrap_or_else(|| SymbolMap::path_for(&output));
+        assembly.symbols.save(&path)?;
+        println!("Wrote the symbols to {}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    /// Assembles the first of `files`, reading includes from the others
+    fn assemble_files(files: &[(&str, &str)], target: InstructionSet) -> Result<Assembly, Vec<AsmError>> {
+        let sources: HashMap<PathBuf, String> = files.iter()
+            .map(|(name, source)| (PathBuf::from(name), source.to_string()))
+            .collect();
+        let read = |path: &Path| sources.get(path).cloned().ok_or_else(|| io::Error::from(io::ErrorKind::NotFound));
+        assemble_with(Path::new(files[0].0), target, &read)
+    }
+
+    fn assemble(source: &str) -> Result<Assembly, Vec<AsmError>> {
+        assemble_files(&[("main.asm", source)], InstructionSet::XoChip)
+    }
+
+    fn messages(result: Result<Assembly, Vec<AsmError>>) -> Vec<String> {
+        result.unwrap_err().iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let tokens: Vec<Tok> = tokenize("loop: LD v0, $1F + 0b101 % 2 ; comment").unwrap()
+            .into_iter().map(|token| token.tok).collect();
+        assert_eq!(tokens, vec![
+            Tok::Ident("loop".to_string()), Tok::Punct(":"), Tok::Ident("LD".to_string()),
+            Tok::Ident("v0".to_string()), Tok::Punct(","), Tok::Number(0x1F), Tok::Punct("+"),
+            Tok::Number(0b101), Tok::Punct("%"), Tok::Number(2),
+        ]);
+        assert_eq!(tokenize("db 12ab"), Err(LineError::new(4, "invalid number `12ab`")));
+    }
+
+    #[test]
+    fn test_instructions() {
+        let assembly = assemble("
+start:  CLS
+        LD V0, 10
+        LD I, sprite
+        DRW V0, V1, 5
+        SHR V2
+        LD [I], V3
+        SAVE V1 - V3
+        JP start
+sprite: db 0xF0, 0b10010000
+").unwrap();
+        assert_eq!(assembly.rom, vec![
+            0x00, 0xE0, 0x60, 0x0A, 0xA2, 0x10, 0xD0, 0x15, 0x82, 0x26, 0xF3, 0x55, 0x51, 0x32, 0x12, 0x00,
+            0xF0, 0x90,
+        ]);
+        assert_eq!(assembly.symbols.labels, BTreeMap::from([
+            ("sprite".to_string(), 0x210),
+            ("start".to_string(), 0x200),
+        ]));
+        assert_eq!(assembly.symbols.lines[1], SourceLine { address: 0x202, file: "main.asm".to_string(), line: 3 });
+    }
+
+    #[test]
+    fn test_every_instruction_round_trips() {
+        let instructions: Vec<Instruction> = (0..=0xFFFF_u16)
+            .filter_map(|opcode| Instruction::decode(opcode, Some(0x1234), InstructionSet::XoChip))
+            .collect();
+        // In pieces that fit in memory
+        for chunk in instructions.chunks(1024) {
+            let source: String = chunk.iter().map(|instruction| format!("{}\n", instruction)).collect();
+            let expected: Vec<u8> = chunk.iter().flat_map(Instruction::to_bytes).collect();
+            assert_eq!(assemble(&source).unwrap().rom, expected);
+        }
+    }
+
+    #[test]
+    fn test_constants_and_expressions() {
+        let assembly = assemble("
+WIDTH = 64
+HALF equ WIDTH / 2 - (1 << 2)
+        LD V0, HALF
+        LD V1, -1
+        JP $ + 2
+        dw end - start, ~0 & 0xFF
+start:
+end:
+").unwrap();
+        assert_eq!(assembly.rom, vec![0x60, 28, 0x61, 0xFF, 0x12, 0x06, 0x00, 0x00, 0x00, 0xFF]);
+        assert_eq!(assembly.symbols.constants["HALF"], 28);
+    }
+
+    #[test]
+    fn test_include() {
+        let assembly = assemble_files(&[
+            ("a/main.asm", "include \"lib/sub.asm\"\nCALL sub"),
+            ("a/lib/sub.asm", "sub: RET\ndb \"AB\""),
+        ], InstructionSet::Chip8).unwrap();
+        assert_eq!(assembly.rom, vec![0x00, 0xEE, 0x41, 0x42, 0x22, 0x00]);
+        assert_eq!(assembly.symbols.lines[0].file, "a/lib/sub.asm");
+
+        let errors = messages(assemble_files(&[("main.asm", "include \"main.asm\"")], InstructionSet::Chip8));
+        assert_eq!(errors, vec!["main.asm:1:1: main.asm includes itself"]);
+    }
+
+    #[test]
+    fn test_errors_have_positions() {
+        let errors = messages(assemble("
+  LD V0, missing
+  FOO V1
+  LD V0, 256
+  SE I, V0
+loop: CLS
+loop: CLS
+X = Y
+Y = X
+  db 1 +
+"));
+        assert_eq!(errors, vec![
+            "main.asm:2:10: undefined symbol `missing`",
+            "main.asm:3:3: unknown instruction `FOO`",
+            "main.asm:4:10: byte 256 is out of range (-128 to 255)",
+            "main.asm:5:3: invalid operands for `SE`",
+            "main.asm:7:1: `loop` is already defined at main.asm:6",
+            "main.asm:8:1: `X` is defined in terms of itself",
+            "main.asm:9:1: `Y` is defined in terms of itself",
+            "main.asm:10:9: expected an expression",
+        ]);
+    }
+
+    #[test]
+    fn test_target_instruction_set() {
+        let source = "SCD 4\nLD I, LONG 0x1234";
+        let errors = messages(assemble_files(&[("main.asm", source)], InstructionSet::Chip8));
+        assert_eq!(errors, vec![
+            "main.asm:1:1: `SCD` needs SCHIP, but the target is CHIP-8",
+            "main.asm:2:1: `LD` needs XO-CHIP, but the target is CHIP-8",
+        ]);
+        let assembly = assemble_files(&[("main.asm", source)], InstructionSet::XoChip).unwrap();
+        assert_eq!(assembly.rom, vec![0x00, 0xC4, 0xF0, 0x00, 0x12, 0x34]);
+
+        let source = format!("data: dw {}", vec!["0"; 1793].join(", "));
+        let errors = messages(assemble_files(&[("main.asm", &source)], InstructionSet::Chip8));
+        assert_eq!(errors, vec!["main.asm:1:7: the program doesn't fit in the memory of CHIP-8, it ends at 0x1001"]);
+    }
+}
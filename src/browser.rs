@@ -0,0 +1,211 @@
+use std::cmp::Reverse;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use serde::{Deserialize, Serialize};
+
+/// Extensions of the ROM formats the emulator can run
+pub const ROM_EXTENSIONS: [&str; 4] = ["ch8", "c8", "sc8", "xo8"];
+/// Name of the recent ROMs list in the data directory
+pub const RECENT_FILE: &str = "recent_roms.json";
+const MAX_RECENT: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryKind {
+    Parent,
+    Directory,
+    Rom { size: u64 },
+}
+
+/// A line of the file browser
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    pub path: PathBuf,
+    pub kind: EntryKind,
+}
+
+impl Entry {
+    fn rom(name: String, path: PathBuf) -> io::Result<Self> {
+        let size = fs::metadata(&path)?.len();
+        Ok(Self { name, path, kind: EntryKind::Rom { size } })
+    }
+}
+
+fn has_extension(path: &Path, extensions: &[String]) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extensions.iter().any(|allowed| allowed.eq_ignore_ascii_case(extension)))
+}
+
+/// Lists the subdirectories and ROMs of `dir`, directories first, each group sorted by name.
+/// Hidden entries are skipped
+pub fn read_dir(dir: &Path, extensions: &[String]) -> io::Result<Vec<Entry>> {
+    let mut directories = vec![];
+    let mut roms = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue
+        }
+        let path = entry.path();
+        // Follows symlinks, unlike `entry.file_type()`
+        let Ok(metadata) = fs::metadata(&path) else { continue };
+        if metadata.is_dir() {
+            directories.push(Entry { name, path, kind: EntryKind::Directory });
+        } else if has_extension(&path, extensions) {
+            roms.push(Entry { name, path, kind: EntryKind::Rom { size: metadata.len() } });
+        }
+    }
+    directories.sort_by(|a, b| a.name.cmp(&b.name));
+    roms.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut entries = vec![];
+    if let Some(parent) = dir.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        entries.push(Entry { name: "..".to_string(), path: parent.to_path_buf(), kind: EntryKind::Parent });
+    }
+    entries.extend(directories);
+    entries.extend(roms);
+    Ok(entries)
+}
+
+/// Indices of the entries matching `query`, best match first. An empty query matches everything
+/// in order
+pub fn fuzzy_filter(entries: &[Entry], query: &str) -> Vec<usize> {
+    if query.is_empty() {
+        return (0..entries.len()).collect()
+    }
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let mut scored: Vec<(i64, usize)> = entries.iter().enumerate()
+        .filter(|(_, entry)| entry.kind != EntryKind::Parent)
+        .filter_map(|(i, entry)| matcher.fuzzy_match(&entry.name, query).map(|score| (score, i)))
+        .collect();
+    // Stable, so equal scores keep the directory order
+    scored.sort_by_key(|(score, _)| Reverse(*score));
+    scored.into_iter().map(|(_, i)| i).collect()
+}
+
+/// Human readable size, e.g. `132 B` or `3.5 KiB`
+pub fn format_size(size: u64) -> String {
+    if size < 1024 {
+        format!("{} B", size)
+    } else if size < 1024 * 1024 {
+        format!("{:.1} KiB", size as f64 / 1024.0)
+    } else {
+        format!("{:.1} MiB", size as f64 / (1024.0 * 1024.0))
+    }
+}
+
+/// The last loaded ROMs, most recent first
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecentRoms {
+    paths: Vec<PathBuf>,
+}
+
+impl RecentRoms {
+    /// Reads the list from `path`. A missing file is an empty list
+    pub fn load(path: &Path) -> color_eyre::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default())
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> color_eyre::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Moves `path` to the top of the list
+    pub fn push(&mut self, path: PathBuf) {
+        let path = path.canonicalize().unwrap_or(path);
+        self.paths.retain(|recent| *recent != path);
+        self.paths.insert(0, path);
+        self.paths.truncate(MAX_RECENT);
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Browser entries of the recent ROMs that still exist
+    pub fn entries(&self) -> Vec<Entry> {
+        self.paths.iter()
+            .filter_map(|path| Entry::rom(path.display().to_string(), path.clone()).ok())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn rom_extensions() -> Vec<String> {
+        ROM_EXTENSIONS.iter().map(|extension| extension.to_string()).collect()
+    }
+
+    fn entry(name: &str, kind: EntryKind) -> Entry {
+        Entry { name: name.to_string(), path: PathBuf::from(name), kind }
+    }
+
+    #[test]
+    fn test_read_dir() {
+        let dir = std::env::temp_dir().join(format!("chip8-browser-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("games")).unwrap();
+        fs::write(dir.join("pong.ch8"), [0x00; 4]).unwrap();
+        fs::write(dir.join("Brix.XO8"), [0x00; 2]).unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        fs::write(dir.join(".hidden.ch8"), "").unwrap();
+
+        let entries = read_dir(&dir, &rom_extensions()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, vec!["..", "games", "Brix.XO8", "pong.ch8"]);
+        assert_eq!(entries[1].kind, EntryKind::Directory);
+        assert_eq!(entries[3].kind, EntryKind::Rom { size: 4 });
+    }
+
+    #[test]
+    fn test_fuzzy_filter() {
+        let entries = vec![
+            entry("..", EntryKind::Parent),
+            entry("tetris.ch8", EntryKind::Rom { size: 0 }),
+            entry("pong.ch8", EntryKind::Rom { size: 0 }),
+            entry("pong2.ch8", EntryKind::Rom { size: 0 }),
+        ];
+        assert_eq!(fuzzy_filter(&entries, ""), vec![0, 1, 2, 3]);
+        assert_eq!(fuzzy_filter(&entries, "png"), vec![2, 3]);
+        assert_eq!(fuzzy_filter(&entries, "TTS"), vec![1]);
+        assert_eq!(fuzzy_filter(&entries, "xyz"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(132), "132 B");
+        assert_eq!(format_size(3584), "3.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+
+    #[test]
+    fn test_recent_roms() {
+        let mut recent = RecentRoms::default();
+        for i in 0..12 {
+            recent.push(PathBuf::from(format!("missing-{}.ch8", i)));
+        }
+        recent.push(PathBuf::from("missing-5.ch8"));
+        assert_eq!(recent.paths().len(), MAX_RECENT);
+        assert_eq!(recent.paths()[0], PathBuf::from("missing-5.ch8"));
+        assert_eq!(recent.paths()[1], PathBuf::from("missing-11.ch8"));
+        assert_eq!(recent.paths().iter().filter(|path| path.ends_with("missing-5.ch8")).count(), 1);
+    }
+}
@@ -0,0 +1,491 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+use crate::asm::PROGRAM_START;
+use crate::cli::CfgArgs;
+use crate::disasm::Disassembly;
+use crate::isa::{Instruction, InstructionSet};
+use crate::romdb::RomDatabase;
+use crate::symbols::SymbolMap;
+
+/// How control gets from a block to the next one
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeKind {
+    /// Execution continues with the next instruction
+    Fallthrough,
+    Jump,
+    Call,
+    /// Taken when a skip instruction skips
+    Skip,
+    /// A `JP V0` to an address only known at run time
+    Indirect,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Edge {
+    pub kind: EdgeKind,
+    /// None for indirect jumps
+    pub target: Option<u16>,
+}
+
+/// A run of instructions only entered at its first one and only left after its last one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: u16,
+    /// Address after the last instruction
+    pub end: u16,
+    pub instructions: Vec<(u16, Instruction)>,
+    pub edges: Vec<Edge>,
+}
+
+/// The blocks reachable from a call target, or from the entry point, without following calls
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subroutine {
+    pub entry: u16,
+    pub name: String,
+    /// Start addresses, in address order
+    pub blocks: Vec<u16>,
+    /// Entries of the subroutines it calls or jumps into, in address order
+    pub calls: Vec<u16>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegionKind {
+    /// Referenced by `LD I` or not decodable as instructions
+    Data,
+    /// Decodes as instructions, but nothing reaches it
+    Unreachable,
+}
+
+/// A run of bytes of the ROM that aren't reachable code
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FlaggedRegion {
+    pub start: u16,
+    /// Address after the last byte
+    pub end: u16,
+    pub kind: RegionKind,
+}
+
+/// Why a subroutine of the call tree isn't expanded
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Expansion {
+    Expanded,
+    /// Already expanded further up the tree
+    Repeated,
+    /// Calls itself through the subroutines above it
+    Recursive,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CallTreeNode {
+    pub depth: usize,
+    pub entry: u16,
+    pub expansion: Expansion,
+}
+
+/// Basic blocks, subroutines and non-code regions of a ROM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlFlowGraph {
+    pub blocks: BTreeMap<u16, BasicBlock>,
+    pub subroutines: BTreeMap<u16, Subroutine>,
+    pub regions: Vec<FlaggedRegion>,
+    pub labels: BTreeMap<u16, String>,
+}
+
+fn ends_block(instruction: &Instruction) -> bool {
+    use Instruction::*;
+    matches!(instruction, Jp(_) | JpV0(_) | Call(_) | Ret | Exit) || Disassembly::is_skip(instruction)
+}
+
+impl ControlFlowGraph {
+    pub fn analyze(rom: &[u8], set: InstructionSet, symbols: Option<&SymbolMap>) -> Self {
+        Self::from_disassembly(&Disassembly::analyze(rom, set, symbols))
+    }
+
+    pub fn from_disassembly(disassembly: &Disassembly) -> Self {
+        let code = &disassembly.code;
+
+        let mut leaders = BTreeSet::from([PROGRAM_START]);
+        for (&address, instruction) in code {
+            if ends_block(instruction) {
+                leaders.extend(disassembly.successors(address, instruction));
+            }
+        }
+
+        let mut blocks = BTreeMap::new();
+        let mut current: Option<BasicBlock> = None;
+        for (&address, &instruction) in code {
+            let mut block = match current.take() {
+                Some(block) if block.end == address && !leaders.contains(&address) => block,
+                previous => {
+                    if let Some(mut previous) = previous {
+                        // Ended by the start of another block or by a gap
+                        if code.contains_key(&previous.end) {
+                            previous.edges.push(Edge { kind: EdgeKind::Fallthrough, target: Some(previous.end) });
+                        }
+                        blocks.insert(previous.start, previous);
+                    }
+                    BasicBlock { start: address, end: address, instructions: vec![], edges: vec![] }
+                }
+            };
+            block.end = address.wrapping_add(instruction.size() as u16);
+            block.instructions.push((address, instruction));
+            if ends_block(&instruction) {
+                block.edges = Self::edges(disassembly, address, &instruction);
+                blocks.insert(block.start, block);
+            } else {
+                current = Some(block);
+            }
+        }
+        if let Some(block) = current {
+            blocks.insert(block.start, block);
+        }
+        // Edges leaving the ROM or into bytes that didn't decode go nowhere known
+        for block in blocks.values_mut() {
+            block.edges.retain(|edge| edge.target.is_none_or(|target| code.contains_key(&target)));
+        }
+
+        let mut graph = Self { blocks, subroutines: BTreeMap::new(), regions: vec![], labels: disassembly.labels.clone() };
+        graph.find_subroutines();
+        graph.regions = Self::flag_regions(disassembly);
+        graph
+    }
+
+    fn edges(disassembly: &Disassembly, address: u16, instruction: &Instruction) -> Vec<Edge> {
+        let next = address.wrapping_add(instruction.size() as u16);
+        match *instruction {
+            Instruction::Jp(target) => vec![Edge { kind: EdgeKind::Jump, target: Some(target) }],
+            Instruction::JpV0(_) => vec![Edge { kind: EdgeKind::Indirect, target: None }],
+            Instruction::Call(target) => vec![
+                Edge { kind: EdgeKind::Call, target: Some(target) },
+                Edge { kind: EdgeKind::Fallthrough, target: Some(next) },
+            ],
+            Instruction::Ret | Instruction::Exit => vec![],
+            _ => {
+                let successors = disassembly.successors(address, instruction);
+                vec![
+                    Edge { kind: EdgeKind::Fallthrough, target: Some(successors[0]) },
+                    Edge { kind: EdgeKind::Skip, target: Some(successors[1]) },
+                ]
+            }
+        }
+    }
+
+    fn find_subroutines(&mut self) {
+        let mut entries: BTreeSet<u16> = self.blocks.values()
+            .flat_map(|block| &block.edges)
+            .filter(|edge| edge.kind == EdgeKind::Call)
+            .filter_map(|edge| edge.target)
+            .collect();
+        if self.blocks.contains_key(&PROGRAM_START) {
+            entries.insert(PROGRAM_START);
+        }
+
+        for &entry in &entries {
+            let mut blocks = BTreeSet::new();
+            let mut calls = BTreeSet::new();
+            let mut pending = vec![entry];
+            while let Some(start) = pending.pop() {
+                if !blocks.insert(start) {
+                    continue
+                }
+                for edge in &self.blocks[&start].edges {
+                    let Some(target) = edge.target else { continue };
+                    if edge.kind == EdgeKind::Call || (target != entry && entries.contains(&target)) {
+                        calls.insert(target);
+                    } else {
+                        pending.push(target);
+                    }
+                }
+            }
+            let name = self.name(entry);
+            self.subroutines.insert(entry, Subroutine {
+                entry,
+                name,
+                blocks: blocks.into_iter().collect(),
+                calls: calls.into_iter().collect(),
+            });
+        }
+    }
+
+    /// Runs of bytes outside the reachable code. A run is data if `LD I` points into it or it
+    /// doesn't decode as a sequence of instructions other than `SYS`
+    fn flag_regions(disassembly: &Disassembly) -> Vec<FlaggedRegion> {
+        let referenced: Vec<u16> = disassembly.code.values()
+            .filter_map(|instruction| match *instruction {
+                Instruction::LdI(target) | Instruction::LdILong(target) => Some(target),
+                _ => None,
+            })
+            .collect();
+
+        let end = PROGRAM_START as u32 + disassembly.rom().len() as u32;
+        let mut regions = vec![];
+        let mut address = PROGRAM_START as u32;
+        while address < end {
+            if disassembly.is_code(address as u16) {
+                address += 1;
+                continue
+            }
+            let start = address;
+            while address < end && !disassembly.is_code(address as u16) {
+                address += 1;
+            }
+            let (start, stop) = (start as u16, address as u16);
+            let is_referenced = referenced.iter().any(|target| (start..stop).contains(target));
+            let kind = if is_referenced || !Self::decodes(disassembly, start, stop) {
+                RegionKind::Data
+            } else {
+                RegionKind::Unreachable
+            };
+            regions.push(FlaggedRegion { start, end: stop, kind });
+        }
+        regions
+    }
+
+    fn decodes(disassembly: &Disassembly, start: u16, end: u16) -> bool {
+        let mut address = start;
+        while address < end {
+            match disassembly.decode_at(address) {
+                Some(Instruction::Sys(_)) | None => return false,
+                Some(instruction) => address += instruction.size() as u16,
+            }
+        }
+        address == end
+    }
+
+    /// The label of `address`, or its address
+    pub fn name(&self, address: u16) -> String {
+        self.labels.get(&address).cloned().unwrap_or_else(|| format!("0x{:03X}", address))
+    }
+
+    /// The subroutines called from the entry point, depth first. Every subroutine is only
+    /// expanded the first time it appears
+    pub fn call_tree(&self) -> Vec<CallTreeNode> {
+        let mut nodes = vec![];
+        let mut expanded = BTreeSet::new();
+        let mut path = vec![];
+        if self.subroutines.contains_key(&PROGRAM_START) {
+            self.walk_calls(PROGRAM_START, 0, &mut path, &mut expanded, &mut nodes);
+        }
+        nodes
+    }
+
+    fn walk_calls(
+        &self,
+        entry: u16,
+        depth: usize,
+        path: &mut Vec<u16>,
+        expanded: &mut BTreeSet<u16>,
+        nodes: &mut Vec<CallTreeNode>,
+    ) {
+        let expansion = if path.contains(&entry) {
+            Expansion::Recursive
+        } else if !expanded.insert(entry) {
+            Expansion::Repeated
+        } else {
+            Expansion::Expanded
+        };
+        nodes.push(CallTreeNode { depth, entry, expansion });
+        if expansion != Expansion::Expanded {
+            return
+        }
+        path.push(entry);
+        for &callee in &self.subroutines[&entry].calls {
+            self.walk_calls(callee, depth + 1, path, expanded, nodes);
+        }
+        path.pop();
+    }
+
+    /// The graph in Graphviz DOT format, with a cluster per subroutine
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        dot.push_str("digraph cfg {\n");
+        dot.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+
+        // Blocks shared by several subroutines are drawn in the first one
+        let mut placed = BTreeSet::new();
+        for subroutine in self.subroutines.values() {
+            let _ = writeln!(dot, "    subgraph \"cluster_{:03X}\" {{", subroutine.entry);
+            let _ = writeln!(dot, "        label=\"{}\";", escape(&subroutine.name));
+            for &start in &subroutine.blocks {
+                if placed.insert(start) {
+                    let _ = writeln!(dot, "        {};", self.block_node(&self.blocks[&start]));
+                }
+            }
+            dot.push_str("    }\n");
+        }
+        for block in self.blocks.values().filter(|block| !placed.contains(&block.start)) {
+            let _ = writeln!(dot, "    {};", self.block_node(block));
+        }
+
+        let mut has_indirect = false;
+        for block in self.blocks.values() {
+            for edge in &block.edges {
+                let target = match edge.target {
+                    Some(target) => format!("b_{:03X}", target),
+                    None => {
+                        has_indirect = true;
+                        "unknown".to_string()
+                    }
+                };
+                let style = match edge.kind {
+                    EdgeKind::Fallthrough => "",
+                    EdgeKind::Jump => " [label=\"jump\"]",
+                    EdgeKind::Call => " [style=dashed, label=\"call\"]",
+                    EdgeKind::Skip => " [color=blue, label=\"skip\"]",
+                    EdgeKind::Indirect => " [style=dotted, label=\"JP V0\"]",
+                };
+                let _ = writeln!(dot, "    b_{:03X} -> {}{};", block.start, target, style);
+            }
+        }
+        if has_indirect {
+            dot.push_str("    unknown [shape=diamond, label=\"?\"];\n");
+        }
+
+        for region in &self.regions {
+            let (name, color) = match region.kind {
+                RegionKind::Data => ("data", "lightgrey"),
+                RegionKind::Unreachable => ("unreachable", "salmon"),
+            };
+            let _ = writeln!(
+                dot,
+                "    r_{:03X} [label=\"{} 0x{:03X}-0x{:03X}\", style=filled, fillcolor={}];",
+                region.start, name, region.start, region.end - 1, color,
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn block_node(&self, block: &BasicBlock) -> String {
+        let mut label = String::new();
+        if let Some(name) = self.labels.get(&block.start) {
+            let _ = write!(label, "{}:\\l", escape(name));
+        }
+        for (address, instruction) in &block.instructions {
+            let text = instruction.format_with(|target| self.name(target));
+            let _ = write!(label, "{:03X}  {}\\l", address, escape(&text));
+        }
+        format!("b_{:03X} [label=\"{}\"]", block.start, label)
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Runs the `cfg` subcommand
+pub fn run(args: &CfgArgs) -> color_eyre::Result<()> {
+    let rom = fs::read(&args.rom)?;
+    let symbols = match &args.symbols {
+        Some(path) => Some(SymbolMap::load(path)?),
+        None => SymbolMap::load_for_rom(&args.rom)?,
+    };
+    let set = RomDatabase::profile_instruction_set(args.target, args.profile.as_deref())?;
+    let graph = ControlFlowGraph::analyze(&rom, set, symbols.as_ref());
+    for region in &graph.regions {
+        let kind = match region.kind {
+            RegionKind::Data => "probable data",
+            RegionKind::Unreachable => "unreachable code",
+        };
+        eprintln!("0x{:03X}-0x{:03X}: {}", region.start, region.end - 1, kind);
+    }
+    let dot = graph.to_dot();
+    match &args.output {
+        Some(path) => fs::write(path, dot)?,
+        None => print!("{}", dot),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::asm;
+
+    fn graph(source: &str) -> ControlFlowGraph {
+        let rom = asm::assemble_source("test.asm", source, InstructionSet::Chip8).unwrap().rom;
+        ControlFlowGraph::analyze(&rom, InstructionSet::Chip8, None)
+    }
+
+    #[test]
+    fn test_blocks_and_edges() {
+        let graph = graph("
+            start:
+                LD V0, 1
+                SE V0, 2
+                CALL draw
+            loop:
+                JP loop
+            draw:
+                LD I, sprite
+                RET
+            sprite:
+                db 0xF0, 0x90
+        ");
+        assert_eq!(graph.blocks.keys().copied().collect::<Vec<_>>(), vec![0x200, 0x204, 0x206, 0x208]);
+        assert_eq!(graph.blocks[&0x200].edges, vec![
+            Edge { kind: EdgeKind::Fallthrough, target: Some(0x204) },
+            Edge { kind: EdgeKind::Skip, target: Some(0x206) },
+        ]);
+        assert_eq!(graph.blocks[&0x204].edges, vec![
+            Edge { kind: EdgeKind::Call, target: Some(0x208) },
+            Edge { kind: EdgeKind::Fallthrough, target: Some(0x206) },
+        ]);
+        assert_eq!(graph.blocks[&0x206].edges, vec![Edge { kind: EdgeKind::Jump, target: Some(0x206) }]);
+        assert_eq!(graph.blocks[&0x208].instructions.len(), 2);
+
+        assert_eq!(graph.subroutines.keys().copied().collect::<Vec<_>>(), vec![0x200, 0x208]);
+        assert_eq!(graph.subroutines[&0x200].blocks, vec![0x200, 0x204, 0x206]);
+        assert_eq!(graph.subroutines[&0x200].calls, vec![0x208]);
+        assert_eq!(graph.regions, vec![FlaggedRegion { start: 0x20C, end: 0x20E, kind: RegionKind::Data }]);
+    }
+
+    #[test]
+    fn test_flags_unreachable_code_and_indirect_jumps() {
+        let graph = graph("
+                JP V0, table
+            table:
+                CLS
+                RET
+        ");
+        assert_eq!(graph.blocks[&0x200].edges, vec![Edge { kind: EdgeKind::Indirect, target: None }]);
+        assert_eq!(graph.regions, vec![FlaggedRegion { start: 0x202, end: 0x206, kind: RegionKind::Unreachable }]);
+        let dot = graph.to_dot();
+        assert!(dot.contains("b_200 -> unknown"));
+        assert!(dot.contains("r_202 [label=\"unreachable 0x202-0x205\""));
+    }
+
+    #[test]
+    fn test_call_tree() {
+        let graph = graph("
+                CALL first
+                CALL second
+            halt:
+                JP halt
+            first:
+                CALL second
+                RET
+            second:
+                CALL first
+                RET
+        ");
+        let tree: Vec<_> = graph.call_tree().into_iter()
+            .map(|node| (node.depth, node.entry, node.expansion))
+            .collect();
+        assert_eq!(tree, vec![
+            (0, 0x200, Expansion::Expanded),
+            (1, 0x206, Expansion::Expanded),
+            (2, 0x20A, Expansion::Expanded),
+            (3, 0x206, Expansion::Recursive),
+            (1, 0x20A, Expansion::Repeated),
+        ]);
+    }
+}
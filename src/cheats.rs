@@ -0,0 +1,148 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Directory of the cheat lists inside the config directory
+pub const CHEATS_DIR: &str = "cheats";
+
+/// How a byte must compare to its value in the previous snapshot to stay a candidate
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    Changed,
+    Increased,
+    Decreased,
+    /// Equal to the value, whatever it was before
+    Value(u8),
+}
+
+impl Comparison {
+    fn matches(self, before: u8, after: u8) -> bool {
+        match self {
+            Comparison::Equal => after == before,
+            Comparison::Changed => after != before,
+            Comparison::Increased => after > before,
+            Comparison::Decreased => after < before,
+            Comparison::Value(value) => after == value,
+        }
+    }
+}
+
+/// Narrows down the addresses of a variable by comparing snapshots of memory
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheatSearch {
+    snapshot: Option<Vec<u8>>,
+    candidates: Vec<u16>,
+}
+
+impl CheatSearch {
+    /// Starts over with every address of `memory` as a candidate
+    pub fn start(&mut self, memory: &[u8]) {
+        self.candidates = (0..memory.len() as u16).collect();
+        self.snapshot = Some(memory.to_vec());
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.snapshot.is_some()
+    }
+
+    /// Keeps the candidates whose byte in `memory` compares to the previous snapshot as
+    /// `comparison` says, then takes `memory` as the new snapshot
+    pub fn filter(&mut self, memory: &[u8], comparison: Comparison) {
+        let Some(snapshot) = &self.snapshot else { return };
+        self.candidates.retain(|&address| {
+            match (snapshot.get(address as usize), memory.get(address as usize)) {
+                (Some(&before), Some(&after)) => comparison.matches(before, after),
+                _ => false,
+            }
+        });
+        self.snapshot = Some(memory.to_vec());
+    }
+
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    /// The value of `address` in the last snapshot
+    pub fn previous(&self, address: u16) -> Option<u8> {
+        self.snapshot.as_ref()?.get(address as usize).copied()
+    }
+}
+
+/// A byte of memory frozen to a value
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Cheat {
+    pub address: u16,
+    pub value: u8,
+}
+
+/// The cheats of a ROM
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CheatList {
+    pub cheats: Vec<Cheat>,
+}
+
+impl CheatList {
+    /// Path of the cheat list of the ROM with SHA-1 `hash`
+    pub fn path(config_dir: &Path, hash: &str) -> PathBuf {
+        config_dir.join(CHEATS_DIR).join(format!("{}.json", hash))
+    }
+
+    /// Loads the cheat list of the ROM with SHA-1 `hash`, empty if it has none
+    pub fn load(config_dir: &Path, hash: &str) -> color_eyre::Result<Self> {
+        let path = Self::path(config_dir, hash);
+        if !path.exists() {
+            return Ok(Self::default())
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Writes the cheat list of the ROM with SHA-1 `hash`, returning its path
+    pub fn save(&self, config_dir: &Path, hash: &str) -> color_eyre::Result<PathBuf> {
+        let path = Self::path(config_dir, hash);
+        fs::create_dir_all(config_dir.join(CHEATS_DIR))?;
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_search() {
+        let mut search = CheatSearch::default();
+        search.start(&[3, 3, 7, 0]);
+        search.filter(&[2, 3, 8, 0], Comparison::Changed);
+        assert_eq!(search.candidates(), &[0, 2]);
+        search.filter(&[1, 3, 9, 0], Comparison::Decreased);
+        assert_eq!(search.candidates(), &[0]);
+        search.filter(&[1, 3, 9, 0], Comparison::Value(1));
+        assert_eq!(search.candidates(), &[0]);
+        search.filter(&[1, 3, 9, 0], Comparison::Increased);
+        assert!(search.candidates().is_empty());
+
+        search.start(&[5, 5]);
+        search.filter(&[5, 6], Comparison::Equal);
+        assert_eq!(search.candidates(), &[0]);
+        assert_eq!(search.previous(1), Some(6));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("chip8-cheats-test-{}", std::process::id()));
+        let list = CheatList { cheats: vec![Cheat { address: 0x3A0, value: 9 }] };
+        list.save(&dir, "abc").unwrap();
+        let loaded = CheatList::load(&dir, "abc").unwrap();
+        let missing = CheatList::load(&dir, "def").unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded, list);
+        assert_eq!(missing, CheatList::default());
+    }
+}
@@ -1,6 +1,10 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
+
+use crate::isa::InstructionSet;
+use crate::patch::PatchFormat;
+use crate::random::RandomKind;
 
 #[derive(Parser, Debug)]
 #[command(author, about)]
@@ -17,4 +21,162 @@ pub struct Cli {
     default_value_t = 10.0
   )]
   pub frame_rate: f64,
+
+  #[arg(long, value_name = "FILE", help = "Record keypad input of the loaded ROM to a movie file")]
+  pub record: Option<PathBuf>,
+
+  #[arg(long, value_name = "FILE", help = "Replay a previously recorded movie file", conflicts_with = "record")]
+  pub replay: Option<PathBuf>,
+
+  #[arg(long, value_name = "INT", help = "Seed of the random number generator used by CXNN")]
+  pub seed: Option<u64>,
+
+  #[arg(long, value_enum, help = "Random number generator used by CXNN")]
+  pub random: Option<RandomKind>,
+
+  #[arg(long, help = "Reload the loaded ROM whenever its file changes")]
+  pub watch: bool,
+
+  #[arg(long, value_name = "FILE", help = "Write an lcov report of the code executed by the loaded ROM on exit")]
+  pub coverage: Option<PathBuf>,
+
+  #[command(subcommand)]
+  pub command: Option<Command>,
+}
+
+/// Tools that run without starting the emulator
+#[derive(Subcommand, Debug)]
+pub enum Command {
+  /// Assemble a program to a ROM
+  Asm(AsmArgs),
+  /// Disassemble a ROM to assembler source
+  Disasm(DisasmArgs),
+  /// Build the control flow graph of a ROM and write it in Graphviz DOT format
+  Cfg(CfgArgs),
+  /// Create or apply IPS and BPS patches
+  Patch(PatchArgs),
+  /// Warn about instructions of a ROM that behave differently across platforms
+  Lint(LintArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct AsmArgs {
+  #[arg(value_name = "FILE", help = "Source file to assemble")]
+  pub input: PathBuf,
+
+  #[arg(short, long, value_name = "FILE", help = "ROM to write, the source file with a .ch8 extension by default")]
+  pub output: Option<PathBuf>,
+
+  #[arg(long, value_enum, default_value_t = InstructionSet::Chip8, help = "Instruction set the program may use")]
+  pub target: InstructionSet,
+
+  #[arg(long, value_name = "FILE", help = "Symbol map to write, the ROM with a .sym extension by default")]
+  pub symbols: Option<PathBuf>,
+
+  #[arg(long, help = "Don't write a symbol map", conflicts_with = "symbols")]
+  pub no_symbols: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct DisasmArgs {
+  #[arg(value_name = "FILE", help = "ROM to disassemble")]
+  pub rom: PathBuf,
+
+  #[arg(short, long, value_name = "FILE", help = "File to write the listing to instead of the standard output")]
+  pub output: Option<PathBuf>,
+
+  #[arg(long, value_enum, default_value_t = InstructionSet::Chip8, help = "Instruction set to decode")]
+  pub target: InstructionSet,
+
+  #[arg(long, value_name = "PLATFORM", conflicts_with = "target",
+  help = "Platform of the ROM database whose instruction set to decode, e.g. superchip or xochip")]
+  pub profile: Option<String>,
+
+  #[arg(long, help = "Decode every word in order instead of following the control flow")]
+  pub linear: bool,
+
+  #[arg(long, help = "Write the listing as JSON")]
+  pub json: bool,
+
+  #[arg(long, value_name = "FILE", help = "Symbol map naming the labels, the ROM with a .sym extension by default")]
+  pub symbols: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct CfgArgs {
+  #[arg(value_name = "FILE", help = "ROM to analyze")]
+  pub rom: PathBuf,
+
+  #[arg(short, long, value_name = "FILE", help = "DOT file to write instead of the standard output")]
+  pub output: Option<PathBuf>,
+
+  #[arg(long, value_enum, default_value_t = InstructionSet::Chip8, help = "Instruction set to decode")]
+  pub target: InstructionSet,
+
+  #[arg(long, value_name = "PLATFORM", conflicts_with = "target",
+  help = "Platform of the ROM database whose instruction set to decode, e.g. superchip or xochip")]
+  pub profile: Option<String>,
+
+  #[arg(long, value_name = "FILE", help = "Symbol map naming the blocks, the ROM with a .sym extension by default")]
+  pub symbols: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct LintArgs {
+  #[arg(value_name = "FILE", help = "ROM to check")]
+  pub rom: PathBuf,
+
+  #[arg(long, value_enum, default_value_t = InstructionSet::Chip8, help = "Instruction set the program is meant to run on")]
+  pub target: InstructionSet,
+
+  #[arg(long, value_name = "PLATFORM", conflicts_with = "target",
+  help = "Platform of the ROM database the program is meant to run on, e.g. superchip or xochip")]
+  pub profile: Option<String>,
+
+  #[arg(long, value_name = "FILE", help = "Symbol map of the ROM, the ROM with a .sym extension by default")]
+  pub symbols: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct PatchArgs {
+  #[command(subcommand)]
+  pub command: PatchCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PatchCommand {
+  /// Write the patch turning a ROM into a modified one
+  Create(PatchCreateArgs),
+  /// Write a ROM with a patch applied
+  Apply(PatchApplyArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct PatchCreateArgs {
+  #[arg(value_name = "ORIGINAL", help = "ROM the patch applies to")]
+  pub original: PathBuf,
+
+  #[arg(value_name = "MODIFIED", help = "ROM the patch produces")]
+  pub modified: PathBuf,
+
+  #[arg(short, long, value_name = "FILE", help = "Patch to write")]
+  pub output: PathBuf,
+
+  #[arg(long, value_enum, help = "Format of the patch, by default the one of the extension of the output, or BPS")]
+  pub format: Option<PatchFormat>,
+}
+
+#[derive(Args, Debug)]
+pub struct PatchApplyArgs {
+  #[arg(value_name = "ROM", help = "ROM to patch")]
+  pub rom: PathBuf,
+
+  #[arg(value_name = "PATCH", help = "IPS or BPS patch")]
+  pub patch: PathBuf,
+
+  #[arg(short, long, value_name = "FILE", help = "Patched ROM to write")]
+  pub output: PathBuf,
+
+  #[arg(long, value_name = "HASH", help = "SHA-1 the patched ROM must have")]
+  pub sha1: Option<String>,
 }
@@ -6,6 +6,7 @@ use tokio::sync::mpsc::UnboundedSender;
 use crate::{
   action::Action,
   config::Config,
+  layout::Region,
   tui::{Event, Frame},
 };
 
@@ -13,7 +14,15 @@ pub mod screen;
 pub mod status;
 pub mod opcodes_list;
 pub mod file_selector;
-mod info;
+pub mod keypad;
+pub mod registers;
+pub mod error_popup;
+pub mod error_log;
+pub mod call_graph;
+pub mod cheat_search;
+pub mod sprite_viewer;
+pub mod help;
+pub mod info;
 
 /// `Component` is a trait that represents a visual and interactive element of the user interface.
 /// Implementors of this trait can be registered with the main application loop and will be able to receive events,
@@ -57,6 +66,14 @@ pub trait Component {
   fn init(&mut self, area: Rect) -> Result<()> {
     Ok(())
   }
+  /// The layout region the component is drawn into.
+  ///
+  /// # Returns
+  ///
+  /// * `Option<Region>` - The region, or none to draw over the whole frame.
+  fn region(&self) -> Option<Region> {
+    None
+  }
   /// Handle incoming events and produce actions if necessary.
   ///
   /// # Arguments
@@ -118,7 +135,8 @@ pub trait Component {
   /// # Arguments
   ///
   /// * `f` - A frame used for rendering.
-  /// * `area` - The area in which the component should be drawn.
+  /// * `area` - The area in which the component should be drawn. It is empty while the region of
+  ///   the component is hidden.
   ///
   /// # Returns
   ///
@@ -0,0 +1,133 @@
+use crossterm::event::{MouseEvent, MouseEventKind};
+use ratatui::layout::{Position, Rect};
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph};
+use crate::action::Action;
+use crate::cfg::{ControlFlowGraph, Expansion, RegionKind};
+use crate::components::Component;
+use crate::config::{Config, Styles};
+use crate::isa::InstructionSet;
+use crate::layout::Region;
+use crate::mode::Mode;
+use crate::symbols::SymbolMap;
+use crate::tui::Frame;
+
+/// Call tree of the loaded program and the parts of the ROM no path reaches
+#[derive(Default)]
+pub struct CallGraph {
+    rom: Vec<u8>,
+    set: InstructionSet,
+    symbols: Option<SymbolMap>,
+    lines: Vec<Line<'static>>,
+    /// Number of lines scrolled down from the top
+    scroll: usize,
+    area: Rect,
+    styles: Styles,
+}
+
+impl CallGraph {
+    pub fn new() -> Self { Self::default() }
+
+    fn rebuild(&mut self) {
+        self.scroll = 0;
+        self.lines.clear();
+        if self.rom.is_empty() {
+            return
+        }
+        let graph = ControlFlowGraph::analyze(&self.rom, self.set, self.symbols.as_ref());
+        for node in graph.call_tree() {
+            let subroutine = &graph.subroutines[&node.entry];
+            let mut spans = vec![Span::raw(format!(
+                "{}{} 0x{:03X}",
+                "  ".repeat(node.depth),
+                subroutine.name,
+                subroutine.entry,
+            ))];
+            match node.expansion {
+                Expansion::Expanded => spans.push(Span::styled(
+                    format!(" {} blocks", subroutine.blocks.len()),
+                    Style::default().fg(Color::DarkGray),
+                )),
+                Expansion::Repeated => spans.push(Span::styled(" ...", Style::default().fg(Color::DarkGray))),
+                Expansion::Recursive => spans.push(Span::styled(" recursive", Style::default().fg(Color::Yellow))),
+            }
+            self.lines.push(Line::from(spans));
+        }
+        if graph.blocks.values().any(|block| block.edges.iter().any(|edge| edge.target.is_none())) {
+            self.lines.push(Line::styled("JP V0 targets are unknown", Style::default().fg(Color::Yellow)));
+        }
+        if !graph.regions.is_empty() {
+            self.lines.push(Line::raw(""));
+        }
+        for region in &graph.regions {
+            let (label, color) = match region.kind {
+                RegionKind::Data => ("probable data", Color::Cyan),
+                RegionKind::Unreachable => ("unreachable code", Color::Red),
+            };
+            self.lines.push(Line::from(vec![
+                Span::raw(format!("0x{:03X}-0x{:03X} ", region.start, region.end - 1)),
+                Span::styled(label, Style::default().fg(color)),
+            ]));
+        }
+    }
+
+    fn scroll_up(&mut self) {
+        self.scroll = self.scroll.saturating_sub(1);
+    }
+
+    fn scroll_down(&mut self) {
+        self.scroll = (self.scroll + 1).min(self.lines.len().saturating_sub(1));
+    }
+}
+
+impl Component for CallGraph {
+    fn region(&self) -> Option<Region> { Some(Region::CallGraph) }
+
+    fn register_config_handler(&mut self, config: Config) -> color_eyre::Result<()> {
+        self.styles = config.styles;
+        Ok(())
+    }
+
+    fn handle_mouse_events(&mut self, mouse: MouseEvent) -> color_eyre::Result<Option<Action>> {
+        if !self.area.contains(Position { x: mouse.column, y: mouse.row }) {
+            return Ok(None)
+        }
+        match mouse.kind {
+            MouseEventKind::ScrollUp => self.scroll_up(),
+            MouseEventKind::ScrollDown => self.scroll_down(),
+            _ => {}
+        }
+
+        Ok(None)
+    }
+
+    fn update(&mut self, action: Action) -> color_eyre::Result<Option<Action>> {
+        match action {
+            Action::SetProgram(rom, set) => {
+                self.rom = rom;
+                self.set = set;
+                self.rebuild();
+            }
+            Action::SetSymbols(symbols) => {
+                self.symbols = symbols;
+                self.rebuild();
+            }
+
+            _ => {}
+        }
+
+        Ok(None)
+    }
+
+    fn draw(&mut self, f: &mut Frame<'_>, area: Rect) -> color_eyre::Result<()> {
+        self.area = area;
+        let block = Block::default().title("Call graph").borders(Borders::ALL)
+            .border_style(self.styles.get_style(Mode::Home, "border"));
+        let lines: Vec<Line> = self.lines.iter().skip(self.scroll).cloned().collect();
+
+        f.render_widget(Paragraph::new(lines).block(block), area);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use crate::action::Action;
+use crate::cheats::{Cheat, CheatSearch, Comparison};
+use crate::components::Component;
+use crate::config::{Config, Styles};
+use crate::layout::Region;
+use crate::mode::Mode;
+use crate::tui::Frame;
+
+/// Most candidates listed, the search goes on with all of them
+const MAX_LISTED: usize = 256;
+
+/// Finds variables by filtering snapshots of memory and freezes them. Digits typed while
+/// focused make up the value searched for or frozen to
+#[derive(Default)]
+pub struct CheatSearchPanel {
+    memory: Vec<u8>,
+    search: CheatSearch,
+    cheats: Vec<Cheat>,
+    value: String,
+    state: ListState,
+    is_focused: bool,
+    styles: Styles,
+}
+
+impl CheatSearchPanel {
+    pub fn new() -> Self { Self::default() }
+
+    /// Frozen addresses first, then the candidates that aren't frozen
+    fn entries(&self) -> Vec<u16> {
+        let mut entries: Vec<u16> = self.cheats.iter().map(|cheat| cheat.address).collect();
+        entries.extend(self.search.candidates().iter()
+            .filter(|address| !self.cheats.iter().any(|cheat| cheat.address == **address))
+            .take(MAX_LISTED));
+        entries
+    }
+
+    fn typed_value(&self) -> Option<u8> {
+        self.value.parse().ok()
+    }
+
+    fn filter(&mut self, comparison: Comparison) {
+        if !self.search.is_started() {
+            self.search.start(&self.memory);
+        }
+        self.search.filter(&self.memory, comparison);
+        self.state.select(Some(0));
+    }
+
+    /// Freezes the selected address to the typed value or else its current one, or thaws it
+    fn toggle_freeze(&mut self) -> Option<Action> {
+        let address = *self.entries().get(self.state.selected()?)?;
+        if let Some(i) = self.cheats.iter().position(|cheat| cheat.address == address) {
+            self.cheats.remove(i);
+        } else {
+            let value = self.typed_value().or_else(|| self.memory.get(address as usize).copied())?;
+            self.cheats.push(Cheat { address, value });
+            self.cheats.sort();
+            self.value.clear();
+        }
+        Some(Action::SetCheats(self.cheats.clone()))
+    }
+
+    fn move_selection(&mut self, up: bool) {
+        let count = self.entries().len();
+        if count == 0 {
+            return
+        }
+        let selected = self.state.selected().unwrap_or(0).min(count - 1);
+        self.state.select(Some(if up { (selected + count - 1) % count } else { (selected + 1) % count }));
+    }
+}
+
+impl Component for CheatSearchPanel {
+    fn region(&self) -> Option<Region> { Some(Region::Cheats) }
+
+    fn register_config_handler(&mut self, config: Config) -> color_eyre::Result<()> {
+        self.styles = config.styles;
+        Ok(())
+    }
+
+    fn handle_key_events(&mut self, key: KeyEvent) -> color_eyre::Result<Option<Action>> {
+        if !self.is_focused || key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) {
+            return Ok(None)
+        }
+        match key.code {
+            KeyCode::Char(c) if c.is_ascii_digit() && self.value.len() < 3 => self.value.push(c),
+            KeyCode::Backspace => { self.value.pop(); },
+            _ => {}
+        }
+
+        Ok(None)
+    }
+
+    fn update(&mut self, action: Action) -> color_eyre::Result<Option<Action>> {
+        match action {
+            Action::ModeChanged(mode) => self.is_focused = mode == Mode::CheatSearch,
+            Action::UpdateMemory(memory) => self.memory = memory,
+            Action::LoadCheats(cheats) => {
+                self.cheats = cheats;
+                self.search = CheatSearch::default();
+                self.state.select(None);
+            }
+            Action::CheatNewSearch => {
+                self.search.start(&self.memory);
+                self.state.select(Some(0));
+            }
+            Action::CheatFilterEqual => self.filter(Comparison::Equal),
+            Action::CheatFilterChanged => self.filter(Comparison::Changed),
+            Action::CheatFilterIncreased => self.filter(Comparison::Increased),
+            Action::CheatFilterDecreased => self.filter(Comparison::Decreased),
+            Action::CheatFilterValue => {
+                if let Some(value) = self.typed_value() {
+                    self.filter(Comparison::Value(value));
+                    self.value.clear();
+                }
+            }
+            Action::ToggleFreeze => return Ok(self.toggle_freeze()),
+            Action::MoveCheatSelectionUp => self.move_selection(true),
+            Action::MoveCheatSelectionDown => self.move_selection(false),
+
+            _ => {}
+        }
+
+        Ok(None)
+    }
+
+    fn draw(&mut self, f: &mut Frame<'_>, area: Rect) -> color_eyre::Result<()> {
+        let border = if self.is_focused { "border_focused" } else { "border" };
+        let title = match self.search.is_started() {
+            true => format!("Cheats {} found", self.search.candidates().len()),
+            false => "Cheats".to_string(),
+        };
+        let block = Block::default().title(title).borders(Borders::ALL)
+            .border_style(self.styles.get_style(Mode::Home, border));
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+        if inner.height == 0 {
+            return Ok(())
+        }
+
+        let input = Rect { height: 1, ..inner };
+        f.render_widget(Paragraph::new(format!("Value: {}", self.value)), input);
+
+        let items: Vec<ListItem> = self.entries().into_iter()
+            .map(|address| {
+                let current = self.memory.get(address as usize).copied().unwrap_or(0);
+                match self.cheats.iter().find(|cheat| cheat.address == address) {
+                    Some(cheat) => ListItem::new(Line::styled(
+                        format!("{:03X} ={:>3} frozen", address, cheat.value),
+                        Style::default().fg(Color::Cyan),
+                    )),
+                    None => {
+                        let previous = self.search.previous(address).unwrap_or(current);
+                        ListItem::new(format!("{:03X} {:>3} was {:>3}", address, current, previous))
+                    }
+                }
+            })
+            .collect();
+        let list = List::new(items)
+            .highlight_style(self.styles.get_style(Mode::Home, "highlight"))
+            .highlight_symbol(">");
+        let entries = Rect { y: inner.y + 1, height: inner.height - 1, ..inner };
+        f.render_stateful_widget(list, entries, &mut self.state);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+use crossterm::event::{MouseEvent, MouseEventKind};
+use ratatui::layout::{Position, Rect};
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph};
+use crate::action::Action;
+use crate::components::Component;
+use crate::config::{Config, Styles};
+use crate::layout::Region;
+use crate::mode::Mode;
+use crate::tui::Frame;
+
+/// Number of entries kept in the history
+const HISTORY_SIZE: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Severity {
+    Warning,
+    Error,
+}
+
+struct Entry {
+    /// Seconds since the start of the application
+    time: f64,
+    severity: Severity,
+    message: String,
+}
+
+/// Scrollable history of the recent errors and warnings, newest last
+pub struct ErrorLog {
+    started: Instant,
+    entries: VecDeque<Entry>,
+    /// Number of entries scrolled up from the newest one
+    scroll: usize,
+    area: Rect,
+    styles: Styles,
+}
+
+impl Default for ErrorLog {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            entries: VecDeque::new(),
+            scroll: 0,
+            area: Rect::default(),
+            styles: Styles::default(),
+        }
+    }
+}
+
+impl ErrorLog {
+    pub fn new() -> Self { Self::default() }
+
+    fn push(&mut self, severity: Severity, message: String) {
+        self.entries.push_back(Entry { time: self.started.elapsed().as_secs_f64(), severity, message });
+        if self.entries.len() > HISTORY_SIZE {
+            self.entries.pop_front();
+        }
+        // Keep the scrolled to entries in view
+        if self.scroll > 0 {
+            self.scroll = (self.scroll + 1).min(self.entries.len() - 1);
+        }
+    }
+
+    fn scroll_up(&mut self) {
+        self.scroll = (self.scroll + 1).min(self.entries.len().saturating_sub(1));
+    }
+
+    fn scroll_down(&mut self) {
+        self.scroll = self.scroll.saturating_sub(1);
+    }
+}
+
+impl Component for ErrorLog {
+    fn region(&self) -> Option<Region> { Some(Region::Log) }
+
+    fn register_config_handler(&mut self, config: Config) -> color_eyre::Result<()> {
+        self.styles = config.styles;
+        Ok(())
+    }
+
+    fn handle_mouse_events(&mut self, mouse: MouseEvent) -> color_eyre::Result<Option<Action>> {
+        if !self.area.contains(Position { x: mouse.column, y: mouse.row }) {
+            return Ok(None)
+        }
+        match mouse.kind {
+            MouseEventKind::ScrollUp => self.scroll_up(),
+            MouseEventKind::ScrollDown => self.scroll_down(),
+            _ => {}
+        }
+
+        Ok(None)
+    }
+
+    fn update(&mut self, action: Action) -> color_eyre::Result<Option<Action>> {
+        match action {
+            Action::Error(message) => self.push(Severity::Error, message),
+            Action::Fault(fault) => {
+                let message = format!("{} at {:0>3X}", fault.message, fault.state.program_counter);
+                self.push(Severity::Error, message)
+            }
+            Action::Warning(message) => self.push(Severity::Warning, message),
+            Action::ScrollErrorLogUp => self.scroll_up(),
+            Action::ScrollErrorLogDown => self.scroll_down(),
+
+            _ => {}
+        }
+
+        Ok(None)
+    }
+
+    fn draw(&mut self, f: &mut Frame<'_>, area: Rect) -> color_eyre::Result<()> {
+        self.area = area;
+        let block = Block::default().title("Errors").borders(Borders::ALL)
+            .border_style(self.styles.get_style(Mode::Home, "border"));
+        let visible = block.inner(area).height as usize;
+
+        let end = self.entries.len() - self.scroll.min(self.entries.len());
+        let start = end.saturating_sub(visible);
+        let lines: Vec<Line> = self.entries.range(start..end)
+            .map(|entry| {
+                let (label, color) = match entry.severity {
+                    Severity::Warning => ("WARN ", Color::Yellow),
+                    Severity::Error => ("ERROR", Color::Red),
+                };
+                Line::from(vec![
+                    Span::raw(format!("{:>7.1}s ", entry.time)),
+                    Span::styled(label, Style::default().fg(color)),
+                    Span::raw(format!(" {}", entry.message)),
+                ])
+            })
+            .collect();
+
+        f.render_widget(Paragraph::new(lines).block(block), area);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Clear, Paragraph, Wrap};
+use crate::action::Action;
+use crate::components::Component;
+use crate::components::help::short_hint;
+use crate::config::{Config, KeyBindings, Styles};
+use crate::emulator::EmulationFault;
+use crate::mode::Mode;
+use crate::tui::Frame;
+
+const POPUP_WIDTH: u16 = 60;
+const POPUP_HEIGHT: u16 = 12;
+
+/// Overlay describing the error that paused the emulation
+#[derive(Default)]
+pub struct ErrorPopup {
+    fault: Option<EmulationFault>,
+    keybindings: KeyBindings,
+    styles: Styles,
+}
+
+impl ErrorPopup {
+    pub fn new() -> Self { Self::default() }
+
+    fn lines(&self, fault: &EmulationFault) -> Vec<Line<'static>> {
+        let state = &fault.state;
+        let registers = |range: std::ops::Range<usize>| {
+            range.map(|i| format!("V{:X}:{:0>2X}", i, state.registers.get(i).copied().unwrap_or(0)))
+                .collect::<Vec<String>>()
+                .join(" ")
+        };
+        vec![
+            Line::from(fault.message.clone()),
+            Line::from(""),
+            Line::from(format!("PC:{:0>3X} Opcode:{:0>4X}", state.program_counter, fault.opcode)),
+            Line::from(registers(0..8)),
+            Line::from(registers(8..16)),
+            Line::from(format!(
+                "I:{:0>3X} SP:{:X} DT:{:0>2X} ST:{:0>2X}",
+                state.index_register, state.stack_pointer, state.delay_timer, state.sound_timer
+            )),
+            Line::from(""),
+            Line::from(short_hint(&self.keybindings, Mode::Error)),
+        ]
+    }
+}
+
+/// A `width` x `height` area in the middle of `area`
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let [_, row, _] = Layout::vertical([Constraint::Fill(1), Constraint::Length(height), Constraint::Fill(1)])
+        .areas(area);
+    let [_, popup, _] = Layout::horizontal([Constraint::Fill(1), Constraint::Length(width), Constraint::Fill(1)])
+        .areas(row);
+    popup
+}
+
+impl Component for ErrorPopup {
+    fn register_config_handler(&mut self, config: Config) -> color_eyre::Result<()> {
+        self.keybindings = config.keybindings;
+        self.styles = config.styles;
+        Ok(())
+    }
+
+    fn update(&mut self, action: Action) -> color_eyre::Result<Option<Action>> {
+        match action {
+            Action::Fault(fault) => { self.fault = Some(fault) }
+            Action::ContinueAfterError
+            | Action::SkipInstruction
+            | Action::Reset
+            | Action::DismissError
+            | Action::LoadFile(_) => { self.fault = None }
+
+            _ => {}
+        }
+
+        Ok(None)
+    }
+
+    fn draw(&mut self, f: &mut Frame<'_>, area: Rect) -> color_eyre::Result<()> {
+        let Some(fault) = &self.fault else { return Ok(()) };
+
+        let popup = centered(area, POPUP_WIDTH.min(area.width), POPUP_HEIGHT.min(area.height));
+        let paragraph = Paragraph::new(self.lines(fault))
+            .wrap(Wrap { trim: true })
+            .block(Block::default().title("Emulation error").borders(Borders::ALL)
+                .border_style(self.styles.get_style(Mode::Error, "error")));
+        f.render_widget(Clear, popup);
+        f.render_widget(paragraph, popup);
+
+        Ok(())
+    }
+}
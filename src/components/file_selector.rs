@@ -1,100 +1,201 @@
-use std::fs;
-use ratatui::layout::{Constraint, Layout, Rect};
-use ratatui::style::{Color, Style};
-use ratatui::widgets::{Block, Borders, List, ListState};
+use std::path::PathBuf;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::layout::Rect;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState};
 use crate::action::Action;
+use crate::browser::{self, Entry, EntryKind, RecentRoms};
 use crate::components::Component;
+use crate::config::{Config, Styles};
+use crate::layout::Region;
+use crate::mode::Mode;
 use crate::tui::Frame;
 
+/// Browses the ROM directory tree. Typing filters the entries with a fuzzy search, <Backspace>
+/// on an empty search goes up a directory and <Tab> switches to the recently loaded ROMs
+#[derive(Default)]
 pub struct FileSelector {
     state: ListState,
-    filenames: Vec<String>,
+    directory: PathBuf,
+    extensions: Vec<String>,
+    entries: Vec<Entry>,
+    /// Indices into `entries` of the entries matching the search, in display order
+    matches: Vec<usize>,
+    query: String,
     selected_file: usize,
+    showing_recent: bool,
+    recent: RecentRoms,
+    recent_path: PathBuf,
+    error: Option<String>,
     is_focused: bool,
+    styles: Styles,
 }
 
 impl FileSelector {
-    pub fn new() -> Self {
-        Self {
-            state: ListState::default(),
-            filenames: vec![],
-            selected_file: 0,
-            is_focused: false,
+    pub fn new() -> Self { Self::default() }
+
+    /// Re-reads the entries of the current view and resets the search
+    fn refresh(&mut self) {
+        self.error = None;
+        self.entries = if self.showing_recent {
+            self.recent.entries()
+        } else {
+            browser::read_dir(&self.directory, &self.extensions).unwrap_or_else(|err| {
+                log::error!("Failed to read {}: {}", self.directory.display(), err);
+                self.error = Some(err.to_string());
+                vec![]
+            })
+        };
+        self.query.clear();
+        self.filter();
+    }
+
+    fn filter(&mut self) {
+        self.matches = browser::fuzzy_filter(&self.entries, &self.query);
+        self.selected_file = 0;
+    }
+
+    fn change_directory(&mut self, directory: PathBuf) {
+        self.directory = directory;
+        self.showing_recent = false;
+        self.refresh();
+    }
+
+    fn selected_entry(&self) -> Option<&Entry> {
+        self.matches.get(self.selected_file).map(|&i| &self.entries[i])
+    }
+
+    fn list_item(entry: &Entry, width: usize) -> ListItem<'static> {
+        match entry.kind {
+            EntryKind::Parent | EntryKind::Directory => ListItem::new(format!("{}/", entry.name)),
+            EntryKind::Rom { size } => {
+                let size = browser::format_size(size);
+                let padding = width.saturating_sub(entry.name.chars().count() + size.len()).max(1);
+                ListItem::new(Line::from(vec![
+                    Span::raw(entry.name.clone()),
+                    Span::raw(" ".repeat(padding)),
+                    Span::raw(size),
+                ]))
+            }
         }
     }
 }
 
 impl Component for FileSelector {
+    fn region(&self) -> Option<Region> { Some(Region::Files) }
+
+    fn register_config_handler(&mut self, config: Config) -> color_eyre::Result<()> {
+        self.directory = config.files.rom_root;
+        self.extensions = config.files.extensions;
+        self.recent_path = config.config._data_dir.join(browser::RECENT_FILE);
+        self.recent = RecentRoms::load(&self.recent_path).unwrap_or_else(|err| {
+            log::error!("Failed to read the recent ROMs: {}", err);
+            RecentRoms::default()
+        });
+        self.styles = config.styles;
+        self.refresh();
+        Ok(())
+    }
+
+    fn handle_key_events(&mut self, key: KeyEvent) -> color_eyre::Result<Option<Action>> {
+        if !self.is_focused || key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) {
+            return Ok(None)
+        }
+        match key.code {
+            KeyCode::Char(c) => {
+                self.query.push(c);
+                self.filter();
+            }
+            KeyCode::Backspace if self.query.is_empty() => {
+                if let Some(parent) = self.directory.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                    self.change_directory(parent.to_path_buf());
+                }
+            }
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.filter();
+            }
+            KeyCode::Tab => {
+                self.showing_recent = !self.showing_recent;
+                self.refresh();
+            }
+            _ => {}
+        }
+
+        Ok(None)
+    }
+
     fn update(&mut self, action: Action) -> color_eyre::Result<Option<Action>> {
         match action {
-            Action::MoveFileSelectorDown => { 
-                self.selected_file = if self.selected_file != 0 {
-                    self.selected_file - 1
-                } else {
-                    self.filenames.len() - 1
-                };
+            Action::MoveFileSelectorUp if !self.matches.is_empty() => {
+                self.selected_file = (self.selected_file + self.matches.len() - 1) % self.matches.len();
             },
-            
-            Action::MoveFileSelectorUp => { 
-                self.selected_file = if self.selected_file >= self.filenames.len() {
-                    0
-                } else {
-                    self.selected_file + 1
-                };
+
+            Action::MoveFileSelectorDown if !self.matches.is_empty() => {
+                self.selected_file = (self.selected_file + 1) % self.matches.len();
             },
-            
+
             Action::SelectFile => {
-                self.is_focused = false;
-                return Ok(Some(Action::LoadFile(self.filenames[self.selected_file].clone())))
+                let Some(entry) = self.selected_entry().cloned() else { return Ok(None) };
+                match entry.kind {
+                    EntryKind::Parent | EntryKind::Directory => self.change_directory(entry.path),
+                    EntryKind::Rom { .. } => {
+                        self.is_focused = false;
+                        return Ok(Some(Action::LoadFile(entry.path.display().to_string())))
+                    }
+                }
             }
-            
-            Action::FocusFileSelector => self.is_focused = true,
-            
+
+            Action::FocusFileSelector => {
+                self.is_focused = true;
+                self.refresh();
+            },
+
+            Action::CloseFileSelector => self.is_focused = false,
+
+            // Typing into the help overlay doesn't search
+            Action::ModeChanged(mode) => self.is_focused = mode == Mode::SelectingFile,
+
+            Action::LoadFile(path) => {
+                self.recent.push(PathBuf::from(path));
+                if let Err(err) = self.recent.save(&self.recent_path) {
+                    log::error!("Failed to save the recent ROMs: {}", err);
+                }
+            }
+
             _ => {}
         }
-        
+
         Ok(None)
     }
     fn draw(&mut self, f: &mut Frame<'_>, area: Rect) -> color_eyre::Result<()> {
-        let chunks_h = Layout::horizontal(
-            vec![
-                Constraint::Length(130),
-                Constraint::Fill(1),
-                Constraint::Length(16),
-            ]
-        ).split(area);
-
-        let chunks_v = Layout::vertical(
-            vec![
-                Constraint::Fill(1),
-                Constraint::Length(3),
-            ]
-        ).split(chunks_h[1]);
-        
-        self.filenames.clear();
-        fs::read_dir("./scripts/").unwrap().for_each(
-            |x| {
-                if let Ok(entry) = x {
-                    if entry.metadata().unwrap().is_file() {
-                        self.filenames.push(
-                            entry.file_name().into_string().unwrap()
-                        )
-                    }
-                }
-            }
-        );
+        let mut title = if self.showing_recent {
+            "Recent ROMs".to_string()
+        } else {
+            format!("ROMs: {}", self.directory.display())
+        };
+        if !self.query.is_empty() {
+            title = format!("{} [{}]", title, self.query);
+        }
+
+        // Inside the borders and the highlight symbol
+        let width = area.width.saturating_sub(4) as usize;
+        let items: Vec<ListItem> = match &self.error {
+            Some(err) => vec![ListItem::new(err.clone())],
+            None => self.matches.iter().map(|&i| Self::list_item(&self.entries[i], width)).collect(),
+        };
 
-        let list = List::new(self.filenames.clone())
-            .block(Block::default().title("Scripts").borders(Borders::ALL).border_style(
-                Style::default().fg(if self.is_focused { Color::Cyan } else { Color::White })
+        let list = List::new(items)
+            .block(Block::default().title(title).borders(Borders::ALL).border_style(
+                self.styles.get_style(Mode::SelectingFile, if self.is_focused { "border_focused" } else { "border" })
             ))
             .highlight_symbol(">>")
-            .highlight_style(Style::default().fg(Color::LightBlue));
-        
-        self.state.select(Some(self.selected_file));
+            .highlight_style(self.styles.get_style(Mode::SelectingFile, "highlight"));
+
+        self.state.select(if self.matches.is_empty() { None } else { Some(self.selected_file) });
+
+        f.render_stateful_widget(list, area, &mut self.state);
 
-        f.render_stateful_widget(list, chunks_v[0], &mut self.state);
-        
         Ok(())
     }
-}
\ No newline at end of file
+}
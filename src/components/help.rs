@@ -0,0 +1,107 @@
+use itertools::Itertools;
+use ratatui::layout::{Margin, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+use crate::action::Action;
+use crate::components::Component;
+use crate::config::{Config, KeyBindings, Styles};
+use crate::keypad::LAYOUT;
+use crate::mode::Mode;
+use crate::tui::Frame;
+
+/// Short hint naming the key of `mode` that opens the help, or closes it in the help itself
+pub fn short_hint(keybindings: &KeyBindings, mode: Mode) -> String {
+    let keys = keybindings.for_mode(mode).into_iter().find(|(_, action)| *action == Action::Help);
+    match (keys, mode) {
+        (Some((keys, _)), Mode::Help) => format!("{} to close the help", keys),
+        (Some((keys, _)), _) => format!("{} for help", keys),
+        (None, _) => String::new(),
+    }
+}
+
+/// Modal overlay listing the keybindings of the mode it was opened from and the keypad map
+#[derive(Default)]
+pub struct Help {
+    keybindings: KeyBindings,
+    keypad_labels: Vec<String>,
+    /// Mode whose bindings are listed
+    mode: Mode,
+    is_visible: bool,
+    scroll: u16,
+    styles: Styles,
+}
+
+impl Help {
+    pub fn new() -> Self { Self::default() }
+
+    fn lines(&self) -> Vec<Line<'static>> {
+        let heading = Style::default().add_modifier(Modifier::BOLD);
+        let mut lines = vec![Line::styled(format!("Keybindings ({:?})", self.mode), heading)];
+        let bindings = self.keybindings.for_mode(self.mode);
+        let width = bindings.iter().map(|(keys, _)| keys.len()).max().unwrap_or(0);
+        lines.extend(bindings.into_iter().map(|(keys, action)| {
+            Line::from(vec![Span::raw(format!("  {:<width$}  ", keys)), Span::raw(action.description())])
+        }));
+
+        if !self.keypad_labels.is_empty() {
+            lines.push(Line::from(""));
+            lines.push(Line::styled("Keypad", heading));
+            for row in LAYOUT.chunks(4) {
+                let cells = row.iter()
+                    .map(|key| format!("{:X} {:<10}", key, self.keypad_labels.get(*key as usize).map_or("", |label| label)))
+                    .join(" ");
+                lines.push(Line::from(format!("  {}", cells)));
+            }
+        }
+        lines
+    }
+}
+
+impl Component for Help {
+    fn register_config_handler(&mut self, config: Config) -> color_eyre::Result<()> {
+        self.keybindings = config.keybindings;
+        self.styles = config.styles;
+        Ok(())
+    }
+
+    fn update(&mut self, action: Action) -> color_eyre::Result<Option<Action>> {
+        match action {
+            Action::ModeChanged(Mode::Help) => {
+                self.is_visible = true;
+                self.scroll = 0;
+            }
+            Action::ModeChanged(mode) => {
+                self.is_visible = false;
+                self.mode = mode;
+            }
+            Action::SetKeypadLabels(labels) => { self.keypad_labels = labels }
+            Action::ScrollHelpUp => { self.scroll = self.scroll.saturating_sub(1) }
+            Action::ScrollHelpDown => { self.scroll = self.scroll.saturating_add(1) }
+
+            _ => {}
+        }
+
+        Ok(None)
+    }
+
+    fn draw(&mut self, f: &mut Frame<'_>, area: Rect) -> color_eyre::Result<()> {
+        if !self.is_visible {
+            return Ok(())
+        }
+
+        let lines = self.lines();
+        let popup = area.inner(&Margin { horizontal: 2, vertical: 1 });
+        let max_scroll = (lines.len() as u16).saturating_sub(popup.height.saturating_sub(2));
+        self.scroll = self.scroll.min(max_scroll);
+
+        let paragraph = Paragraph::new(lines)
+            .scroll((self.scroll, 0))
+            .block(Block::default().title("Help").borders(Borders::ALL)
+                .border_style(self.styles.get_style(Mode::Help, "border_focused")));
+        f.render_widget(Clear, popup);
+        f.render_widget(paragraph, popup);
+
+        Ok(())
+    }
+}
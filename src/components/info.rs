@@ -0,0 +1,80 @@
+use itertools::Itertools;
+use ratatui::layout::Rect;
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Paragraph, Wrap};
+use crate::action::Action;
+use crate::components::Component;
+use crate::config::{Config, Styles};
+use crate::layout::Region;
+use crate::mode::Mode;
+use crate::romdb::RomMetadata;
+use crate::tui::Frame;
+
+/// What the ROM database knows about the loaded ROM
+#[derive(Default)]
+pub struct RomInfo {
+    is_loaded: bool,
+    metadata: Option<RomMetadata>,
+    styles: Styles,
+}
+
+impl RomInfo {
+    pub fn new() -> Self { Self::default() }
+
+    fn lines(metadata: &RomMetadata) -> Vec<Line<'static>> {
+        let mut lines = vec![Line::styled(metadata.title.clone(), Style::default().add_modifier(Modifier::BOLD))];
+        let mut credits = metadata.authors.join(", ");
+        if let Some(release) = &metadata.release {
+            credits = if credits.is_empty() { release.clone() } else { format!("{} ({})", credits, release) };
+        }
+        if !credits.is_empty() {
+            lines.push(Line::from(credits));
+        }
+        if let Some(platform) = &metadata.platform {
+            lines.push(Line::from(format!("Platform: {}", platform)));
+        }
+        if !metadata.keys.is_empty() {
+            let keys = metadata.keys.iter().map(|(name, key)| format!("{} {:X}", name, key)).join(", ");
+            lines.push(Line::from(format!("Keys: {}", keys)));
+        }
+        if let Some(description) = &metadata.description {
+            lines.push(Line::from(description.clone()));
+        }
+        lines
+    }
+}
+
+impl Component for RomInfo {
+    fn region(&self) -> Option<Region> { Some(Region::Info) }
+
+    fn register_config_handler(&mut self, config: Config) -> color_eyre::Result<()> {
+        self.styles = config.styles;
+        Ok(())
+    }
+
+    fn update(&mut self, action: Action) -> color_eyre::Result<Option<Action>> {
+        if let Action::SetRomInfo(metadata) = action {
+            self.is_loaded = true;
+            self.metadata = metadata;
+        }
+
+        Ok(None)
+    }
+
+    fn draw(&mut self, f: &mut Frame<'_>, area: Rect) -> color_eyre::Result<()> {
+        let text = match &self.metadata {
+            Some(metadata) => Self::lines(metadata),
+            None if self.is_loaded => vec![Line::from("Unknown ROM")],
+            None => vec![],
+        };
+
+        let paragraph = Paragraph::new(text)
+            .wrap(Wrap { trim: true })
+            .block(Block::default().title("ROM").borders(Borders::ALL)
+                .border_style(self.styles.get_style(Mode::Home, "border")));
+        f.render_widget(paragraph, area);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
+use ratatui::layout::{Alignment, Constraint, Layout, Position, Rect};
+use ratatui::style::Style;
+use ratatui::widgets::{Block, Borders, Paragraph};
+use tokio::sync::mpsc::UnboundedSender;
+use crate::action::Action;
+use crate::components::Component;
+use crate::config::{Config, Styles};
+use crate::keypad::LAYOUT;
+use crate::layout::Region;
+use crate::mode::Mode;
+use crate::tui::Frame;
+
+/// On-screen COSMAC VIP hex keypad. Shows the keys held by the emulator and can be played with
+/// the mouse: a key is held for as long as the left button is
+#[derive(Default)]
+pub struct Keypad {
+    action_tx: Option<UnboundedSender<Action>>,
+    held_keys: Vec<bool>,
+    key_areas: Vec<(Rect, u8)>,
+    mouse_key: Option<u8>,
+    styles: Styles,
+}
+
+impl Keypad {
+    pub fn new() -> Self { Self::default() }
+
+    fn key_at(&self, column: u16, row: u16) -> Option<u8> {
+        self.key_areas.iter()
+            .find(|(area, _)| area.contains(Position { x: column, y: row }))
+            .map(|(_, key)| *key)
+    }
+
+    fn send(&self, action: Action) -> color_eyre::Result<()> {
+        if let Some(tx) = &self.action_tx {
+            tx.send(action)?;
+        }
+        Ok(())
+    }
+
+    /// Moves the mouse press to `key`, releasing the previously pressed key
+    fn move_mouse_press(&mut self, key: Option<u8>) -> color_eyre::Result<()> {
+        if key == self.mouse_key {
+            return Ok(())
+        }
+        if let Some(previous) = self.mouse_key {
+            self.send(Action::ReleaseKey(previous))?;
+        }
+        if let Some(key) = key {
+            self.send(Action::PressKey(key))?;
+        }
+        self.mouse_key = key;
+        Ok(())
+    }
+}
+
+impl Component for Keypad {
+    fn region(&self) -> Option<Region> { Some(Region::Keypad) }
+
+    fn register_config_handler(&mut self, config: Config) -> color_eyre::Result<()> {
+        self.styles = config.styles;
+        Ok(())
+    }
+
+    fn register_action_handler(&mut self, tx: UnboundedSender<Action>) -> color_eyre::Result<()> {
+        self.action_tx = Some(tx);
+        Ok(())
+    }
+
+    fn handle_mouse_events(&mut self, mouse: MouseEvent) -> color_eyre::Result<Option<Action>> {
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                let key = self.key_at(mouse.column, mouse.row);
+                self.move_mouse_press(key)?;
+            }
+            MouseEventKind::Drag(MouseButton::Left) if self.mouse_key.is_some() => {
+                let key = self.key_at(mouse.column, mouse.row);
+                self.move_mouse_press(key)?;
+            }
+            MouseEventKind::Up(MouseButton::Left) => {
+                self.move_mouse_press(None)?;
+            }
+            _ => {}
+        }
+
+        Ok(None)
+    }
+
+    fn update(&mut self, action: Action) -> color_eyre::Result<Option<Action>> {
+        if let Action::UpdateKeys(keys) = action {
+            self.held_keys = keys;
+        }
+
+        Ok(None)
+    }
+
+    fn draw(&mut self, f: &mut Frame<'_>, area: Rect) -> color_eyre::Result<()> {
+        let border_style = self.styles.get_style(Mode::Home, "border");
+        let block = Block::default().title("Keypad").borders(Borders::ALL).border_style(border_style);
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+
+        let rows = Layout::vertical(vec![Constraint::Length(3); 4]).split(inner);
+        self.key_areas.clear();
+        for (row_index, row) in rows.iter().enumerate() {
+            let cells = Layout::horizontal(vec![Constraint::Length(5); 4]).split(*row);
+            for (column_index, cell) in cells.iter().enumerate() {
+                let key = LAYOUT[row_index * 4 + column_index];
+                let is_held = self.held_keys.get(key as usize).copied().unwrap_or(false);
+                let style = if is_held {
+                    self.styles.get_style(Mode::Home, "key_held")
+                } else {
+                    Style::default()
+                };
+                let widget = Paragraph::new(format!("{:X}", key))
+                    .alignment(Alignment::Center)
+                    .style(style)
+                    .block(Block::default().borders(Borders::ALL).border_style(border_style));
+                f.render_widget(widget, *cell);
+                self.key_areas.push((*cell, key));
+            }
+        }
+
+        Ok(())
+    }
+}
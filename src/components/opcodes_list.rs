@@ -1,27 +1,100 @@
-use ratatui::layout::{Constraint, Layout, Rect};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use ratatui::layout::Rect;
 use ratatui::style::{Color, Style};
-use ratatui::widgets::{Block, Borders, List, ListDirection, ListState};
+use ratatui::widgets::{Block, Borders, List, ListDirection, ListItem, ListState};
 use crate::action::Action;
 use crate::components::Component;
+use crate::config::{Config, Styles};
+use crate::isa::{Instruction, InstructionSet};
+use crate::layout::Region;
+use crate::mode::Mode;
+use crate::symbols::SymbolMap;
 use crate::tui::Frame;
 
 #[derive(Default)]
 pub struct  OpcodesList {
     state: ListState,
     opcodes: Vec<u16>,
+    /// Labels of the assembled ROM, by address
+    labels: BTreeMap<u16, String>,
+    /// Trimmed source line of each address that starts one
+    source: HashMap<u16, String>,
     current_opcode: u16,
+    /// Executions of every address of memory, when the heat map is shown
+    heat_map: Option<Vec<u64>>,
+    /// Whether every address of memory was executed, when the coverage is shown
+    coverage: Option<Vec<bool>>,
+    styles: Styles,
 }
 
+/// Backgrounds of the heat map, from the least to the most executed instructions
+const HEAT_COLORS: [Color; 5] = [Color::Blue, Color::Cyan, Color::Green, Color::Yellow, Color::Red];
+
 impl OpcodesList {
     pub fn new() -> Self { Self::default() }
+
+    /// Reads the source files named by `symbols`. Files that can't be read are left out
+    fn read_source(symbols: &SymbolMap) -> HashMap<u16, String> {
+        let mut files: HashMap<&str, Option<Vec<String>>> = HashMap::new();
+        let mut source = HashMap::new();
+        for line in &symbols.lines {
+            let text = files.entry(line.file.as_str()).or_insert_with(|| {
+                fs::read_to_string(&line.file).ok().map(|text| text.lines().map(str::to_string).collect())
+            });
+            if let Some(text) = text.as_ref().zip(line.line.checked_sub(1)).and_then(|(text, index)| text.get(index)) {
+                source.entry(line.address).or_insert_with(|| text.trim().to_string());
+            }
+        }
+        source
+    }
+
+    /// Style of a row executed `count` times, on a logarithmic scale up to `max`
+    fn heat_style(count: u64, max: u64) -> Style {
+        if count == 0 {
+            return Style::default()
+        }
+        let level = (count as f64).ln_1p() / (max as f64).ln_1p() * HEAT_COLORS.len() as f64;
+        let color = HEAT_COLORS[(level.ceil() as usize).clamp(1, HEAT_COLORS.len()) - 1];
+        Style::default().fg(Color::Black).bg(color)
+    }
 }
 
 impl Component for OpcodesList {
+    fn region(&self) -> Option<Region> { Some(Region::Disassembly) }
+
+    fn register_config_handler(&mut self, config: Config) -> color_eyre::Result<()> {
+        self.styles = config.styles;
+        Ok(())
+    }
+
     fn update(&mut self, action: Action) -> color_eyre::Result<Option<Action>> {
         match action {
             Action::LoadOpcodesList(data) => {
                 self.opcodes = data;
             }
+            Action::SetSymbols(symbols) => {
+                self.labels = symbols.as_ref().map(SymbolMap::labels_by_address).unwrap_or_default();
+                self.source = symbols.as_ref().map(Self::read_source).unwrap_or_default();
+            }
+            Action::ToggleHeatMap => {
+                self.heat_map = match self.heat_map {
+                    Some(_) => None,
+                    None => Some(vec![]),
+                };
+            }
+            Action::UpdateHeatMap(executions) if self.heat_map.is_some() => {
+                self.heat_map = Some(executions);
+            }
+            Action::ToggleCoverage => {
+                self.coverage = match self.coverage {
+                    Some(_) => None,
+                    None => Some(vec![]),
+                };
+            }
+            Action::UpdateCoverage(executed) if self.coverage.is_some() => {
+                self.coverage = Some(executed);
+            }
             Action::SelectOpcode(i) => {
                 self.state.select(Some(i as usize))
             }
@@ -32,28 +105,45 @@ impl Component for OpcodesList {
         Ok(None)
     }
     fn draw(&mut self, f: &mut Frame<'_>, area: Rect) -> color_eyre::Result<()> {
-        let v_chunks = Layout::vertical(
-            vec![
-                Constraint::Fill(1),
-                Constraint::Length(3),
-            ]
-        ).split(area);
-
-        let h_chunks = Layout::horizontal(
-            vec![
-                Constraint::Fill(1),
-                Constraint::Length(16),
-            ]
-        ).split(v_chunks[0]);
-
-        let list = List::new(self.opcodes.iter().map(|x| {format!("0x{:0>4X}", x)}))
-            .block(Block::default().title("Program").borders(Borders::ALL))
+        let heat_map = self.heat_map.as_deref();
+        let coverage = self.coverage.as_deref();
+        let title = match (heat_map, coverage) {
+            (Some(_), _) => "Heat map",
+            (None, Some(_)) => "Coverage",
+            (None, None) => "Program",
+        };
+        let max = heat_map.and_then(|executions| executions.iter().max().copied()).unwrap_or(0);
+        let list = List::new(self.opcodes.iter().enumerate().map(|(i, x)| {
+            let address = 0x200 + 2 * i as u16;
+            // Every instruction set, as the list doesn't know the quirks of the ROM
+            let instruction = Instruction::decode(*x, self.opcodes.get(i + 1).copied(), InstructionSet::XoChip);
+            let mut row = match instruction {
+                Some(instruction) => format!("0x{:0>4X} {}", x, instruction),
+                None => format!("0x{:0>4X}", x),
+            };
+            if let Some(label) = self.labels.get(&address) {
+                row = format!("{} {}:", row, label);
+            }
+            if let Some(source) = self.source.get(&address) {
+                row = format!("{} | {}", row, source);
+            }
+            let count = heat_map.and_then(|executions| executions.get(address as usize)).copied().unwrap_or(0);
+            let executed = coverage.and_then(|executed| executed.get(address as usize)).copied().unwrap_or(true);
+            let style = match (heat_map, executed) {
+                (Some(_), _) => Self::heat_style(count, max),
+                (None, false) => Style::default().fg(Color::DarkGray),
+                (None, true) => Style::default(),
+            };
+            ListItem::new(row).style(style)
+        }))
+            .block(Block::default().title(title).borders(Borders::ALL)
+                .border_style(self.styles.get_style(Mode::Home, "border")))
             .style(Style::default())
-            .highlight_style(Style::default().fg(Color::LightBlue))
+            .highlight_style(self.styles.get_style(Mode::Home, "highlight"))
             .highlight_symbol(">>")
             .direction(ListDirection::TopToBottom);
 
-        f.render_stateful_widget(list, h_chunks[1], &mut self.state);
+        f.render_stateful_widget(list, area, &mut self.state);
 
         Ok(())
     }
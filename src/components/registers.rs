@@ -0,0 +1,63 @@
+use ratatui::layout::Rect;
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Paragraph, Wrap};
+use crate::action::Action;
+use crate::components::Component;
+use crate::config::{Config, Styles};
+use crate::emulator::CpuState;
+use crate::layout::Region;
+use crate::mode::Mode;
+use crate::tui::Frame;
+
+#[derive(Default)]
+pub struct Registers {
+    state: CpuState,
+    styles: Styles,
+}
+
+impl Registers {
+    pub fn new() -> Self { Self::default() }
+}
+
+impl Component for Registers {
+    fn region(&self) -> Option<Region> { Some(Region::Registers) }
+
+    fn register_config_handler(&mut self, config: Config) -> color_eyre::Result<()> {
+        self.styles = config.styles;
+        Ok(())
+    }
+
+    fn update(&mut self, action: Action) -> color_eyre::Result<Option<Action>> {
+        if let Action::UpdateCpuState(state) = action {
+            self.state = state;
+        }
+
+        Ok(None)
+    }
+
+    fn draw(&mut self, f: &mut Frame<'_>, area: Rect) -> color_eyre::Result<()> {
+        let registers = self.state.registers.iter().enumerate()
+            .map(|(i, value)| format!("V{:X}:{:0>2X}", i, value))
+            .collect::<Vec<String>>()
+            .join(" ");
+        let text = vec![
+            Line::from(registers),
+            Line::from(format!(
+                "PC:{:0>3X} I:{:0>3X} SP:{:X} DT:{:0>2X} ST:{:0>2X}",
+                self.state.program_counter,
+                self.state.index_register,
+                self.state.stack_pointer,
+                self.state.delay_timer,
+                self.state.sound_timer,
+            )),
+        ];
+
+        let paragraph = Paragraph::new(text)
+            .wrap(Wrap { trim: true })
+            .block(Block::default().title("Registers").borders(Borders::ALL)
+                .border_style(self.styles.get_style(Mode::Home, "border")));
+        f.render_widget(paragraph, area);
+
+        Ok(())
+    }
+}
@@ -1,64 +1,47 @@
-use std::ops::Div;
-use ratatui::layout::{Constraint, Layout, Rect};
-use ratatui::prelude::Text;
-use ratatui::style::{Color, Style};
+use ratatui::layout::Rect;
 use ratatui::text::Line;
 use ratatui::widgets::{Block, Borders, Paragraph};
-use tokio::sync::mpsc::UnboundedSender;
-use tracing_subscriber::fmt::format;
 use crate::action::Action;
 use crate::components::Component;
+use crate::config::{Config, Styles};
+use crate::layout::Region;
+use crate::mode::Mode;
+use crate::palette::Palette;
+use crate::persistence::PhosphorFilter;
+use crate::renderer::{Framebuffer, RendererKind};
 use crate::tui::Frame;
 
+#[derive(Default)]
 pub struct Screen {
-    screen_data: Vec<String>,
+    filter: PhosphorFilter,
+    renderer: RendererKind,
+    palette: Palette,
+    styles: Styles,
     is_running: bool,
 }
 
 impl Screen {
-    pub fn new() -> Self { Self {screen_data: Vec::new(), is_running: false } }
+    pub fn new() -> Self { Self::default() }
 }
 
 impl Component for Screen {
+    fn region(&self) -> Option<Region> { Some(Region::Screen) }
+
+    fn register_config_handler(&mut self, config: Config) -> color_eyre::Result<()> {
+        self.renderer = config.display.renderer;
+        self.palette = config.display.palette;
+        self.filter = PhosphorFilter::new(config.display.persistence);
+        self.styles = config.styles;
+        Ok(())
+    }
+
     fn update(&mut self, action: Action) -> color_eyre::Result<Option<Action>> {
         match action {
-            Action::Redraw(data) => {
-                self.screen_data = Vec::new();
-                for row in 0..32 {
-                    let mut row_data = String::new();
-                    for column in 0..8 {
-                        let mut byte = data[row * 8 + column];
-                        let mut byte_data = String::new();
-                        let leading_space =  String::from(' ').repeat(
-                            (byte.leading_zeros() * 2) as usize
-                        );
-                        while byte > 0 {
-                            byte_data.insert(
-                                0,
-                                match byte % 2 {
-                                    0 => { ' ' }
-                                    1 => { '█' }
-                                    _ => unreachable!()
-                                }
-                            );
-                            byte_data.insert(
-                                0,
-                                match byte % 2 {
-                                    0 => { ' ' }
-                                    1 => { '█' }
-                                    _ => unreachable!()
-                                }
-                            );
-                            byte = byte.div(2);
-                        }
-                        byte_data = leading_space + byte_data.as_str();
-                        row_data += byte_data.as_str();
-                    }
-                    self.screen_data.push(row_data);
-                }
-            }
+            Action::Redraw(data) => { self.filter.push(Framebuffer::from_data(data)) }
             Action::StartEmulation => { self.is_running = true }
             Action::StopEmulation => { self.is_running = false }
+            Action::SetPalette(palette) => { self.palette = palette }
+            Action::SetPersistence(persistence) => { self.filter.set_persistence(persistence) }
             
             _ => {}
         }
@@ -66,39 +49,22 @@ impl Component for Screen {
         Ok(None)
     }
     fn draw(&mut self, f: &mut Frame<'_>, area: Rect) -> color_eyre::Result<()> {
-        let chunks_h = Layout::horizontal(
-            vec![
-                Constraint::Length(130),
-                Constraint::Min(3),
-            ]
-        ).split(area);
-
-        let chunks_v = Layout::vertical(
-            vec![
-                Constraint::Length(34),
-                Constraint::Min(3),
-            ]
-        ).split(chunks_h[0]);
+        let block = Block::default().title("Screen").borders(Borders::ALL).border_style(
+            self.styles.get_style(Mode::Home, if self.is_running { "border_running" } else { "border" })
+        );
+        let inner = block.inner(area);
+        f.render_widget(block, area);
 
         let mut text = Vec::<Line>::new();
-        
-        self.screen_data.iter().for_each(
-            |line| {
-                text.push(Line::from(line.clone()))
-            }
-        );
+        if let Some(frame) = self.filter.output() {
+            let renderer = self.renderer.resolve(frame.width, frame.height, inner.width, inner.height);
+            text = renderer.render(&frame, self.palette);
+        }
 
-        let screen = Paragraph::new(text)
-            .block(
-                Block::default().title("Screen").borders(Borders::ALL).border_style(
-                    Style::default().fg(
-                        if self.is_running { Color::LightCyan } else { Color::White }
-                    )
-                )
-            );
+        let screen = Paragraph::new(text).style(self.palette.style());
         
-        f.render_widget(screen, chunks_v[0]);
+        f.render_widget(screen, inner);
 
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,131 @@
+use crossterm::event::{MouseEvent, MouseEventKind};
+use ratatui::layout::{Constraint, Layout, Position, Rect};
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph};
+use crate::action::Action;
+use crate::components::Component;
+use crate::config::{Config, Styles};
+use crate::layout::Region;
+use crate::mode::Mode;
+use crate::sprites::Sprite;
+use crate::tui::Frame;
+
+/// Width of the list of recent sprites
+const RECENT_WIDTH: u16 = 14;
+
+/// Memory drawn as sprites from an address, by default the one in I, next to the sprites the
+/// program drew last
+#[derive(Default)]
+pub struct SpriteViewer {
+    memory: Vec<u8>,
+    index_register: u16,
+    /// None to follow I
+    address: Option<u16>,
+    /// Whether rows are 16 pixels wide, as in the 16x16 sprites of SCHIP
+    wide: bool,
+    recent: Vec<Sprite>,
+    area: Rect,
+    styles: Styles,
+}
+
+impl SpriteViewer {
+    pub fn new() -> Self { Self::default() }
+
+    fn address(&self) -> u16 {
+        self.address.unwrap_or(self.index_register)
+    }
+
+    fn row_size(&self) -> u16 {
+        if self.wide { 2 } else { 1 }
+    }
+
+    fn scroll_up(&mut self) {
+        self.address = Some(self.address().saturating_sub(self.row_size()));
+    }
+
+    fn scroll_down(&mut self) {
+        let last = (self.memory.len() as u16).saturating_sub(self.row_size());
+        self.address = Some((self.address() + self.row_size()).min(last));
+    }
+
+    fn memory_lines(&self, rows: usize) -> Vec<Line<'static>> {
+        let on = Style::default().fg(Color::White);
+        let off = Style::default().fg(Color::DarkGray);
+        let width = if self.wide { 16 } else { 8 };
+        (0..rows)
+            .map(|row| self.address() as usize + row * self.row_size() as usize)
+            .take_while(|address| *address < self.memory.len())
+            .map(|address| {
+                let sprite = Sprite { address: address as u16, height: 1, wide: self.wide };
+                let bits = sprite.rows(&self.memory)[0];
+                let mut spans = vec![Span::raw(format!("{:03X} ", address))];
+                spans.extend((0..width).map(|x| match bits & (0x8000 >> x) != 0 {
+                    true => Span::styled("██", on),
+                    false => Span::styled("░░", off),
+                }));
+                Line::from(spans)
+            })
+            .collect()
+    }
+}
+
+impl Component for SpriteViewer {
+    fn region(&self) -> Option<Region> { Some(Region::Sprites) }
+
+    fn register_config_handler(&mut self, config: Config) -> color_eyre::Result<()> {
+        self.styles = config.styles;
+        Ok(())
+    }
+
+    fn handle_mouse_events(&mut self, mouse: MouseEvent) -> color_eyre::Result<Option<Action>> {
+        if !self.area.contains(Position { x: mouse.column, y: mouse.row }) {
+            return Ok(None)
+        }
+        match mouse.kind {
+            MouseEventKind::ScrollUp => self.scroll_up(),
+            MouseEventKind::ScrollDown => self.scroll_down(),
+            _ => {}
+        }
+
+        Ok(None)
+    }
+
+    fn update(&mut self, action: Action) -> color_eyre::Result<Option<Action>> {
+        match action {
+            Action::UpdateMemory(memory) => self.memory = memory,
+            Action::UpdateSprites(recent) => self.recent = recent,
+            Action::UpdateCpuState(state) => self.index_register = state.index_register,
+            Action::SpriteViewerUp => self.scroll_up(),
+            Action::SpriteViewerDown => self.scroll_down(),
+            Action::ToggleSpriteWidth => self.wide = !self.wide,
+            Action::FollowIndexRegister => self.address = None,
+
+            _ => {}
+        }
+
+        Ok(None)
+    }
+
+    fn draw(&mut self, f: &mut Frame<'_>, area: Rect) -> color_eyre::Result<()> {
+        self.area = area;
+        let follow = if self.address.is_none() { " (I)" } else { "" };
+        let block = Block::default()
+            .title(format!("Sprites 0x{:03X}{}", self.address(), follow))
+            .borders(Borders::ALL)
+            .border_style(self.styles.get_style(Mode::Home, "border"));
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+
+        let [memory, recent] = Layout::horizontal([Constraint::Fill(1), Constraint::Length(RECENT_WIDTH)]).areas(inner);
+        f.render_widget(Paragraph::new(self.memory_lines(memory.height as usize)), memory);
+
+        let mut lines = vec![Line::styled("Recent", Style::default().fg(Color::DarkGray))];
+        lines.extend(self.recent.iter().map(|sprite| {
+            Line::raw(format!("{:03X} {}x{}", sprite.address, sprite.width(), sprite.height))
+        }));
+        f.render_widget(Paragraph::new(lines), recent);
+
+        Ok(())
+    }
+}
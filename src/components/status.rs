@@ -1,41 +1,52 @@
-use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::layout::Rect;
 use ratatui::widgets::{Block, Borders, Paragraph};
 use crate::action::Action;
 use crate::components::Component;
+use crate::components::help::short_hint;
+use crate::config::{Config, KeyBindings, Styles};
+use crate::layout::Region;
+use crate::mode::Mode;
 use crate::tui::Frame;
 
+#[derive(Default)]
 pub struct StatusBar {
     opcode: u16,
+    mode: Mode,
+    keybindings: KeyBindings,
+    styles: Styles,
 }
 
 impl StatusBar {
-    pub fn new() -> Self { Self {opcode: 0x0000} }
+    pub fn new() -> Self { Self::default() }
 }
 
 impl Component for StatusBar {
+    fn region(&self) -> Option<Region> { Some(Region::Status) }
+
+    fn register_config_handler(&mut self, config: Config) -> color_eyre::Result<()> {
+        self.keybindings = config.keybindings;
+        self.styles = config.styles;
+        Ok(())
+    }
+
     fn update(&mut self, action: Action) -> color_eyre::Result<Option<Action>> {
-        if let Action::UpdateOpcode(opcode) = action {
-            self.opcode = opcode;
+        match action {
+            Action::UpdateOpcode(opcode) => { self.opcode = opcode }
+            Action::ModeChanged(mode) => { self.mode = mode }
+
+            _ => {}
         }
         
         Ok(None)
     }
     
     fn draw(&mut self, f: &mut Frame<'_>, area: Rect) -> color_eyre::Result<()> {
-        let chunks_v = Layout::vertical(
-            vec![
-                Constraint::Fill(1),
-                Constraint::Length(3),
-            ]
-        ).split(area);
-
         let status = Paragraph::new(format!(
-            "Press <Ctrl-O> to focus file selector, <Enter> to load selected script, <Ctrl-R> to run loaded script, <Ctrl-H> to pause running script | Current opcode: 0x{:X}",
-            self.opcode)
-        )
-            .block(Block::default().borders(Borders::ALL));
+            "Opcode: 0x{:0>4X} | {}", self.opcode, short_hint(&self.keybindings, self.mode)
+        ))
+            .block(Block::default().borders(Borders::ALL).border_style(self.styles.get_style(Mode::Home, "border")));
         
-        f.render_widget(status, chunks_v[1]);
+        f.render_widget(status, area);
 
         Ok(())
     }
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fmt, path::PathBuf};
+use std::{collections::HashMap, fmt, path::PathBuf, time::Duration};
 
 use color_eyre::eyre::Result;
 use config::Value;
@@ -11,7 +11,11 @@ use serde::{
 };
 use serde_json::Value as JsonValue;
 
-use crate::{action::Action, mode::Mode};
+use crate::{
+  action::Action, browser::ROM_EXTENSIONS, keypad::KeypadConfig, mode::Mode, octo::OCTO_EXTENSION, palette::Palette,
+  persistence::Persistence, random::RandomKind,
+  renderer::RendererKind,
+};
 
 const CONFIG: &str = include_str!("../.config/config.json5");
 
@@ -23,11 +27,123 @@ pub struct AppConfig {
   pub _config_dir: PathBuf,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub struct EmulatorConfig {
+  /// Seed of the CXNN random source. A random seed is picked when unset
+  #[serde(default)]
+  pub seed: Option<u64>,
+  #[serde(default)]
+  pub random: RandomKind,
+  /// Milliseconds after which a keypad key is released on terminals that don't report releases
+  #[serde(default = "default_key_release_timeout")]
+  pub key_release_timeout: u64,
+  /// Per-key overrides of `key_release_timeout`, keyed by the hex digit of the keypad key
+  #[serde(default)]
+  pub key_release_timeouts: HashMap<String, u64>,
+  /// Pause the emulation and show the error when an instruction fails
+  #[serde(default = "default_pause_on_error")]
+  pub pause_on_error: bool,
+  /// Reload the loaded ROM whenever its file changes
+  #[serde(default)]
+  pub watch_rom: bool,
+}
+
+fn default_pause_on_error() -> bool {
+  true
+}
+
+fn default_key_release_timeout() -> u64 {
+  // Long enough to be bridged by the terminal's key repeat
+  600
+}
+
+impl Default for EmulatorConfig {
+  fn default() -> Self {
+    Self {
+      seed: None,
+      random: RandomKind::default(),
+      key_release_timeout: default_key_release_timeout(),
+      key_release_timeouts: HashMap::new(),
+      pause_on_error: default_pause_on_error(),
+      watch_rom: false,
+    }
+  }
+}
+
+impl EmulatorConfig {
+  pub fn key_release_timeout(&self, key: u8) -> Duration {
+    let timeout = self
+      .key_release_timeouts
+      .iter()
+      .find(|(digit, _)| u8::from_str_radix(digit, 16) == Ok(key))
+      .map_or(self.key_release_timeout, |(_, timeout)| *timeout);
+    Duration::from_millis(timeout)
+  }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct FilesConfig {
+  /// Directory the file browser starts in
+  #[serde(default = "default_rom_root")]
+  pub rom_root: PathBuf,
+  /// Extensions of the files listed by the browser
+  #[serde(default = "default_extensions")]
+  pub extensions: Vec<String>,
+}
+
+fn default_rom_root() -> PathBuf {
+  PathBuf::from("./scripts")
+}
+
+fn default_extensions() -> Vec<String> {
+  ROM_EXTENSIONS.iter().chain([OCTO_EXTENSION].iter()).map(|extension| extension.to_string()).collect()
+}
+
+impl Default for FilesConfig {
+  fn default() -> Self {
+    Self { rom_root: default_rom_root(), extensions: default_extensions() }
+  }
+}
+
+/// Display settings of a single ROM, applied over the global ones
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct DisplayOverride {
+  #[serde(default)]
+  pub persistence: Option<Persistence>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct DisplayConfig {
+  #[serde(default)]
+  pub renderer: RendererKind,
+  #[serde(default)]
+  pub palette: Palette,
+  #[serde(default)]
+  pub persistence: Persistence,
+  /// Overrides keyed by ROM filename
+  #[serde(default)]
+  pub roms: HashMap<String, DisplayOverride>,
+}
+
+impl DisplayConfig {
+  pub fn persistence_for(&self, rom_name: &str) -> Persistence {
+    self.roms.get(rom_name).and_then(|rom| rom.persistence).unwrap_or(self.persistence)
+  }
+}
+
 #[derive(Clone, Debug, Default, Deserialize)]
 pub struct Config {
   #[serde(default, flatten)]
   pub config: AppConfig,
   #[serde(default)]
+  pub emulator: EmulatorConfig,
+  #[serde(default)]
+  pub keypad: KeypadConfig,
+  #[serde(default)]
+  pub display: DisplayConfig,
+  #[serde(default)]
+  pub files: FilesConfig,
+  #[serde(default)]
   pub keybindings: KeyBindings,
   #[serde(default)]
   pub styles: Styles,
@@ -71,7 +187,7 @@ impl Config {
     for (mode, default_styles) in default_config.styles.iter() {
       let user_styles = cfg.styles.entry(*mode).or_default();
       for (style_key, style) in default_styles.iter() {
-        user_styles.entry(style_key.clone()).or_insert_with(|| style.clone());
+        user_styles.entry(style_key.clone()).or_insert(*style);
       }
     }
 
@@ -82,6 +198,18 @@ impl Config {
 #[derive(Clone, Debug, Default, Deref, DerefMut)]
 pub struct KeyBindings(pub HashMap<Mode, HashMap<Vec<KeyEvent>, Action>>);
 
+impl KeyBindings {
+  /// Bindings of `mode` as key sequences such as `<ctrl-c>`, sorted by key sequence
+  pub fn for_mode(&self, mode: Mode) -> Vec<(String, Action)> {
+    let mut bindings: Vec<(String, Action)> = self
+      .get(&mode)
+      .map(|keymap| keymap.iter().map(|(keys, action)| (key_sequence_to_string(keys), action.clone())).collect())
+      .unwrap_or_default();
+    bindings.sort_by(|a, b| a.0.cmp(&b.0));
+    bindings
+  }
+}
+
 impl<'de> Deserialize<'de> for KeyBindings {
   fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
   where
@@ -168,7 +296,7 @@ fn parse_key_code_with_modifiers(raw: &str, mut modifiers: KeyModifiers) -> Resu
     "hyphen" => KeyCode::Char('-'),
     "minus" => KeyCode::Char('-'),
     "tab" => KeyCode::Tab,
-    c if c.len() == 1 => {
+    c if c.chars().count() == 1 => {
       let mut c = c.chars().next().unwrap();
       if modifiers.contains(KeyModifiers::SHIFT) {
         c = c.to_ascii_uppercase();
@@ -198,10 +326,10 @@ pub fn key_event_to_string(key_event: &KeyEvent) -> String {
     KeyCode::Delete => "delete",
     KeyCode::Insert => "insert",
     KeyCode::F(c) => {
-      char = format!("f({c})");
+      char = format!("f{c}");
       &char
     },
-    KeyCode::Char(c) if c == ' ' => "space",
+    KeyCode::Char(' ') => "space",
     KeyCode::Char(c) => {
       char = c.to_string();
       &char
@@ -243,6 +371,11 @@ pub fn key_event_to_string(key_event: &KeyEvent) -> String {
   key
 }
 
+/// Inverse of [`parse_key_sequence`]
+pub fn key_sequence_to_string(keys: &[KeyEvent]) -> String {
+  keys.iter().map(|key| format!("<{}>", key_event_to_string(key))).collect()
+}
+
 pub fn parse_key_sequence(raw: &str) -> Result<Vec<KeyEvent>, String> {
   if raw.chars().filter(|c| *c == '>').count() != raw.chars().filter(|c| *c == '<').count() {
     return Err(format!("Unable to parse `{}`", raw));
@@ -273,6 +406,17 @@ pub fn parse_key_sequence(raw: &str) -> Result<Vec<KeyEvent>, String> {
 #[derive(Clone, Debug, Default, Deref, DerefMut)]
 pub struct Styles(pub HashMap<Mode, HashMap<String, Style>>);
 
+impl Styles {
+  /// Style `name` of `mode`, falling back to the style of the home mode and then to the default style
+  pub fn get_style(&self, mode: Mode, name: &str) -> Style {
+    [mode, Mode::Home]
+      .iter()
+      .find_map(|mode| self.get(mode).and_then(|styles| styles.get(name)))
+      .copied()
+      .unwrap_or_default()
+  }
+}
+
 impl<'de> Deserialize<'de> for Styles {
   fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
   where
@@ -391,6 +535,7 @@ mod tests {
   use pretty_assertions::assert_eq;
 
   use super::*;
+  use crate::persistence::PersistenceMode;
 
   #[test]
   fn test_parse_style_default() {
@@ -429,7 +574,7 @@ mod tests {
   #[test]
   fn test_parse_color_rgb() {
     let color = parse_color("rgb123");
-    let expected = 16 + 1 * 36 + 2 * 6 + 3;
+    let expected = 16 + 36 + 2 * 6 + 3;
     assert_eq!(color, Some(Color::Indexed(expected)));
   }
 
@@ -443,12 +588,55 @@ mod tests {
   fn test_config() -> Result<()> {
     let c = Config::new()?;
     assert_eq!(
-      c.keybindings.get(&Mode::Home).unwrap().get(&parse_key_sequence("<q>").unwrap_or_default()).unwrap(),
+      c.keybindings.get(&Mode::Home).unwrap().get(&parse_key_sequence("<Ctrl-c>").unwrap_or_default()).unwrap(),
       &Action::Quit
     );
     Ok(())
   }
 
+  #[test]
+  fn test_emulator_config() {
+    let c: Config = json5::from_str(r#"{ "emulator": { "seed": 42, "random": "CosmacVip" } }"#).unwrap();
+    assert_eq!(c.emulator.seed, Some(42));
+    assert_eq!(c.emulator.random, RandomKind::CosmacVip);
+    assert_eq!(c.emulator.key_release_timeout(0xA), Duration::from_millis(600));
+    assert!(c.emulator.pause_on_error);
+  }
+
+  #[test]
+  fn test_files_config() {
+    let c: Config = json5::from_str(r#"{ "files": { "rom_root": "~/roms" } }"#).unwrap();
+    assert_eq!(c.files.rom_root, PathBuf::from("~/roms"));
+    assert_eq!(c.files.extensions, vec!["ch8", "c8", "sc8", "xo8", "8o"]);
+  }
+
+  #[test]
+  fn test_display_rom_overrides() {
+    let c: Config = json5::from_str(
+      r#"{ "display": { "persistence": { "mode": "Or" }, "roms": { "pong.ch8": { "persistence": { "mode": "Fade", "frames": 5 } } } } }"#,
+    )
+    .unwrap();
+    assert_eq!(c.display.persistence_for("other.ch8"), Persistence { mode: PersistenceMode::Or, frames: 3 });
+    assert_eq!(c.display.persistence_for("pong.ch8"), Persistence { mode: PersistenceMode::Fade, frames: 5 });
+  }
+
+  #[test]
+  fn test_key_release_timeouts() {
+    let c: Config =
+      json5::from_str(r#"{ "emulator": { "key_release_timeout": 300, "key_release_timeouts": { "a": 100 } } }"#)
+        .unwrap();
+    assert_eq!(c.emulator.key_release_timeout(0xA), Duration::from_millis(100));
+    assert_eq!(c.emulator.key_release_timeout(0x5), Duration::from_millis(300));
+  }
+
+  #[test]
+  fn test_default_styles() -> Result<()> {
+    let c = Config::new()?;
+    assert_eq!(c.styles.get_style(Mode::SelectingFile, "border_focused").fg, Some(Color::Indexed(6)));
+    assert_eq!(c.styles.get_style(Mode::Home, "missing"), Style::default());
+    Ok(())
+  }
+
   #[test]
   fn test_simple_keys() {
     assert_eq!(parse_key_event("a").unwrap(), KeyEvent::new(KeyCode::Char('a'), KeyModifiers::empty()));
@@ -488,6 +676,26 @@ mod tests {
     );
   }
 
+  #[test]
+  fn test_key_sequence_round_trip() {
+    for raw in ["<ctrl-c>", "<f1>", "<esc>", "<g><g>", "<space>"] {
+      assert_eq!(key_sequence_to_string(&parse_key_sequence(raw).unwrap()), raw);
+    }
+  }
+
+  #[test]
+  fn test_bindings_for_mode() {
+    let c: Config = json5::from_str(r#"{ "keybindings": { "Home": { "<Ctrl-o>": "FocusFileSelector", "<F1>": "Help" } } }"#)
+      .unwrap();
+    let bindings = c.keybindings.for_mode(Mode::Home);
+    assert_eq!(bindings, vec![
+      ("<ctrl-o>".to_string(), Action::FocusFileSelector),
+      ("<f1>".to_string(), Action::Help),
+    ]);
+    assert_eq!(bindings[0].1.description(), "Focus file selector");
+    assert!(c.keybindings.for_mode(Mode::Error).is_empty());
+  }
+
   #[test]
   fn test_invalid_keys() {
     assert!(parse_key_event("invalid-key").is_err());
@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use crate::disasm::Disassembly;
+use crate::isa::Instruction;
+use crate::symbols::SymbolMap;
+
+/// Which addresses were executed and which ways their skip instructions went
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    executed: Vec<bool>,
+    skip_taken: Vec<bool>,
+    skip_not_taken: Vec<bool>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self { executed: vec![false; 0x1000], skip_taken: vec![false; 0x1000], skip_not_taken: vec![false; 0x1000] }
+    }
+}
+
+/// Coverage of the source lines of one file
+#[derive(Debug, Default)]
+struct FileCoverage {
+    /// Whether each line with code was executed
+    lines: BTreeMap<usize, bool>,
+    /// Whether each skip was taken and not taken, if it was executed
+    branches: BTreeMap<usize, Option<(bool, bool)>>,
+}
+
+impl Coverage {
+    /// Records the execution of `instruction` at `address`, after which the program counter is
+    /// `next`
+    pub fn record(&mut self, address: u16, instruction: &Instruction, next: u16) {
+        let Some(executed) = self.executed.get_mut(address as usize) else { return };
+        *executed = true;
+        if Disassembly::is_skip(instruction) {
+            match next > address.wrapping_add(2) {
+                true => self.skip_taken[address as usize] = true,
+                false => self.skip_not_taken[address as usize] = true,
+            }
+        }
+    }
+
+    /// Whether every address of memory was executed
+    pub fn executed(&self) -> &[bool] {
+        &self.executed
+    }
+
+    fn is_executed(&self, address: u16) -> bool {
+        self.executed.get(address as usize).copied().unwrap_or(false)
+    }
+
+    /// Coverage of the code of `disassembly` in the lcov tracefile format, with the addresses
+    /// mapped to the source lines of `symbols`. Lines that only produced data are left out
+    pub fn to_lcov(&self, disassembly: &Disassembly, symbols: &SymbolMap) -> String {
+        let mut files: BTreeMap<&str, FileCoverage> = BTreeMap::new();
+        for (i, line) in symbols.lines.iter().enumerate() {
+            let end = symbols.lines.get(i + 1).map_or(line.address.saturating_add(2), |next| next.address);
+            if end <= line.address {
+                continue
+            }
+            let instructions: Vec<u16> = disassembly.code.range(line.address..end)
+                .map(|(&address, _)| address)
+                .collect();
+            let executed = (line.address..end).any(|address| self.is_executed(address));
+            if instructions.is_empty() && !executed {
+                continue
+            }
+            let file = files.entry(line.file.as_str()).or_default();
+            *file.lines.entry(line.line).or_default() |= executed;
+            for address in instructions {
+                if Disassembly::is_skip(&disassembly.code[&address]) {
+                    let branch = self.is_executed(address)
+                        .then(|| (self.skip_taken[address as usize], self.skip_not_taken[address as usize]));
+                    file.branches.insert(line.line, branch);
+                }
+            }
+        }
+
+        let mut lcov = String::new();
+        for (name, file) in files {
+            let _ = writeln!(lcov, "TN:");
+            let _ = writeln!(lcov, "SF:{}", name);
+            for (line, branch) in &file.branches {
+                let (taken, not_taken) = match branch {
+                    Some((taken, not_taken)) => ((*taken as u8).to_string(), (*not_taken as u8).to_string()),
+                    None => ("-".to_string(), "-".to_string()),
+                };
+                let _ = writeln!(lcov, "BRDA:{},0,0,{}", line, taken);
+                let _ = writeln!(lcov, "BRDA:{},0,1,{}", line, not_taken);
+            }
+            let branches_hit = file.branches.values().flatten()
+                .map(|(taken, not_taken)| *taken as usize + *not_taken as usize)
+                .sum::<usize>();
+            let _ = writeln!(lcov, "BRF:{}", file.branches.len() * 2);
+            let _ = writeln!(lcov, "BRH:{}", branches_hit);
+            for (line, executed) in &file.lines {
+                let _ = writeln!(lcov, "DA:{},{}", line, *executed as u8);
+            }
+            let _ = writeln!(lcov, "LF:{}", file.lines.len());
+            let _ = writeln!(lcov, "LH:{}", file.lines.values().filter(|executed| **executed).count());
+            let _ = writeln!(lcov, "end_of_record");
+        }
+        lcov
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::asm;
+    use crate::emulator::Chip8Emu;
+    use crate::isa::InstructionSet;
+
+    #[test]
+    fn test_lcov_report() {
+        let source = "\
+start:
+    LD V0, 1
+    SE V0, 1
+    CALL never
+done:
+    JP done
+never:
+    RET
+sprite:
+    db 0xFF
+";
+        let assembly = asm::assemble_source("game.asm", source, InstructionSet::Chip8).unwrap();
+        let mut emulator = Chip8Emu::new();
+        emulator.load_rom(assembly.rom.clone()).unwrap();
+        for _ in 0..4 {
+            emulator.emulate_cycle().unwrap();
+        }
+        let coverage = emulator.get_coverage();
+        assert!(coverage.executed()[0x202]);
+        assert!(!coverage.executed()[0x204]);
+
+        let disassembly = Disassembly::analyze(&assembly.rom, InstructionSet::Chip8, Some(&assembly.symbols));
+        assert_eq!(coverage.to_lcov(&disassembly, &assembly.symbols), "\
+TN:
+SF:game.asm
+BRDA:3,0,0,1
+BRDA:3,0,1,0
+BRF:2
+BRH:1
+DA:2,1
+DA:3,1
+DA:4,0
+DA:6,1
+DA:8,0
+LF:5
+LH:3
+end_of_record
+");
+    }
+
+    #[test]
+    fn test_records_skips_only() {
+        let mut coverage = Coverage::default();
+        coverage.record(0x200, &Instruction::SeReg(1, 2), 0x204);
+        coverage.record(0x204, &Instruction::SaveRange(1, 2), 0x206);
+
+        assert!(coverage.skip_taken[0x200]);
+        assert!(!coverage.skip_taken[0x204] && !coverage.skip_not_taken[0x204]);
+        assert!(coverage.executed()[0x204]);
+    }
+}
@@ -0,0 +1,382 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use color_eyre::eyre::eyre;
+use serde::{Deserialize, Serialize};
+
+use crate::asm::PROGRAM_START;
+use crate::cli::DisasmArgs;
+use crate::isa::{Instruction, InstructionSet};
+use crate::romdb::RomDatabase;
+use crate::symbols::SymbolMap;
+
+/// Bytes from the start of programs to the end of the 64 KB address space of XO-CHIP
+const MAX_ROM_SIZE: usize = 0x10000 - PROGRAM_START as usize;
+/// Data bytes per `db` line
+const BYTES_PER_LINE: usize = 8;
+/// Column of the address comments of the listing
+const COMMENT_COLUMN: usize = 28;
+
+/// Why an address is labelled. Earlier kinds name the label when there are several
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelKind {
+    Entry,
+    Subroutine,
+    Jump,
+    Data,
+}
+
+impl LabelKind {
+    fn name(self, address: u16) -> String {
+        match self {
+            LabelKind::Entry => "start".to_string(),
+            LabelKind::Subroutine => format!("sub_{:03X}", address),
+            LabelKind::Jump => format!("loc_{:03X}", address),
+            LabelKind::Data => format!("data_{:03X}", address),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineKind {
+    Code,
+    Data,
+}
+
+/// A line of the listing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Line {
+    pub address: u16,
+    pub label: Option<String>,
+    pub kind: LineKind,
+    pub bytes: Vec<u8>,
+    /// Assembler syntax of the line
+    pub text: String,
+}
+
+/// A ROM split into the instructions reachable from its entry point and data
+#[derive(Debug, Clone)]
+pub struct Disassembly {
+    pub set: InstructionSet,
+    rom: Vec<u8>,
+    /// Instructions by address
+    pub code: BTreeMap<u16, Instruction>,
+    /// Whether each byte of the ROM is part of an instruction
+    is_code: Vec<bool>,
+    /// Labels of the addresses that start a line
+    pub labels: BTreeMap<u16, String>,
+}
+
+impl Disassembly {
+    fn end(&self) -> u32 {
+        PROGRAM_START as u32 + self.rom.len() as u32
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    /// Whether `address` is inside the ROM
+    pub fn contains(&self, address: u16) -> bool {
+        address >= PROGRAM_START && (address as u32) < self.end()
+    }
+
+    /// Whether the byte at `address` is part of a reachable instruction
+    pub fn is_code(&self, address: u16) -> bool {
+        self.contains(address) && self.is_code[(address - PROGRAM_START) as usize]
+    }
+
+    fn word(&self, address: u16) -> Option<u16> {
+        let offset = address.checked_sub(PROGRAM_START)? as usize;
+        let bytes = self.rom.get(offset..offset + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Decodes the instruction at `address`
+    pub fn decode_at(&self, address: u16) -> Option<Instruction> {
+        Instruction::decode(self.word(address)?, address.checked_add(2).and_then(|next| self.word(next)), self.set)
+    }
+
+    pub fn is_skip(instruction: &Instruction) -> bool {
+        use Instruction::*;
+        matches!(instruction, SeImm(..) | SneImm(..) | SeReg(..) | SneReg(..) | Skp(_) | Sknp(_))
+    }
+
+    /// Addresses execution can continue at after `instruction` at `address`. Where a `JP V0`
+    /// goes is unknown, so it has none
+    pub fn successors(&self, address: u16, instruction: &Instruction) -> Vec<u16> {
+        use Instruction::*;
+
+        let next = address.wrapping_add(instruction.size() as u16);
+        match *instruction {
+            Jp(target) => vec![target],
+            Call(target) => vec![target, next],
+            Ret | Exit | JpV0(_) => vec![],
+            _ if Self::is_skip(instruction) => {
+                // A skip jumps over a whole XO-CHIP `LD I, LONG`
+                let skipped = match self.word(next) {
+                    Some(0xF000) if self.set == InstructionSet::XoChip => 4,
+                    _ => 2,
+                };
+                vec![next, next.wrapping_add(skipped)]
+            }
+            _ => vec![next],
+        }
+    }
+
+    /// `rom` with nothing decoded yet
+    fn new(rom: &[u8], set: InstructionSet) -> Self {
+        Self { set, rom: rom.to_vec(), code: BTreeMap::new(), is_code: vec![false; rom.len()], labels: BTreeMap::new() }
+    }
+
+    /// Follows every path from the entry point. Labels of `symbols` replace generated ones
+    pub fn analyze(rom: &[u8], set: InstructionSet, symbols: Option<&SymbolMap>) -> Self {
+        let mut disassembly = Self::new(rom, set);
+        let mut kinds: BTreeMap<u16, LabelKind> = BTreeMap::from([(PROGRAM_START, LabelKind::Entry)]);
+        let mut pending = vec![PROGRAM_START];
+        while let Some(address) = pending.pop() {
+            if !disassembly.contains(address) || disassembly.is_code[(address - PROGRAM_START) as usize] {
+                continue
+            }
+            let Some(instruction) = disassembly.decode_at(address) else { continue };
+            let offset = (address - PROGRAM_START) as usize;
+            let bytes = offset..offset + instruction.size();
+            if disassembly.is_code[bytes.clone()].iter().any(|is_code| *is_code) {
+                continue
+            }
+            disassembly.is_code[bytes].fill(true);
+            disassembly.code.insert(address, instruction);
+
+            let reference = match instruction {
+                Instruction::Call(target) => Some((target, LabelKind::Subroutine)),
+                Instruction::Jp(target) | Instruction::JpV0(target) => Some((target, LabelKind::Jump)),
+                Instruction::LdI(target) | Instruction::LdILong(target) => Some((target, LabelKind::Data)),
+                _ => None,
+            };
+            if let Some((target, kind)) = reference {
+                let label = kinds.entry(target).or_insert(kind);
+                *label = (*label).min(kind);
+            }
+            pending.extend(disassembly.successors(address, &instruction));
+        }
+
+        let mut names: BTreeMap<u16, String> = kinds.into_iter()
+            .map(|(address, kind)| (address, kind.name(address)))
+            .collect();
+        if let Some(symbols) = symbols {
+            names.extend(symbols.labels_by_address());
+        }
+        // Only the starts of lines can be labelled
+        disassembly.labels = names.into_iter()
+            .filter(|(address, _)| disassembly.contains(*address))
+            .filter(|(address, _)| {
+                disassembly.code.contains_key(address) || !disassembly.is_code[(address - PROGRAM_START) as usize]
+            })
+            .collect();
+        disassembly
+    }
+
+    fn format(&self, instruction: &Instruction) -> String {
+        instruction.format_with(|address| {
+            self.labels.get(&address).cloned().unwrap_or_else(|| format!("0x{:03X}", address))
+        })
+    }
+
+    /// Instructions and `db` lines of the whole ROM, in address order
+    pub fn lines(&self) -> Vec<Line> {
+        let mut lines = vec![];
+        // A ROM may end at the top of the address space, past which a u16 overflows
+        let mut cursor = PROGRAM_START as u32;
+        while cursor < self.end() {
+            let address = cursor as u16;
+            let label = self.labels.get(&address).cloned();
+            if let Some(instruction) = self.code.get(&address) {
+                let offset = (address - PROGRAM_START) as usize;
+                lines.push(Line {
+                    address,
+                    label,
+                    kind: LineKind::Code,
+                    bytes: self.rom[offset..offset + instruction.size()].to_vec(),
+                    text: self.format(instruction),
+                });
+                cursor += instruction.size() as u32;
+                continue
+            }
+            let mut bytes = vec![];
+            while cursor < self.end()
+                && !self.is_code[(cursor - PROGRAM_START as u32) as usize]
+                && bytes.len() < BYTES_PER_LINE
+                && (bytes.is_empty() || !self.labels.contains_key(&(cursor as u16))) {
+                bytes.push(self.rom[(cursor - PROGRAM_START as u32) as usize]);
+                cursor += 1;
+            }
+            let text = format!("db {}", bytes.iter().map(|byte| format!("0x{:02X}", byte)).collect::<Vec<_>>().join(", "));
+            lines.push(Line { address, label, kind: LineKind::Data, bytes, text });
+        }
+        lines
+    }
+}
+
+/// Every word of `rom` decoded in order, without following the control flow
+pub fn linear(rom: &[u8], set: InstructionSet) -> Vec<Line> {
+    let disassembly = Disassembly::new(rom, set);
+    let mut lines = vec![];
+    let mut offset = 0;
+    while offset < rom.len() {
+        let address = (PROGRAM_START as usize + offset) as u16;
+        let (kind, size, text) = match disassembly.decode_at(address) {
+            Some(instruction) => (LineKind::Code, instruction.size(), instruction.to_string()),
+            None => {
+                let size = 2.min(rom.len() - offset);
+                let bytes = rom[offset..offset + size].iter().map(|byte| format!("0x{:02X}", byte)).collect::<Vec<_>>();
+                (LineKind::Data, size, format!("db {}", bytes.join(", ")))
+            }
+        };
+        lines.push(Line { address, label: None, kind, bytes: rom[offset..offset + size].to_vec(), text });
+        offset += size;
+    }
+    lines
+}
+
+/// Assembler source of `lines`, with the address and bytes of each line as a comment
+pub fn to_source(lines: &[Line]) -> String {
+    let mut source = String::new();
+    for line in lines {
+        if let Some(label) = &line.label {
+            let _ = writeln!(source, "{}:", label);
+        }
+        let text = format!("    {}", line.text);
+        let bytes: String = line.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let _ = writeln!(source, "{:<width$} ; 0x{:03X} {}", text, line.address, bytes, width = COMMENT_COLUMN);
+    }
+    source
+}
+
+#[derive(Serialize)]
+struct JsonListing<'a> {
+    instruction_set: InstructionSet,
+    labels: BTreeMap<String, u16>,
+    lines: &'a [Line],
+}
+
+/// Runs the `disasm` subcommand
+pub fn run(args: &DisasmArgs) -> color_eyre::Result<()> {
+    let rom = fs::read(&args.rom)?;
+    if rom.len() > MAX_ROM_SIZE {
+        return Err(eyre!("{} is {} bytes, more than the {} that fit in the address space", args.rom.display(), rom.len(), MAX_ROM_SIZE))
+    }
+    let symbols = match &args.symbols {
+        Some(path) => Some(SymbolMap::load(path)?),
+        None => SymbolMap::load_for_rom(&args.rom)?,
+    };
+    let set = RomDatabase::profile_instruction_set(args.target, args.profile.as_deref())?;
+    let (lines, labels) = if args.linear {
+        (linear(&rom, set), BTreeMap::new())
+    } else {
+        let disassembly = Disassembly::analyze(&rom, set, symbols.as_ref());
+        let labels = disassembly.labels.iter().map(|(address, name)| (name.clone(), *address)).collect();
+        (disassembly.lines(), labels)
+    };
+
+    let output = if args.json {
+        serde_json::to_string_pretty(&JsonListing { instruction_set: set, labels, lines: &lines })?
+    } else {
+        to_source(&lines)
+    };
+    match &args.output {
+        Some(path) => fs::write(path, output)?,
+        None => print!("{}", output),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::asm;
+
+    fn round_trip(rom: &[u8], set: InstructionSet) -> Vec<u8> {
+        let source = to_source(&Disassembly::analyze(rom, set, None).lines());
+        asm::assemble_source("disassembly.asm", &source, set).unwrap().rom
+    }
+
+    #[test]
+    fn test_separates_code_from_data() {
+        let rom = [
+            0xA2, 0x08, // LD I, data_208
+            0x22, 0x0A, // CALL sub_20A
+            0x12, 0x04, // JP loc_204
+            0xFF, 0xFF, // unreachable
+            0x3C, 0x42, // sprite
+            0x30, 0x01, // SE V0, 1
+            0x00, 0xEE, // RET
+            0x00, 0xEE, // RET
+        ];
+        let disassembly = Disassembly::analyze(&rom, InstructionSet::Chip8, None);
+        let lines: Vec<(Option<String>, String)> = disassembly.lines().into_iter()
+            .map(|line| (line.label, line.text))
+            .collect();
+        assert_eq!(lines, vec![
+            (Some("start".to_string()), "LD I, data_208".to_string()),
+            (None, "CALL sub_20A".to_string()),
+            (Some("loc_204".to_string()), "JP loc_204".to_string()),
+            (None, "db 0xFF, 0xFF".to_string()),
+            (Some("data_208".to_string()), "db 0x3C, 0x42".to_string()),
+            (Some("sub_20A".to_string()), "SE V0, 0x01".to_string()),
+            (None, "RET".to_string()),
+            (None, "RET".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_symbols_name_labels() {
+        let rom = [0x12, 0x00];
+        let symbols = SymbolMap {
+            labels: BTreeMap::from([("main".to_string(), 0x200)]),
+            ..SymbolMap::default()
+        };
+        let disassembly = Disassembly::analyze(&rom, InstructionSet::Chip8, Some(&symbols));
+        assert_eq!(disassembly.lines()[0].text, "JP main");
+    }
+
+    #[test]
+    fn test_skips_over_long_loads() {
+        let rom = [0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x00, 0xFD];
+        let disassembly = Disassembly::analyze(&rom, InstructionSet::XoChip, None);
+        assert_eq!(disassembly.code.keys().copied().collect::<Vec<_>>(), vec![0x200, 0x202, 0x206]);
+    }
+
+    #[test]
+    fn test_bundled_roms_round_trip() {
+        for path in ["scripts/IBM Logo.ch8", "scripts/test_opcode.ch8", "scripts/6-keypad.ch8"] {
+            let rom = fs::read(path).unwrap();
+            assert_eq!(round_trip(&rom, InstructionSet::Chip8), rom, "{}", path);
+            assert_eq!(round_trip(&rom, InstructionSet::XoChip), rom, "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_linear() {
+        let lines = linear(&[0x00, 0xE0, 0xFF, 0xFF, 0x12], InstructionSet::Chip8);
+        let texts: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(texts, vec!["CLS", "db 0xFF, 0xFF", "db 0x12"]);
+    }
+
+    #[test]
+    fn test_rom_filling_the_address_space() {
+        let rom = [0x00, 0xE0].repeat(MAX_ROM_SIZE / 2);
+
+        let analyzed = Disassembly::analyze(&rom, InstructionSet::XoChip, None).lines();
+        let decoded = linear(&rom, InstructionSet::XoChip);
+
+        assert_eq!(analyzed.len(), MAX_ROM_SIZE / 2);
+        assert_eq!(analyzed.last().unwrap().address, 0xFFFE);
+        assert_eq!(decoded.last().unwrap().address, 0xFFFE);
+    }
+}
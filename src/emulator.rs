@@ -4,7 +4,14 @@ use std::ops::{Deref, Div};
 use std::path::Path;
 use clap::builder::Str;
 use log::Level;
-use rand::Rng;
+use crate::random::{RandomKind, RandomSource};
+use crate::cheats::Cheat;
+use crate::isa::{Instruction, InstructionSet};
+use crate::coverage::Coverage;
+use crate::profiler::Profiler;
+use crate::rom::MAX_PROGRAM_SIZE;
+use crate::sprites::{Sprite, SpriteLog};
+use serde::{Deserialize, Serialize};
 use itertools::{Itertools, Tuples};
 use itertools::traits::HomogeneousTuple;
 
@@ -18,6 +25,7 @@ pub enum EmulationErr {
     InvalidKeycode,
     ProgramExited,
     InvalidRegisterReference,
+    RomTooLarge(usize),
 }
 
 impl From<EmulationErr> for String {
@@ -47,6 +55,9 @@ impl From<EmulationErr> for String {
             EmulationErr::InvalidRegisterReference => {
                 "Invalid register reference supplied".to_string()
             }
+            EmulationErr::RomTooLarge(size) => {
+                format!("The ROM is {} bytes, more than the {} that fit in memory", size, MAX_PROGRAM_SIZE)
+            }
         }
     }
 }
@@ -73,12 +84,37 @@ fn font() -> Vec<u8> {
     ]
 }
 
-#[derive(Default)]
-struct Quirks {
-    superchip_opcodes: bool, // Enables opcodes that were *added* in Superchip
-    superchip_shift: bool, // Enables the new behaviour of 0x8XY6 and 0x8XYE from Superchip
-    superchip_offset_jump: bool, // Enables the 0xBNNN behaviour from Superchip
-    superchip_memory: bool, // Enables the 0xFX55 and 0xFX65 behaviour from Superchip
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Quirks {
+    pub superchip_opcodes: bool, // Enables opcodes that were *added* in Superchip
+    pub superchip_shift: bool, // Enables the new behaviour of 0x8XY6 and 0x8XYE from Superchip
+    pub superchip_offset_jump: bool, // Enables the 0xBNNN behaviour from Superchip
+    pub superchip_memory: bool, // Enables the 0xFX55 and 0xFX65 behaviour from Superchip
+}
+
+/// Snapshot of the registers of the emulator
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CpuState {
+    pub registers: Vec<u8>,
+    pub index_register: u16,
+    pub program_counter: u16,
+    pub stack_pointer: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+/// An error raised by an instruction, with the state of the emulator at that instruction
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmulationFault {
+    pub message: String,
+    pub opcode: u16,
+    pub state: CpuState,
+}
+
+/// Returns the SHA-1 digest of `bytes` as a lowercase hex string
+pub fn rom_hash(bytes: &[u8]) -> String {
+    sha1_smol::Sha1::from(bytes).digest().to_string()
 }
 
 
@@ -100,11 +136,26 @@ pub struct Chip8Emu {
     stack_pointer: u16,
 
     keys: Vec<bool>,
+    awaited_key: Option<u8>, // Key pressed during 0xFX0A, which completes once it is released
     quirks: Quirks,
 
+    // Deterministic execution
+    rom: Vec<u8>,
+    seed: u64,
+    random_kind: RandomKind,
+    rng: Box<dyn RandomSource>,
+    frame: u64,
+
     // SUPERCHIP related features
     rpl: Vec<u8>,
-    is_hi_res_mode: bool
+    is_hi_res_mode: bool,
+
+    // Debugging
+    sprites: SpriteLog,
+    profiler: Profiler,
+    coverage: Coverage,
+    /// Bytes of memory written back every frame
+    cheats: Vec<Cheat>,
 }
 
 impl Default for Chip8Emu {
@@ -121,15 +172,29 @@ impl Default for Chip8Emu {
             stack: vec![0x0000; 16],
             stack_pointer: 0x0000,
             keys: vec![false; 16],
+            awaited_key: None,
             quirks: Quirks::default(),
+            rom: Vec::new(),
+            seed: 0,
+            random_kind: RandomKind::default(),
+            rng: RandomKind::default().create(0),
+            frame: 0,
             rpl: vec![0x00; 8],
             is_hi_res_mode: false,
+            sprites: SpriteLog::default(),
+            profiler: Profiler::default(),
+            coverage: Coverage::default(),
+            cheats: Vec::new(),
         }
     }
 }
 
 impl Chip8Emu {
-    pub fn new() -> Self { Self::default() }
+    pub fn new() -> Self {
+        let mut emulator = Self::default();
+        emulator.set_seed(rand::random());
+        emulator
+    }
     pub fn screen(&self) -> Vec<u8> { self.gfx.clone() }
     
     fn reset(&mut self) {
@@ -144,11 +209,71 @@ impl Chip8Emu {
         self.stack = vec![0x0000; 16];
         self.stack_pointer = 0x0000;
         self.keys = vec![false; 16];
+        self.awaited_key = None;
+        self.rng.reseed(self.seed);
+        self.frame = 0;
         self.is_hi_res_mode = false;
     }
     
     pub fn get_opcode(&self) -> u16 { self.opcode }
     pub fn get_program_counter(&self) -> u16 { self.program_counter }
+    pub fn get_cpu_state(&self) -> CpuState {
+        CpuState {
+            registers: self.registers.clone(),
+            index_register: self.index_register,
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+        }
+    }
+    pub fn get_screen_size(&self) -> (usize, usize) {
+        if self.gfx.len() == 128 / 8 * 64 { (128, 64) } else { (64, 32) }
+    }
+    pub fn get_keys(&self) -> Vec<bool> { self.keys.clone() }
+    pub fn get_seed(&self) -> u64 { self.seed }
+    pub fn get_random_kind(&self) -> RandomKind { self.random_kind }
+    pub fn get_frame(&self) -> u64 { self.frame }
+    pub fn get_quirks(&self) -> Quirks { self.quirks }
+    /// The loaded ROM, as it was given to [`Chip8Emu::load_rom`]
+    pub fn get_rom(&self) -> &[u8] { &self.rom }
+    pub fn get_rom_hash(&self) -> String { rom_hash(&self.rom) }
+    pub fn get_cheats(&self) -> &[Cheat] { &self.cheats }
+    pub fn get_memory(&self) -> Vec<u8> { self.memory.clone() }
+    /// Sprites drawn since a different ROM was loaded
+    pub fn get_sprites(&self) -> &SpriteLog { &self.sprites }
+    /// Profile of the run since the ROM was last loaded or restarted
+    pub fn get_profiler(&self) -> &Profiler { &self.profiler }
+    /// Coverage of the run since the ROM was last loaded or restarted
+    pub fn get_coverage(&self) -> &Coverage { &self.coverage }
+
+    /// Sets the seed of the CXNN random source and restarts its sequence
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng.reseed(seed);
+    }
+
+    /// Replaces the CXNN random source, seeding it with the current seed
+    pub fn set_random_kind(&mut self, kind: RandomKind) {
+        self.random_kind = kind;
+        self.rng = kind.create(self.seed);
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) { self.quirks = quirks }
+
+    /// Freezes the bytes of `cheats` to their values, from now on and at the end of every frame
+    pub fn set_cheats(&mut self, cheats: Vec<Cheat>) {
+        self.cheats = cheats;
+        self.apply_cheats();
+    }
+
+    fn apply_cheats(&mut self) {
+        for cheat in &self.cheats {
+            if let Some(byte) = self.memory.get_mut(cheat.address as usize) {
+                *byte = cheat.value;
+            }
+        }
+    }
 
     pub fn get_opcodes(&self) -> Vec<u16> {
         let mut result: Vec<u16> = Vec::new();
@@ -166,15 +291,8 @@ impl Chip8Emu {
         let file_contents = fs::read(file_path);
 
         match file_contents {
-            Ok(mut bytes) => {
-                self.reset();
-                let length = bytes.len();
-                self.memory = Vec::new();
-                self.memory.append(&mut vec![0x00; 80]);
-                self.memory.append(&mut font());
-                self.memory.append(&mut vec![0x00; 512-160]);
-                self.memory.append(&mut bytes);
-                self.memory.append(&mut vec![0x00; 4096 - length - 511]);
+            Ok(bytes) => {
+                self.load_rom(bytes)?;
                 log::log!(Level::Info, "ROM loaded from file {}", file_path);
                 Ok(())
             }
@@ -184,6 +302,41 @@ impl Chip8Emu {
         }
 
     }
+
+    /// Resets the emulator and loads `bytes` at the start of programs. A ROM that doesn't fit in
+    /// memory is refused, leaving the emulator as it was
+    pub fn load_rom(&mut self, mut bytes: Vec<u8>) -> Result<(), EmulationErr> {
+        if bytes.len() > MAX_PROGRAM_SIZE {
+            return Err(EmulationErr::RomTooLarge(bytes.len()))
+        }
+        self.reset();
+        // Restarting the same ROM keeps the sprites it drew
+        if bytes != self.rom {
+            self.sprites = SpriteLog::default();
+        }
+        self.profiler = Profiler::default();
+        self.coverage = Coverage::default();
+        let length = bytes.len();
+        self.rom = bytes.clone();
+        self.memory = Vec::new();
+        self.memory.append(&mut vec![0x00; 80]);
+        self.memory.append(&mut font());
+        self.memory.append(&mut vec![0x00; 512-160]);
+        self.memory.append(&mut bytes);
+        self.memory.append(&mut vec![0x00; 4096 - length - 511]);
+        Ok(())
+    }
+
+    /// Finishes the current frame: decrements the timers and advances the frame counter.
+    /// Returns `true` if the sound timer was active during this frame
+    pub fn advance_frame(&mut self) -> bool {
+        self.frame += 1;
+        self.rng.on_frame();
+        self.profiler.end_frame();
+        self.apply_cheats();
+        self.update_delay_timer();
+        self.update_sound_timer()
+    }
     
     pub fn update_delay_timer(&mut self) {
         if self.delay_timer > 0 {
@@ -219,9 +372,46 @@ impl Chip8Emu {
         Ok(())
     }
 
+    pub fn set_key(&mut self, key: u8, pressed: bool) -> Result<(), EmulationErr> {
+        if pressed { self.press(&key) } else { self.release(&key) }
+    }
 
 
+
+    /// Executes one instruction. On error the program counter is left on the faulting instruction
     pub fn emulate_cycle(&mut self) -> Result<(), EmulationErr> {
+        let address = self.program_counter;
+        let delay_timer = self.delay_timer;
+        match self.execute_instruction() {
+            Ok(instruction) => {
+                self.profiler.record(address, self.opcode, self.program_counter, delay_timer);
+                self.coverage.record(address, &instruction, self.program_counter);
+                Ok(())
+            }
+            Err(err) => {
+                self.program_counter = address;
+                Err(err)
+            }
+        }
+    }
+
+    /// Describes `err`, raised by the instruction at the program counter
+    pub fn fault(&self, err: EmulationErr) -> EmulationFault {
+        EmulationFault { message: err.into(), opcode: self.opcode, state: self.get_cpu_state() }
+    }
+
+    /// Moves the program counter past the current instruction without executing it
+    pub fn skip_instruction(&mut self) {
+        self.program_counter += 2;
+    }
+
+    /// Restarts the loaded ROM from a clean state
+    pub fn restart(&mut self) {
+        self.load_rom(self.rom.clone()).expect("The loaded ROM fits in memory");
+    }
+
+    /// Fetches, decodes and executes the instruction at the program counter, returning it
+    fn execute_instruction(&mut self) -> Result<Instruction, EmulationErr> {
         // Fetch opcode
         let first_byte = self.memory[self.program_counter as usize] as u16;
         let second_byte = self.memory[(self.program_counter + 1) as usize] as u16;
@@ -236,19 +426,23 @@ impl Chip8Emu {
         let n: u8 = (self.opcode & 0x000F) as u8;
         let nn: u8 = (self.opcode & 0x00FF) as u8;
         let nnn: u16 = self.opcode & 0x0FFF;
-
-
+        let set = if self.quirks.superchip_opcodes { InstructionSet::Schip } else { InstructionSet::Chip8 };
+        // The 4-byte `LD I, LONG` is XO-CHIP only, so no second word is needed
+        let Some(instruction) = Instruction::decode(self.opcode, None, set) else {
+            return Err(EmulationErr::UnknownOpcode(self.opcode))
+        };
 
         // Execute opcode
-        match self.opcode {
+        use Instruction::*;
+        match instruction {
             // 0x00E0 - Clear screen
-            0x00E0 => {
+            Cls => {
                 self.gfx = vec![0x00; 8 * 32];
                 log::log!(Level::Info, "Clearing the screen");
             },
 
             // 0x00EE - Exit from subroutine
-            0x00EE => {
+            Ret => {
                 self.program_counter = self.stack[self.stack_pointer as usize];
                 log::info!("Exiting from subroutine to 0x{:0>3X}", self.program_counter);
 
@@ -260,13 +454,13 @@ impl Chip8Emu {
             },
 
             // 0x1NNN - Jump to NNN
-            0x1000..=0x1FFF => {
+            Jp(_) => {
                 self.program_counter = nnn;
                 log::log!(Level::Info, "Set PC to 0x{:0>3X}", nnn);
             },
 
             // 0x2NNN - Start subroutine from address NNN
-            0x2000..=0x2FFF => {
+            Call(_) => {
                 self.stack_pointer += 1;
                 self.stack[self.stack_pointer as usize] = self.program_counter;
                 self.program_counter = nnn;
@@ -274,7 +468,7 @@ impl Chip8Emu {
             },
 
             // 0x3XNN - Skip one instruction if the value in VX is equal to NN
-            0x3000..=0x3FFF => {
+            SeImm(..) => {
                 if self.registers[x] == nn {
                     log::info!("Skipped instruction at 0x{:0>3X}", self.program_counter);
                     self.program_counter += 2;
@@ -282,7 +476,7 @@ impl Chip8Emu {
             },
 
             // 0x4XNN - Skip one instruction if the value in VX is not equal to NN
-            0x4000..=0x4FFF => {
+            SneImm(..) => {
                 if self.registers[x] != nn {
                     log::info!("Skipped instruction at 0x{:0>3X}", self.program_counter);
                     self.program_counter += 2;
@@ -290,7 +484,7 @@ impl Chip8Emu {
             },
 
             // 0x5XY0 - Skip one instruction if the value in VX is equal to value in VY
-            0x5000..=0x5FF0 => {
+            SeReg(..) => {
                 if self.registers[x] == self.registers[y] {
                     log::info!("Skipped instruction at 0x{:0>3X}", self.program_counter);
                     self.program_counter += 2;
@@ -298,100 +492,94 @@ impl Chip8Emu {
             },
 
             // 0x6XNN - Set register VX to NN
-            0x6000..=0x6FFF => {
+            LdImm(..) => {
                 self.registers[x] = nn;
                 log::log!(Level::Info, "Set register V{:X} to {}", x, nn);
             },
 
             // 0x7XNN - Add NN to register VX
-            0x7000..=0x7FFF => {
+            AddImm(..) => {
                 self.registers[x] = self.registers[x].wrapping_add(nn);
                 log::log!(Level::Info, "Added {} to register V{:X}", nn, x);
             },
 
-            // 0x8XYN - Logical and arithmetic instructions
-            0x8000..=0x8FFF => {
-                match n {
-
-                    // VX is set to the value of VY
-                    0 => {
-                        self.registers[x] = self.registers[y];
-                        log::info!("Set the value of register {x} to the value of the register {y}")
-                    },
-
-                    // VX is set to the bitwise (OR) of VX and VY. VY is not affected.
-                    1 => {
-                        self.registers[x] |= self.registers[y];
-                        log::info!("Set the register {x} to the bitwise OR of register {x} and register {y}")
-                    },
+            // 0x8XY0 - VX is set to the value of VY
+            LdReg(..) => {
+                self.registers[x] = self.registers[y];
+                log::info!("Set the value of register {x} to the value of the register {y}")
+            },
 
-                    // VX is set to the bitwise (AND) of VX and VY. VY is not affected.
-                    2 => {
-                        self.registers[x] &= self.registers[y];
-                        log::info!("Set the register {x} to the bitwise AND of register {x} and register {y}")
-                    },
+            // 0x8XY1 - VX is set to the bitwise (OR) of VX and VY. VY is not affected.
+            Or(..) => {
+                self.registers[x] |= self.registers[y];
+                log::info!("Set the register {x} to the bitwise OR of register {x} and register {y}")
+            },
 
-                    // VX is set to the bitwise (XOR) of VX and VY. VY is not affected.
-                    3 => {
-                        self.registers[x] ^= self.registers[y];
-                        log::info!("Set the register {x} to the bitwise XOR or register {x} and register {y}")
-                    },
+            // 0x8XY2 - VX is set to the bitwise (AND) of VX and VY. VY is not affected.
+            And(..) => {
+                self.registers[x] &= self.registers[y];
+                log::info!("Set the register {x} to the bitwise AND of register {x} and register {y}")
+            },
 
-                    // VX is set to the value of VX plus the value of VY. VY is not affected.
-                    4 => {
-                        let (result, is_overflow) = self.registers[x]
-                            .overflowing_add(self.registers[y]);
-                        self.registers[x] = result;
-                        self.registers[15] = is_overflow as u8;
-                        log::info!("Set the register {x} to the sum of register {x} and register {y}")
-                    },
+            // 0x8XY3 - VX is set to the bitwise (XOR) of VX and VY. VY is not affected.
+            Xor(..) => {
+                self.registers[x] ^= self.registers[y];
+                log::info!("Set the register {x} to the bitwise XOR or register {x} and register {y}")
+            },
 
-                    // VX is set to the result of VX - VY
-                    5 => {
-                        let (result, is_overflow) = self.registers[x]
-                            .overflowing_sub(self.registers[y]);
-                        self.registers[x] = result;
-                        self.registers[15] = 1 - (is_overflow as u8);
-                        log::info!("Set the register {x} to the result of subtracting register {y} from register {x}")
-                    }
+            // 0x8XY4 - VX is set to the value of VX plus the value of VY. VY is not affected.
+            AddReg(..) => {
+                let (result, is_overflow) = self.registers[x]
+                    .overflowing_add(self.registers[y]);
+                self.registers[x] = result;
+                self.registers[15] = is_overflow as u8;
+                log::info!("Set the register {x} to the sum of register {x} and register {y}")
+            },
 
-                    // Sets VX equal to VY and shifts it one bit to the right. VF is set to the
-                    // shifted out bit
-                    6 => {
-                        self.registers[x] = self.registers[y];
-                        let shifted_out = self.registers[x] % 2;
-                        self.registers[x] >>= 1;
-                        self.registers[15] = shifted_out;
-                        log::info!("Set the register {x} to the register {y} shifted one bit to the right")
-                    },
+            // 0x8XY5 - VX is set to the result of VX - VY
+            Sub(..) => {
+                let (result, is_overflow) = self.registers[x]
+                    .overflowing_sub(self.registers[y]);
+                self.registers[x] = result;
+                self.registers[15] = 1 - (is_overflow as u8);
+                log::info!("Set the register {x} to the result of subtracting register {y} from register {x}")
+            }
 
-                    // VX is set to the result of VY - VX
-                    7 => {
-                        let (result, is_overflow) = self.registers[y]
-                            .overflowing_sub(self.registers[x]);
-                        self.registers[x] = result;
-                        self.registers[15] = 1 - (is_overflow as u8);
-                        log::info!("Set the register {x} to the result of subtracting register {x} from register {y}")
-                    },
+            // 0x8XY6 - Sets VX equal to VY and shifts it one bit to the right. VF is set to the
+            // shifted out bit. SCHIP shifts VX in place
+            Shr(..) => {
+                if !self.quirks.superchip_shift {
+                    self.registers[x] = self.registers[y];
+                }
+                let shifted_out = self.registers[x] % 2;
+                self.registers[x] >>= 1;
+                self.registers[15] = shifted_out;
+                log::info!("Set the register {x} to the register {y} shifted one bit to the right")
+            },
 
-                    // Sets VX equal to VY and shifts it one bit to the left. VF is set to the
-                    // shifted out bit
-                    0xE => {
-                        self.registers[x] = self.registers[y];
-                        let shifted_out = (self.registers[x] >= 128) as u8;
-                        self.registers[x] <<= 1;
-                        self.registers[15] = shifted_out;
-                        log::info!("Set the register {x} to the register {y} shifted one bit to the left")
-                    },
+            // 0x8XY7 - VX is set to the result of VY - VX
+            Subn(..) => {
+                let (result, is_overflow) = self.registers[y]
+                    .overflowing_sub(self.registers[x]);
+                self.registers[x] = result;
+                self.registers[15] = 1 - (is_overflow as u8);
+                log::info!("Set the register {x} to the result of subtracting register {x} from register {y}")
+            },
 
-                    _ => {
-                        return Err(EmulationErr::UnknownOpcode(self.opcode))
-                    }
+            // 0x8XYE - Sets VX equal to VY and shifts it one bit to the left. VF is set to the
+            // shifted out bit. SCHIP shifts VX in place
+            Shl(..) => {
+                if !self.quirks.superchip_shift {
+                    self.registers[x] = self.registers[y];
                 }
+                let shifted_out = (self.registers[x] >= 128) as u8;
+                self.registers[x] <<= 1;
+                self.registers[15] = shifted_out;
+                log::info!("Set the register {x} to the register {y} shifted one bit to the left")
             },
 
-            // 0x5XY0 - Skip one instruction if the value in VX is not equal to value in VY
-            0x9000..=0x9FF0 => {
+            // 0x9XY0 - Skip one instruction if the value in VX is not equal to value in VY
+            SneReg(..) => {
                 if self.registers[x] != self.registers[y] {
                     self.program_counter += 2;
                     log::info!("Skipped instruction at 0x{:0>3X}", self.program_counter);
@@ -399,30 +587,35 @@ impl Chip8Emu {
             },
 
             // 0xANNN - Set index register to NNN
-            0xA000..=0xAFFF => {
+            LdI(_) => {
                 self.index_register = nnn;
                 log::log!(Level::Info, "Set index register to 0x{:0>3X}", nnn);
             },
 
-            // 0xBNNN - Jump with offset of NNN
-            0xB000..=0xBFFF => {
-                self.program_counter = nnn + self.registers[0] as u16;
+            // 0xBNNN - Jump to NNN plus V0. SCHIP jumps to XNN plus VX instead
+            JpV0(_) => {
+                let offset = if self.quirks.superchip_offset_jump { self.registers[x] } else { self.registers[0] };
+                self.program_counter = nnn + offset as u16;
                 log::info!("Jumped to the 0x{:0>3X}", self.program_counter)
             },
 
             // 0xCXNN - Put random value with mask NN into VX
-            0xC000..=0xCFFF => {
-                let mut rng = rand::thread_rng();
-                self.registers[x] = rng.gen_range(0..=255) & nn;
+            Rnd(..) => {
+                self.registers[x] = self.rng.next_byte(&self.memory) & nn;
                 log::info!("Set the register {x} to the random value of {}", self.registers[x])
             }
 
             // 0xDXYN - Draw N bytes starting at memory address in index register at (VX, VY)
-            0xD000..=0xDFFF => {
+            Drw(..) => {
                 let cx: u8 = self.registers[x] & 0x3F;
                 let cy: u8 = self.registers[y] & 0x1F;
                 self.registers[0xF] = 0x00;
 
+                // DXY0 draws nothing, not even the 16x16 sprite of SCHIP, so there is nothing to record
+                if n > 0 {
+                    self.sprites.record(Sprite { address: self.index_register, height: n, wide: false }, &self.memory);
+                }
+
                 for row in 0..n as u16 {
                     let row_data: u8 = self.memory[(self.index_register + row) as usize];
                     let screen_byte_index = cy * 8 + cx.div(8) + (row * 8) as u8;
@@ -464,7 +657,7 @@ impl Chip8Emu {
             },
 
             // 0xEX9E - Skip if key VX is pressed
-            opcode if opcode & 0xF0FF == 0xE09E => {
+            Skp(_) => {
                 if self.keys[(self.registers[x] & 0x0F) as usize] {
                     self.program_counter += 2;
                     log::info!("Skipped to 0x{:0>3X} as the key {x} was pressed", self.program_counter)
@@ -472,7 +665,7 @@ impl Chip8Emu {
             },
 
             // 0xEXA1 - Skip if key VX is not pressed
-            opcode if opcode & 0xF0FF == 0xE0A1 => {
+            Sknp(_) => {
                 if !self.keys[(self.registers[x] & 0x0F) as usize] {
                     self.program_counter += 2;
                     log::info!("Skipped to 0x{:0>3X} as the key {x} was not pressed", self.program_counter)
@@ -480,25 +673,25 @@ impl Chip8Emu {
             },
 
             // 0xFX07 - Set VX to the current value of the delay timer
-            opcode if opcode & 0xF0FF == 0xF007 => {
+            LdVxDt(_) => {
                 self.registers[x] = self.delay_timer;
                 log::info!("Set register {x} to the value of delay timer {}", self.delay_timer)
             },
 
             // 0xFX15 - Set the delay timer to VX
-            opcode if opcode & 0xF0FF == 0xF015 => {
+            LdDtVx(_) => {
                 self.delay_timer = self.registers[x];
                 log::info!("Set the delay timer to the value of register {x} - {}", self.delay_timer)
             },
 
             // 0xFX18 - Set the sound timer to VX
-            opcode if opcode & 0xF0FF == 0xF018 => {
+            LdStVx(_) => {
                 self.sound_timer = self.registers[x];
                 log::info!("Set the sound timer to the value of register {x} - {}", self.delay_timer)
             },
 
             // 0xFX1E - Add the value in VX to the index register
-            opcode if opcode & 0xF0FF == 0xF01E => {
+            AddIVx(_) => {
                 self.index_register += self.registers[x] as u16;
                 if self.index_register > 4095 {
                     self.registers[15] = 0x01;
@@ -509,22 +702,29 @@ impl Chip8Emu {
                 log::info!("Added the value from register {x} to the index register")
             },
 
-            // 0xFX0A - Wait for a key press and store it in VX
-            opcode if opcode & 0xF0FF == 0xF00A => {
-                log::info!("Waiting for a key press at 0x{:0>3X}", self.program_counter);
-                if self.keys.iter().any(|x| { *x }) {
-                    let (index, value) = self.keys.iter()
-                        .find_position(|x| { **x }).unwrap();
-                    self.registers[x] = index as u8;
-                    log::info!("Captured keypress: {index}")
-                } else {
-                    self.program_counter -= 2;
+            // 0xFX0A - Wait for a key to be pressed and released and store it in VX
+            LdVxK(_) => {
+                match self.awaited_key {
+                    Some(key) if !self.keys[key as usize] => {
+                        self.awaited_key = None;
+                        self.registers[x] = key;
+                        log::info!("Captured keypress: {key}")
+                    },
+                    Some(_) => {
+                        self.program_counter -= 2;
+                    },
+                    None => {
+                        log::info!("Waiting for a key press at 0x{:0>3X}", self.program_counter);
+                        if let Some((index, _)) = self.keys.iter().find_position(|x| { **x }) {
+                            self.awaited_key = Some(index as u8);
+                        }
+                        self.program_counter -= 2;
+                    },
                 }
-                
             },
 
             // 0xFX29 - Set the index register to the position of the hexadecimal character in VX
-            opcode if opcode & 0xF0FF == 0xF029 => {
+            LdFVx(_) => {
                 self.index_register = match self.registers[x] {
                     0x0 => { 0x0050 },
                     0x1 => { 0x0055 },
@@ -549,7 +749,7 @@ impl Chip8Emu {
             },
 
             // 0xFX33 - Store the Binary-coded decimal value of VX starting at index register
-            opcode if opcode & 0xF0FF == 0xF033 => {
+            LdBVx(_) => {
                 self.memory[self.index_register as usize] = self.registers[x].div(100);
                 self.memory[self.index_register as usize + 1] = (self.registers[x] % 100).div(10);
                 self.memory[self.index_register as usize + 2] = self.registers[x] % 10;
@@ -557,19 +757,20 @@ impl Chip8Emu {
                     self.registers[x], self.index_register)
             },
             
-            // 0xFX55 - Store V0 - VX into memory
-            opcode if opcode & 0xF0FF == 0xF055 => {
+            // 0xFX55 - Store V0 - VX into memory. I is left after them, except on SCHIP
+            StoreRegs(_) => {
                 for offset in 0..=x {
                     self.memory[
                         (self.index_register + offset as u16) as usize
                         ] = self.registers[offset]
                 }
                 log::info!("Saved values {:?} into memory starting at 0x{:0>3X}",
-                    &self.registers[0..=x], self.index_register)
+                    &self.registers[0..=x], self.index_register);
+                self.advance_index_register(x);
             },
             
-            // 0xFX65 - Load into V0 - VX from memory
-            opcode if opcode & 0xF0FF == 0xF065 => {
+            // 0xFX65 - Load into V0 - VX from memory. I is left after them, except on SCHIP
+            LoadRegs(_) => {
                 for offset in 0..=x {
                     self.registers[offset] = self.memory[
                         (self.index_register + offset as u16) as usize
@@ -577,31 +778,39 @@ impl Chip8Emu {
                 }
 
                 log::info!("Loaded values {:?} from memory starting at 0x{:0>3X}",
-                    &self.registers[0..=x], self.index_register)
+                    &self.registers[0..=x], self.index_register);
+                self.advance_index_register(x);
             },
             
-            opcode => {
-                if self.quirks.superchip_opcodes {
-                    return self.handle_superchip_opcode(opcode, x, y, n, nn, nnn)
-                } else {
-                    return Err(EmulationErr::UnknownOpcode(self.opcode))
-                }
+            Scd(_) | Scr | Scl | Exit | Low | High | LdHfVx(_) | StoreRpl(_) | LoadRpl(_) => {
+                return self.handle_superchip_opcode(instruction, x, n).map(|()| instruction)
+            }
+
+            _ => {
+                return Err(EmulationErr::UnknownOpcode(self.opcode))
             }
         }
         log::log!(Level::Info, "Executed opcode: 0x{:0>4X}, registers: {:?}, index register: {}",
             self.opcode, self.registers, self.index_register);
         // Update timers
 
-        Ok(())
+        Ok(instruction)
 
     }
-    fn handle_superchip_opcode(
-        &mut self, opcode: u16, x: usize, y: usize, n: u8, nn: u8, nnn: u16
-    ) -> Result<(), EmulationErr> {
+    /// Moves I past the registers V0 - VX that FX55 or FX65 accessed, unless the memory quirk
+    /// leaves it unchanged
+    fn advance_index_register(&mut self, x: usize) {
+        if !self.quirks.superchip_memory {
+            self.index_register = (self.index_register + x as u16 + 1) & 0x0FFF;
+        }
+    }
 
-        match opcode {
+    fn handle_superchip_opcode(&mut self, instruction: Instruction, x: usize, n: u8) -> Result<(), EmulationErr> {
+        use Instruction::*;
+
+        match instruction {
             // 0x00CN - Scroll display N lines down
-            0x00C0..=0x00CF => {
+            Scd(_) => {
                 self.gfx = [
                     vec![0x00; (8 * n) as usize],
                     self.gfx.clone(),
@@ -609,15 +818,15 @@ impl Chip8Emu {
             }
             
             // 0x00FB - Scroll display 4 pixels right
-            0x00FB => {
+            Scr => {
                 let mut rem: Option<u8>;
                 for row in 0..32 {
                     rem = None;
                     for col in 0..8 {
                         let chunk = self.gfx[row * 8 + col];
                         let new_rem = chunk & 0x0F;
-                        if rem.is_some() {
-                            self.gfx[row * 8 + col] = (chunk >> 4) | (rem.unwrap() << 4);
+                        if let Some(rem) = rem {
+                            self.gfx[row * 8 + col] = (chunk >> 4) | (rem << 4);
                         } else {
                             self.gfx[row * 8 + col] = chunk >> 4;
                         }
@@ -626,20 +835,20 @@ impl Chip8Emu {
                 }
             }
             
-            0x00FD => {
+            Exit => {
                 return Err(EmulationErr::ProgramExited)
             }
 
-            0x00FE => {
+            Low => {
                 // Disable high-resolution mode
             }
 
-            0x00FF => {
+            High => {
                 // Enable high-resolution mode
             }
 
             // 0xFX75 - Store V0..VX in RPL user flags (X <= 7)
-            _ if opcode & 0xF0FF == 0xF075 => {
+            StoreRpl(_) => {
                 if x > 7 {
                     return Err(EmulationErr::InvalidRegisterReference)
                 }
@@ -648,7 +857,7 @@ impl Chip8Emu {
             }
             
             // 0xFX85 - Read V0..VX from RPL user flags (X <= 7)
-            _ if opcode & 0xF0FF == 0xF075 => {
+            LoadRpl(_) => {
                 if x > 7 {
                     return Err(EmulationErr::InvalidRegisterReference)
                 }
@@ -657,10 +866,187 @@ impl Chip8Emu {
             }
 
             _ => {
-                return Err(EmulationErr::UnknownOpcode(opcode))
+                return Err(EmulationErr::UnknownOpcode(self.opcode))
             }
         }
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_fx0a_waits_for_release() {
+        let mut emulator = Chip8Emu::new();
+        // 0x200: V5 = key, 0x202: jump to self
+        emulator.load_rom(vec![0xF5, 0x0A, 0x12, 0x02]).unwrap();
+
+        emulator.emulate_cycle().unwrap();
+        assert_eq!(emulator.get_program_counter(), 0x200);
+
+        emulator.press(&0xB).unwrap();
+        emulator.emulate_cycle().unwrap();
+        emulator.emulate_cycle().unwrap();
+        assert_eq!(emulator.get_program_counter(), 0x200);
+
+        emulator.release(&0xB).unwrap();
+        emulator.emulate_cycle().unwrap();
+        assert_eq!(emulator.get_program_counter(), 0x202);
+        assert_eq!(emulator.registers[5], 0xB);
+    }
+
+    #[test]
+    fn test_fault_stays_on_instruction() {
+        let mut emulator = Chip8Emu::new();
+        // 0x200: V0 = 0x12, 0x202: unknown opcode
+        emulator.load_rom(vec![0x60, 0x12, 0xFF, 0xFF]).unwrap();
+
+        emulator.emulate_cycle().unwrap();
+        let err = emulator.emulate_cycle().unwrap_err();
+        let fault = emulator.fault(err);
+        assert_eq!(fault.opcode, 0xFFFF);
+        assert_eq!(fault.state.program_counter, 0x202);
+        assert_eq!(fault.state.registers[0], 0x12);
+
+        emulator.skip_instruction();
+        assert_eq!(emulator.get_program_counter(), 0x204);
+        emulator.restart();
+        assert_eq!(emulator.get_program_counter(), 0x200);
+        assert_eq!(emulator.get_cpu_state().registers[0], 0x00);
+    }
+
+    #[test]
+    fn test_decodes_like_the_disassembler() {
+        let mut emulator = Chip8Emu::new();
+        // 0x200: 5XY1 isn't a skip on any platform
+        emulator.load_rom(vec![0x50, 0x11]).unwrap();
+        assert!(matches!(emulator.emulate_cycle(), Err(EmulationErr::UnknownOpcode(0x5011))));
+
+        emulator.set_quirks(Quirks { superchip_opcodes: true, ..Quirks::default() });
+        // 0x200: V0 = 0x34, 0x202: store V0 in the RPL flags, 0x204: V0 = 0, 0x206: read V0 back
+        emulator.load_rom(vec![0x60, 0x34, 0xF0, 0x75, 0x60, 0x00, 0xF0, 0x85]).unwrap();
+        for _ in 0..4 {
+            emulator.emulate_cycle().unwrap();
+        }
+        assert_eq!(emulator.registers[0], 0x34);
+    }
+
+    /// Runs `rom` to its end with `quirks`
+    fn run_with_quirks(rom: Vec<u8>, quirks: Quirks) -> Chip8Emu {
+        let mut emulator = Chip8Emu::new();
+        emulator.set_quirks(quirks);
+        let end = 0x200 + rom.len() as u16;
+        emulator.load_rom(rom).unwrap();
+        while emulator.get_program_counter() < end {
+            emulator.emulate_cycle().unwrap();
+        }
+        emulator
+    }
+
+    #[test]
+    fn test_refuses_roms_larger_than_memory() {
+        let mut emulator = Chip8Emu::new();
+        emulator.load_rom(vec![0x12, 0x00]).unwrap();
+        assert!(matches!(emulator.load_rom(vec![0; MAX_PROGRAM_SIZE + 1]), Err(EmulationErr::RomTooLarge(_))));
+        assert_eq!(emulator.get_rom(), &[0x12, 0x00]);
+        emulator.load_rom(vec![0; MAX_PROGRAM_SIZE]).unwrap();
+    }
+
+    #[test]
+    fn test_shift_quirk() {
+        // 0x200: V1 = 0x03, 0x202: V2 = 0x80, 0x204: V1 = V2 >> 1, 0x206: V3 = 0x81, 0x208: V3 = V3 << 1
+        let rom = vec![0x61, 0x03, 0x62, 0x80, 0x81, 0x26, 0x63, 0x81, 0x83, 0x3E];
+        let emulator = run_with_quirks(rom.clone(), Quirks::default());
+        assert_eq!(emulator.registers[1], 0x40);
+        assert_eq!(emulator.registers[3], 0x02);
+        assert_eq!(emulator.registers[0xF], 1);
+
+        let emulator = run_with_quirks(rom, Quirks { superchip_shift: true, ..Quirks::default() });
+        assert_eq!(emulator.registers[1], 0x01);
+        assert_eq!(emulator.registers[3], 0x02);
+    }
+
+    #[test]
+    fn test_offset_jump_quirk() {
+        // 0x200: V0 = 0x02, 0x202: V2 = 0x04, 0x204: jump to 0x206 plus V0 or V2
+        let rom = vec![0x60, 0x02, 0x62, 0x04, 0xB2, 0x06];
+        let mut emulator = Chip8Emu::new();
+        for (quirks, target) in [(Quirks::default(), 0x208), (Quirks { superchip_offset_jump: true, ..Quirks::default() }, 0x20A)] {
+            emulator.set_quirks(quirks);
+            emulator.load_rom(rom.clone()).unwrap();
+            for _ in 0..3 {
+                emulator.emulate_cycle().unwrap();
+            }
+            assert_eq!(emulator.get_program_counter(), target);
+        }
+    }
+
+    #[test]
+    fn test_memory_quirk() {
+        // 0x200: I = 0x300, 0x202: store V0 - V2, 0x204: load V0 - V1
+        let rom = vec![0xA3, 0x00, 0xF2, 0x55, 0xF1, 0x65];
+        let emulator = run_with_quirks(rom.clone(), Quirks::default());
+        assert_eq!(emulator.get_cpu_state().index_register, 0x305);
+
+        let emulator = run_with_quirks(rom, Quirks { superchip_memory: true, ..Quirks::default() });
+        assert_eq!(emulator.get_cpu_state().index_register, 0x300);
+    }
+
+    #[test]
+    fn test_restart_keeps_rpl_flags() {
+        let mut emulator = Chip8Emu::new();
+        emulator.set_quirks(Quirks { superchip_opcodes: true, ..Quirks::default() });
+        // 0x200: V0 = 0x34, 0x202: store V0 in the RPL flags
+        emulator.load_rom(vec![0x60, 0x34, 0xF0, 0x75]).unwrap();
+        emulator.emulate_cycle().unwrap();
+        emulator.emulate_cycle().unwrap();
+
+        emulator.restart();
+        assert_eq!(emulator.get_cpu_state().registers[0], 0x00);
+        assert_eq!(emulator.rpl[0], 0x34);
+    }
+
+    #[test]
+    fn test_records_drawn_sprites() {
+        let mut emulator = Chip8Emu::new();
+        // 0x200: I = 0x050, 0x202: draw 5 rows, 0x204: draw 5 rows again
+        emulator.load_rom(vec![0xA0, 0x50, 0xD0, 0x05, 0xD0, 0x05]).unwrap();
+        for _ in 0..3 {
+            emulator.emulate_cycle().unwrap();
+        }
+        let sprite = Sprite { address: 0x050, height: 5, wide: false };
+        assert_eq!(emulator.get_sprites().recent(), vec![sprite]);
+
+        emulator.restart();
+        assert_eq!(emulator.get_sprites().recent(), vec![sprite]);
+        emulator.load_rom(vec![0x00, 0xE0]).unwrap();
+        assert!(emulator.get_sprites().is_empty());
+
+        // 0x200: I = 0x050, 0x202: draw the 16x16 sprite of SCHIP
+        emulator.set_quirks(Quirks { superchip_opcodes: true, ..Quirks::default() });
+        emulator.load_rom(vec![0xA0, 0x50, 0xD0, 0x00]).unwrap();
+        emulator.emulate_cycle().unwrap();
+        emulator.emulate_cycle().unwrap();
+        assert!(emulator.get_sprites().is_empty());
+    }
+
+    #[test]
+    fn test_cheats_are_applied_every_frame() {
+        let mut emulator = Chip8Emu::new();
+        // 0x200: I = 0x300, 0x202: store V0 at 0x300
+        emulator.load_rom(vec![0xA3, 0x00, 0xF0, 0x55]).unwrap();
+        emulator.set_cheats(vec![Cheat { address: 0x300, value: 9 }]);
+        assert_eq!(emulator.memory[0x300], 9);
+
+        emulator.emulate_cycle().unwrap();
+        emulator.emulate_cycle().unwrap();
+        assert_eq!(emulator.memory[0x300], 0);
+        emulator.advance_frame();
+        assert_eq!(emulator.memory[0x300], 9);
+    }
+}
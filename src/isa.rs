@@ -0,0 +1,424 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+/// Instruction sets, each one a superset of the previous
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, Display,
+    clap::ValueEnum)]
+pub enum InstructionSet {
+    #[default]
+    #[value(name = "chip8")]
+    #[strum(serialize = "CHIP-8")]
+    Chip8,
+    #[value(name = "schip")]
+    #[strum(serialize = "SCHIP")]
+    Schip,
+    #[value(name = "xochip")]
+    #[strum(serialize = "XO-CHIP")]
+    XoChip,
+}
+
+impl InstructionSet {
+    /// Highest address a program of this instruction set can use
+    pub fn max_address(self) -> usize {
+        match self {
+            InstructionSet::Chip8 | InstructionSet::Schip => 0xFFF,
+            InstructionSet::XoChip => 0xFFFF,
+        }
+    }
+}
+
+/// A decoded instruction. `x` and `y` are register indices, `nnn` addresses, `nn` bytes and `n`
+/// nibbles
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Instruction {
+    /// 0NNN - Call a machine code routine
+    Sys(u16),
+    /// 00E0
+    Cls,
+    /// 00EE
+    Ret,
+    /// 1NNN
+    Jp(u16),
+    /// 2NNN
+    Call(u16),
+    /// 3XNN
+    SeImm(u8, u8),
+    /// 4XNN
+    SneImm(u8, u8),
+    /// 5XY0
+    SeReg(u8, u8),
+    /// 6XNN
+    LdImm(u8, u8),
+    /// 7XNN
+    AddImm(u8, u8),
+    /// 8XY0
+    LdReg(u8, u8),
+    /// 8XY1
+    Or(u8, u8),
+    /// 8XY2
+    And(u8, u8),
+    /// 8XY3
+    Xor(u8, u8),
+    /// 8XY4
+    AddReg(u8, u8),
+    /// 8XY5
+    Sub(u8, u8),
+    /// 8XY6
+    Shr(u8, u8),
+    /// 8XY7
+    Subn(u8, u8),
+    /// 8XYE
+    Shl(u8, u8),
+    /// 9XY0
+    SneReg(u8, u8),
+    /// ANNN
+    LdI(u16),
+    /// BNNN
+    JpV0(u16),
+    /// CXNN
+    Rnd(u8, u8),
+    /// DXYN
+    Drw(u8, u8, u8),
+    /// EX9E
+    Skp(u8),
+    /// EXA1
+    Sknp(u8),
+    /// FX07
+    LdVxDt(u8),
+    /// FX0A
+    LdVxK(u8),
+    /// FX15
+    LdDtVx(u8),
+    /// FX18
+    LdStVx(u8),
+    /// FX1E
+    AddIVx(u8),
+    /// FX29
+    LdFVx(u8),
+    /// FX33
+    LdBVx(u8),
+    /// FX55
+    StoreRegs(u8),
+    /// FX65
+    LoadRegs(u8),
+    /// 00CN - SCHIP
+    Scd(u8),
+    /// 00FB - SCHIP
+    Scr,
+    /// 00FC - SCHIP
+    Scl,
+    /// 00FD - SCHIP
+    Exit,
+    /// 00FE - SCHIP
+    Low,
+    /// 00FF - SCHIP
+    High,
+    /// FX30 - SCHIP
+    LdHfVx(u8),
+    /// FX75 - SCHIP
+    StoreRpl(u8),
+    /// FX85 - SCHIP
+    LoadRpl(u8),
+    /// 00DN - XO-CHIP
+    Scu(u8),
+    /// 5XY2 - XO-CHIP
+    SaveRange(u8, u8),
+    /// 5XY3 - XO-CHIP
+    LoadRange(u8, u8),
+    /// F000 NNNN - XO-CHIP
+    LdILong(u16),
+    /// FN01 - XO-CHIP
+    Plane(u8),
+    /// F002 - XO-CHIP
+    Audio,
+    /// FX3A - XO-CHIP
+    Pitch(u8),
+}
+
+impl Instruction {
+    /// Decodes `opcode`, reading the second word of 4-byte instructions from `next`. Returns none
+    /// for opcodes that aren't part of `set`
+    pub fn decode(opcode: u16, next: Option<u16>, set: InstructionSet) -> Option<Self> {
+        use Instruction::*;
+
+        let x = ((opcode & 0x0F00) >> 8) as u8;
+        let y = ((opcode & 0x00F0) >> 4) as u8;
+        let n = (opcode & 0x000F) as u8;
+        let nn = (opcode & 0x00FF) as u8;
+        let nnn = opcode & 0x0FFF;
+
+        let instruction = match opcode {
+            0x00E0 => Cls,
+            0x00EE => Ret,
+            0x00FB => Scr,
+            0x00FC => Scl,
+            0x00FD => Exit,
+            0x00FE => Low,
+            0x00FF => High,
+            0x00C0..=0x00CF => Scd(n),
+            0x00D0..=0x00DF => Scu(n),
+            0x0000..=0x0FFF => Sys(nnn),
+            0x1000..=0x1FFF => Jp(nnn),
+            0x2000..=0x2FFF => Call(nnn),
+            0x3000..=0x3FFF => SeImm(x, nn),
+            0x4000..=0x4FFF => SneImm(x, nn),
+            0x5000..=0x5FFF => match n {
+                0x0 => SeReg(x, y),
+                0x2 => SaveRange(x, y),
+                0x3 => LoadRange(x, y),
+                _ => return None,
+            },
+            0x6000..=0x6FFF => LdImm(x, nn),
+            0x7000..=0x7FFF => AddImm(x, nn),
+            0x8000..=0x8FFF => match n {
+                0x0 => LdReg(x, y),
+                0x1 => Or(x, y),
+                0x2 => And(x, y),
+                0x3 => Xor(x, y),
+                0x4 => AddReg(x, y),
+                0x5 => Sub(x, y),
+                0x6 => Shr(x, y),
+                0x7 => Subn(x, y),
+                0xE => Shl(x, y),
+                _ => return None,
+            },
+            0x9000..=0x9FFF if n == 0 => SneReg(x, y),
+            0xA000..=0xAFFF => LdI(nnn),
+            0xB000..=0xBFFF => JpV0(nnn),
+            0xC000..=0xCFFF => Rnd(x, nn),
+            0xD000..=0xDFFF => Drw(x, y, n),
+            0xE000..=0xEFFF => match nn {
+                0x9E => Skp(x),
+                0xA1 => Sknp(x),
+                _ => return None,
+            },
+            0xF000..=0xFFFF => match nn {
+                0x00 if x == 0 => LdILong(next?),
+                0x01 => Plane(x),
+                0x02 if x == 0 => Audio,
+                0x07 => LdVxDt(x),
+                0x0A => LdVxK(x),
+                0x15 => LdDtVx(x),
+                0x18 => LdStVx(x),
+                0x1E => AddIVx(x),
+                0x29 => LdFVx(x),
+                0x30 => LdHfVx(x),
+                0x33 => LdBVx(x),
+                0x3A => Pitch(x),
+                0x55 => StoreRegs(x),
+                0x65 => LoadRegs(x),
+                0x75 => StoreRpl(x),
+                0x85 => LoadRpl(x),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        if instruction.instruction_set() <= set {
+            Some(instruction)
+        } else if opcode < 0x1000 {
+            // Later instruction sets took over some machine code calls
+            Some(Sys(nnn))
+        } else {
+            None
+        }
+    }
+
+    /// The first instruction set that has this instruction
+    pub fn instruction_set(&self) -> InstructionSet {
+        use Instruction::*;
+
+        match self {
+            Scd(_) | Scr | Scl | Exit | Low | High | LdHfVx(_) | StoreRpl(_) | LoadRpl(_) => InstructionSet::Schip,
+            Scu(_) | SaveRange(..) | LoadRange(..) | LdILong(_) | Plane(_) | Audio | Pitch(_) => InstructionSet::XoChip,
+            _ => InstructionSet::Chip8,
+        }
+    }
+
+    /// Size of the instruction in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::LdILong(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /// The words of the instruction
+    pub fn encode(&self) -> Vec<u16> {
+        use Instruction::*;
+
+        let xy = |base: u16, x: u8, y: u8| base | (x as u16) << 8 | (y as u16) << 4;
+        let xnn = |base: u16, x: u8, nn: u8| base | (x as u16) << 8 | nn as u16;
+        let fx = |x: u8, nn: u16| 0xF000 | (x as u16) << 8 | nn;
+
+        let opcode = match *self {
+            Sys(nnn) => nnn & 0x0FFF,
+            Cls => 0x00E0,
+            Ret => 0x00EE,
+            Jp(nnn) => 0x1000 | (nnn & 0x0FFF),
+            Call(nnn) => 0x2000 | (nnn & 0x0FFF),
+            SeImm(x, nn) => xnn(0x3000, x, nn),
+            SneImm(x, nn) => xnn(0x4000, x, nn),
+            SeReg(x, y) => xy(0x5000, x, y),
+            LdImm(x, nn) => xnn(0x6000, x, nn),
+            AddImm(x, nn) => xnn(0x7000, x, nn),
+            LdReg(x, y) => xy(0x8000, x, y),
+            Or(x, y) => xy(0x8001, x, y),
+            And(x, y) => xy(0x8002, x, y),
+            Xor(x, y) => xy(0x8003, x, y),
+            AddReg(x, y) => xy(0x8004, x, y),
+            Sub(x, y) => xy(0x8005, x, y),
+            Shr(x, y) => xy(0x8006, x, y),
+            Subn(x, y) => xy(0x8007, x, y),
+            Shl(x, y) => xy(0x800E, x, y),
+            SneReg(x, y) => xy(0x9000, x, y),
+            LdI(nnn) => 0xA000 | (nnn & 0x0FFF),
+            JpV0(nnn) => 0xB000 | (nnn & 0x0FFF),
+            Rnd(x, nn) => xnn(0xC000, x, nn),
+            Drw(x, y, n) => xy(0xD000, x, y) | (n & 0xF) as u16,
+            Skp(x) => fx(x, 0x9E) & 0xEFFF,
+            Sknp(x) => fx(x, 0xA1) & 0xEFFF,
+            LdVxDt(x) => fx(x, 0x07),
+            LdVxK(x) => fx(x, 0x0A),
+            LdDtVx(x) => fx(x, 0x15),
+            LdStVx(x) => fx(x, 0x18),
+            AddIVx(x) => fx(x, 0x1E),
+            LdFVx(x) => fx(x, 0x29),
+            LdBVx(x) => fx(x, 0x33),
+            StoreRegs(x) => fx(x, 0x55),
+            LoadRegs(x) => fx(x, 0x65),
+            Scd(n) => 0x00C0 | (n & 0xF) as u16,
+            Scr => 0x00FB,
+            Scl => 0x00FC,
+            Exit => 0x00FD,
+            Low => 0x00FE,
+            High => 0x00FF,
+            LdHfVx(x) => fx(x, 0x30),
+            StoreRpl(x) => fx(x, 0x75),
+            LoadRpl(x) => fx(x, 0x85),
+            Scu(n) => 0x00D0 | (n & 0xF) as u16,
+            SaveRange(x, y) => xy(0x5002, x, y),
+            LoadRange(x, y) => xy(0x5003, x, y),
+            LdILong(nnnn) => return vec![0xF000, nnnn],
+            Plane(n) => fx(n, 0x01),
+            Audio => 0xF002,
+            Pitch(x) => fx(x, 0x3A),
+        };
+        vec![opcode]
+    }
+
+    /// The instruction as big-endian bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.encode().into_iter().flat_map(u16::to_be_bytes).collect()
+    }
+
+    /// Address the instruction jumps to or calls, if it's known without running the program
+    pub fn target(&self) -> Option<u16> {
+        match *self {
+            Instruction::Jp(nnn) | Instruction::Call(nnn) => Some(nnn),
+            _ => None,
+        }
+    }
+
+    /// Writes the instruction in assembler syntax, formatting addresses with `address`
+    pub fn format_with(&self, address: impl Fn(u16) -> String) -> String {
+        use Instruction::*;
+
+        let v = |x: u8| format!("V{:X}", x);
+        match *self {
+            Sys(nnn) => format!("SYS {}", address(nnn)),
+            Cls => "CLS".to_string(),
+            Ret => "RET".to_string(),
+            Jp(nnn) => format!("JP {}", address(nnn)),
+            Call(nnn) => format!("CALL {}", address(nnn)),
+            SeImm(x, nn) => format!("SE {}, 0x{:02X}", v(x), nn),
+            SneImm(x, nn) => format!("SNE {}, 0x{:02X}", v(x), nn),
+            SeReg(x, y) => format!("SE {}, {}", v(x), v(y)),
+            LdImm(x, nn) => format!("LD {}, 0x{:02X}", v(x), nn),
+            AddImm(x, nn) => format!("ADD {}, 0x{:02X}", v(x), nn),
+            LdReg(x, y) => format!("LD {}, {}", v(x), v(y)),
+            Or(x, y) => format!("OR {}, {}", v(x), v(y)),
+            And(x, y) => format!("AND {}, {}", v(x), v(y)),
+            Xor(x, y) => format!("XOR {}, {}", v(x), v(y)),
+            AddReg(x, y) => format!("ADD {}, {}", v(x), v(y)),
+            Sub(x, y) => format!("SUB {}, {}", v(x), v(y)),
+            Shr(x, y) => format!("SHR {}, {}", v(x), v(y)),
+            Subn(x, y) => format!("SUBN {}, {}", v(x), v(y)),
+            Shl(x, y) => format!("SHL {}, {}", v(x), v(y)),
+            SneReg(x, y) => format!("SNE {}, {}", v(x), v(y)),
+            LdI(nnn) => format!("LD I, {}", address(nnn)),
+            JpV0(nnn) => format!("JP V0, {}", address(nnn)),
+            Rnd(x, nn) => format!("RND {}, 0x{:02X}", v(x), nn),
+            Drw(x, y, n) => format!("DRW {}, {}, {}", v(x), v(y), n),
+            Skp(x) => format!("SKP {}", v(x)),
+            Sknp(x) => format!("SKNP {}", v(x)),
+            LdVxDt(x) => format!("LD {}, DT", v(x)),
+            LdVxK(x) => format!("LD {}, K", v(x)),
+            LdDtVx(x) => format!("LD DT, {}", v(x)),
+            LdStVx(x) => format!("LD ST, {}", v(x)),
+            AddIVx(x) => format!("ADD I, {}", v(x)),
+            LdFVx(x) => format!("LD F, {}", v(x)),
+            LdBVx(x) => format!("LD B, {}", v(x)),
+            StoreRegs(x) => format!("LD [I], {}", v(x)),
+            LoadRegs(x) => format!("LD {}, [I]", v(x)),
+            Scd(n) => format!("SCD {}", n),
+            Scr => "SCR".to_string(),
+            Scl => "SCL".to_string(),
+            Exit => "EXIT".to_string(),
+            Low => "LOW".to_string(),
+            High => "HIGH".to_string(),
+            LdHfVx(x) => format!("LD HF, {}", v(x)),
+            StoreRpl(x) => format!("LD R, {}", v(x)),
+            LoadRpl(x) => format!("LD {}, R", v(x)),
+            Scu(n) => format!("SCU {}", n),
+            SaveRange(x, y) => format!("SAVE {} - {}", v(x), v(y)),
+            LoadRange(x, y) => format!("LOAD {} - {}", v(x), v(y)),
+            LdILong(nnnn) => format!("LD I, LONG {}", address(nnnn)),
+            Plane(n) => format!("PLANE {}", n),
+            Audio => "AUDIO".to_string(),
+            Pitch(x) => format!("PITCH {}", v(x)),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format_with(|address| format!("0x{:03X}", address)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_decode_encode_round_trip() {
+        for opcode in 0..=0xFFFF_u16 {
+            if let Some(instruction) = Instruction::decode(opcode, Some(0x1234), InstructionSet::XoChip) {
+                assert_eq!(instruction.encode()[0], opcode, "{instruction}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_respects_instruction_set() {
+        assert_eq!(Instruction::decode(0x00FF, None, InstructionSet::Chip8), Some(Instruction::Sys(0x0FF)));
+        assert_eq!(Instruction::decode(0x00FF, None, InstructionSet::Schip), Some(Instruction::High));
+        assert_eq!(Instruction::decode(0xF301, None, InstructionSet::Schip), None);
+        assert_eq!(Instruction::decode(0xF301, None, InstructionSet::XoChip), Some(Instruction::Plane(3)));
+        assert_eq!(Instruction::decode(0xF000, Some(0xABCD), InstructionSet::XoChip), Some(Instruction::LdILong(0xABCD)));
+        assert_eq!(Instruction::decode(0xF000, None, InstructionSet::XoChip), None);
+        assert_eq!(Instruction::decode(0x8008, None, InstructionSet::XoChip), None);
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(Instruction::Drw(1, 2, 5).to_string(), "DRW V1, V2, 5");
+        assert_eq!(Instruction::Jp(0x208).to_string(), "JP 0x208");
+        assert_eq!(Instruction::Call(0x208).format_with(|_| "sub".to_string()), "CALL sub");
+        assert_eq!(Instruction::SaveRange(1, 3).to_string(), "SAVE V1 - V3");
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+
+use crossterm::event::KeyCode;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter, IntoEnumIterator};
+
+use crate::config::parse_key_sequence;
+
+/// Keys of the COSMAC VIP hex keypad in the order they are laid out:
+///
+/// ```text
+/// 1 2 3 C
+/// 4 5 6 D
+/// 7 8 9 E
+/// A 0 B F
+/// ```
+pub const LAYOUT: [u8; 16] = [0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF];
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Display, EnumIter)]
+pub enum KeypadPreset {
+  /// The left side of a QWERTY keyboard, 1234/QWER/ASDF/ZXCV
+  #[default]
+  Qwerty,
+  /// The same physical keys on an AZERTY keyboard
+  Azerty,
+  /// The same physical keys on a Dvorak keyboard
+  Dvorak,
+  /// Digits map to themselves, / * - + Enter . to A-F
+  Numpad,
+}
+
+impl KeypadPreset {
+  /// Keyboard keys of the preset, in the order of [`LAYOUT`] for the positional presets
+  fn keys(self) -> Vec<(KeyCode, u8)> {
+    let positional = |chars: [char; 16]| chars.iter().zip(LAYOUT).map(|(c, key)| (KeyCode::Char(*c), key)).collect();
+    match self {
+      KeypadPreset::Qwerty => {
+        positional(['1', '2', '3', '4', 'q', 'w', 'e', 'r', 'a', 's', 'd', 'f', 'z', 'x', 'c', 'v'])
+      },
+      KeypadPreset::Azerty => {
+        positional(['&', 'é', '"', '\'', 'a', 'z', 'e', 'r', 'q', 's', 'd', 'f', 'w', 'x', 'c', 'v'])
+      },
+      KeypadPreset::Dvorak => {
+        positional(['1', '2', '3', '4', '\'', ',', '.', 'p', 'a', 'o', 'e', 'u', ';', 'q', 'j', 'k'])
+      },
+      KeypadPreset::Numpad => {
+        let mut keys: Vec<(KeyCode, u8)> =
+          ('0'..='9').zip(0..).map(|(c, key)| (KeyCode::Char(c), key)).collect();
+        keys.extend([
+          (KeyCode::Char('/'), 0xA),
+          (KeyCode::Char('*'), 0xB),
+          (KeyCode::Char('-'), 0xC),
+          (KeyCode::Char('+'), 0xD),
+          (KeyCode::Enter, 0xE),
+          (KeyCode::Char('.'), 0xF),
+        ]);
+        keys
+      },
+    }
+  }
+}
+
+/// Keypad configuration of a single ROM, applied over the global one
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeypadOverride {
+  #[serde(default)]
+  pub preset: Option<KeypadPreset>,
+  #[serde(default)]
+  pub keys: HashMap<String, String>,
+}
+
+impl KeypadOverride {
+  /// The preset with the bindings applied over it
+  pub fn map(&self) -> KeypadMap {
+    let mut map = KeypadMap::from_preset(self.preset.unwrap_or_default());
+    map.bind_all(&self.keys);
+    map
+  }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct KeypadConfig {
+  #[serde(default)]
+  pub preset: KeypadPreset,
+  /// Bindings of single keypad keys, from the hex digit to a key such as `"<Up>"`.
+  /// Replaces whatever the preset binds to that keypad key
+  #[serde(default)]
+  pub keys: HashMap<String, String>,
+  /// Overrides keyed by ROM filename
+  #[serde(default)]
+  pub roms: HashMap<String, KeypadOverride>,
+}
+
+impl KeypadConfig {
+  /// Merges the keypad settings of the ROM `rom_name`, or returns the global ones if no ROM is
+  /// loaded. The keypad of a ROM profile is applied last
+  pub fn effective(&self, rom_name: Option<&str>, profile: Option<&KeypadOverride>) -> KeypadOverride {
+    let mut effective = KeypadOverride { preset: Some(self.preset), keys: HashMap::new() };
+    let rom = rom_name.and_then(|name| self.roms.get(name));
+    for settings in [Some(&KeypadOverride { preset: None, keys: self.keys.clone() }), rom, profile].into_iter().flatten() {
+      effective.preset = settings.preset.or(effective.preset);
+      // Later bindings of a keypad key replace earlier ones however its digit is written
+      effective.keys.extend(settings.keys.iter().map(|(digit, raw)| (digit.to_uppercase(), raw.clone())));
+    }
+    effective
+  }
+
+  /// Builds the keypad map for the ROM `rom_name`, or the global map if no ROM is loaded
+  pub fn resolve(&self, rom_name: Option<&str>, profile: Option<&KeypadOverride>) -> KeypadMap {
+    self.effective(rom_name, profile).map()
+  }
+}
+
+/// Mapping from keyboard keys to the keys of the hex keypad
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeypadMap(HashMap<KeyCode, u8>);
+
+impl KeypadMap {
+  pub fn from_preset(preset: KeypadPreset) -> Self {
+    Self(preset.keys().into_iter().collect())
+  }
+
+  /// Binds `code` to the keypad `key`, replacing any previous binding of that keypad key
+  pub fn bind(&mut self, key: u8, code: KeyCode) {
+    self.0.retain(|_, bound| *bound != key);
+    self.0.insert(normalize(code), key);
+  }
+
+  /// Binds `code` to the keypad `key`, keeping the other bindings of that keypad key
+  pub fn add(&mut self, key: u8, code: KeyCode) {
+    self.0.insert(normalize(code), key);
+  }
+
+  fn bind_all(&mut self, keys: &HashMap<String, String>) {
+    for (digit, raw) in keys {
+      let key = match u8::from_str_radix(digit, 16) {
+        Ok(key) if key < 16 => key,
+        _ => {
+          log::error!("Invalid keypad key `{digit}`, expected a hex digit");
+          continue;
+        },
+      };
+      match parse_key_sequence(raw).as_deref() {
+        Ok([event]) => self.bind(key, event.code),
+        _ => log::error!("Unable to parse keypad binding `{raw}` for key {key:X}"),
+      }
+    }
+  }
+
+  pub fn get(&self, code: KeyCode) -> Option<u8> {
+    self.0.get(&normalize(code)).copied()
+  }
+
+  /// Keyboard keys bound to the keypad `key`
+  pub fn codes_for(&self, key: u8) -> Vec<KeyCode> {
+    self.0.iter().filter(|(_, bound)| **bound == key).map(|(code, _)| *code).collect()
+  }
+}
+
+/// Letters are matched regardless of case, so that Shift or Caps Lock don't disable the keypad
+fn normalize(code: KeyCode) -> KeyCode {
+  match code {
+    KeyCode::Char(c) => KeyCode::Char(c.to_lowercase().next().unwrap_or(c)),
+    code => code,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn test_presets_cover_keypad() {
+    for preset in KeypadPreset::iter() {
+      let map = KeypadMap::from_preset(preset);
+      for key in 0..16 {
+        assert_eq!(map.codes_for(key).len(), 1, "{preset} key {key:X}");
+      }
+    }
+  }
+
+  #[test]
+  fn test_qwerty_layout() {
+    let map = KeypadMap::from_preset(KeypadPreset::Qwerty);
+    assert_eq!(map.get(KeyCode::Char('4')), Some(0xC));
+    assert_eq!(map.get(KeyCode::Char('X')), Some(0x0));
+    assert_eq!(map.get(KeyCode::Char('v')), Some(0xF));
+    assert_eq!(map.get(KeyCode::Char('t')), None);
+  }
+
+  #[test]
+  fn test_overrides() {
+    let config: KeypadConfig = json5::from_str(
+      r#"{
+        "preset": "Azerty",
+        "keys": { "5": "<Up>" },
+        "roms": { "pong.ch8": { "preset": "Numpad", "keys": { "a": "<Left>" } } }
+      }"#,
+    )
+    .unwrap();
+
+    let global = config.resolve(None, None);
+    assert_eq!(global.get(KeyCode::Up), Some(0x5));
+    assert_eq!(global.get(KeyCode::Char('z')), None);
+    assert_eq!(global.get(KeyCode::Char('a')), Some(0x4));
+
+    let rom = config.resolve(Some("pong.ch8"), None);
+    assert_eq!(rom.get(KeyCode::Up), Some(0x5));
+    assert_eq!(rom.get(KeyCode::Char('5')), None);
+    assert_eq!(rom.get(KeyCode::Left), Some(0xA));
+    assert_eq!(rom.get(KeyCode::Char('/')), None);
+
+    let profile = KeypadOverride { preset: Some(KeypadPreset::Qwerty), keys: HashMap::from([("A".into(), "<Right>".into())]) };
+    let effective = config.effective(Some("pong.ch8"), Some(&profile));
+    assert_eq!(effective.preset, Some(KeypadPreset::Qwerty));
+    assert_eq!(effective.keys, HashMap::from([("5".into(), "<Up>".into()), ("A".into(), "<Right>".into())]));
+    let map = effective.map();
+    assert_eq!(map.get(KeyCode::Right), Some(0xA));
+    assert_eq!(map.get(KeyCode::Left), None);
+  }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use ratatui::layout::{Constraint, Layout, Rect};
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+use crate::renderer::RendererKind;
+
+/// Named areas of the terminal that components draw into
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Display)]
+pub enum Region {
+    Screen,
+    Registers,
+    Info,
+    Files,
+    Keypad,
+    Disassembly,
+    Status,
+    /// Only shown in place of another region
+    Log,
+    /// Only shown in place of another region
+    CallGraph,
+    /// Only shown in place of another region
+    Sprites,
+    /// Only shown in place of another region
+    Cheats,
+}
+
+const STATUS_HEIGHT: u16 = 3;
+const REGISTERS_HEIGHT: u16 = 6;
+const INFO_MIN_HEIGHT: u16 = 4;
+const DISASSEMBLY_WIDTH: u16 = 16;
+const SIDE_WIDTH: u16 = 24;
+const FILES_MIN_HEIGHT: u16 = 5;
+const KEYPAD_SIZE: (u16, u16) = (22, 14);
+/// Below this height the status bar is dropped to leave room for the screen
+const STATUS_MIN_TERMINAL_HEIGHT: u16 = 16;
+
+/// Positions of every visible region for a terminal of a given size. Regions that don't fit are
+/// left out, the screen being the last one to go
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AppLayout {
+    regions: HashMap<Region, Rect>,
+}
+
+impl AppLayout {
+    /// Lays out `area` for a `width` x `height` framebuffer drawn with `renderer`
+    pub fn compute(area: Rect, renderer: RendererKind, width: usize, height: usize) -> Self {
+        let mut regions = HashMap::new();
+
+        let mut body = area;
+        if area.height >= STATUS_MIN_TERMINAL_HEIGHT {
+            let [rest, status] = split_vertical(area, Constraint::Fill(1), Constraint::Length(STATUS_HEIGHT));
+            regions.insert(Region::Status, status);
+            body = rest;
+        }
+
+        // The densest renderer needs the least room, so it decides which panels can stay
+        let (min_columns, min_rows) = RendererKind::Braille.size(width, height);
+        let screen_min_width = min_columns + 2;
+        let show_disassembly = body.width >= screen_min_width + DISASSEMBLY_WIDTH;
+        let show_side = body.width >= screen_min_width + DISASSEMBLY_WIDTH + SIDE_WIDTH;
+        let panels_width = if show_disassembly { DISASSEMBLY_WIDTH } else { 0 }
+            + if show_side { SIDE_WIDTH } else { 0 };
+
+        let available_width = body.width - panels_width;
+        let renderer = renderer.resolve(width, height, available_width.saturating_sub(2), body.height.saturating_sub(2));
+        let (columns, rows) = renderer.size(width, height);
+        let screen_width = if show_side { (columns + 2).min(available_width) } else { available_width };
+
+        let [left, rest] = split_horizontal(body, Constraint::Length(screen_width), Constraint::Fill(1));
+        let [side, disassembly] = split_horizontal(
+            rest,
+            Constraint::Fill(1),
+            Constraint::Length(if show_disassembly { DISASSEMBLY_WIDTH } else { 0 })
+        );
+
+        let screen_height = (rows + 2).min(left.height);
+        let [screen, registers] = split_vertical(left, Constraint::Length(screen_height), Constraint::Fill(1));
+        if screen.height >= min_rows + 2 {
+            regions.insert(Region::Screen, screen);
+        }
+        if registers.height >= REGISTERS_HEIGHT {
+            regions.insert(Region::Registers, Rect { height: REGISTERS_HEIGHT, ..registers });
+        }
+        if registers.height >= REGISTERS_HEIGHT + INFO_MIN_HEIGHT {
+            let info = Rect { y: registers.y + REGISTERS_HEIGHT, height: registers.height - REGISTERS_HEIGHT, ..registers };
+            regions.insert(Region::Info, info);
+        }
+
+        if show_disassembly {
+            regions.insert(Region::Disassembly, disassembly);
+        }
+
+        if show_side {
+            let show_keypad = side.height >= KEYPAD_SIZE.1 + FILES_MIN_HEIGHT && side.width >= KEYPAD_SIZE.0;
+            let [files, keypad] = split_vertical(
+                side,
+                Constraint::Fill(1),
+                Constraint::Length(if show_keypad { KEYPAD_SIZE.1 } else { 0 })
+            );
+            if files.height >= FILES_MIN_HEIGHT {
+                regions.insert(Region::Files, files);
+            }
+            if show_keypad {
+                regions.insert(Region::Keypad, keypad);
+            }
+        }
+
+        Self { regions }
+    }
+
+    pub fn get(&self, region: Region) -> Option<Rect> {
+        self.regions.get(&region).copied()
+    }
+
+    /// Makes a hidden `region` visible in place of `replaced`, e.g. to show a focused panel
+    pub fn show_instead_of(&mut self, region: Region, replaced: Region) {
+        if self.regions.contains_key(&region) {
+            return
+        }
+        if let Some(area) = self.regions.remove(&replaced) {
+            self.regions.insert(region, area);
+        }
+    }
+}
+
+fn split_vertical(area: Rect, first: Constraint, second: Constraint) -> [Rect; 2] {
+    let chunks = Layout::vertical(vec![first, second]).split(area);
+    [chunks[0], chunks[1]]
+}
+
+fn split_horizontal(area: Rect, first: Constraint, second: Constraint) -> [Rect; 2] {
+    let chunks = Layout::horizontal(vec![first, second]).split(area);
+    [chunks[0], chunks[1]]
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_large_terminal_shows_everything() {
+        let layout = AppLayout::compute(Rect::new(0, 0, 200, 50), RendererKind::Auto, 64, 32);
+        assert_eq!(layout.get(Region::Screen), Some(Rect::new(0, 0, 130, 34)));
+        assert_eq!(layout.get(Region::Registers), Some(Rect::new(0, 34, 130, 6)));
+        assert_eq!(layout.get(Region::Info), Some(Rect::new(0, 40, 130, 7)));
+        assert_eq!(layout.get(Region::Disassembly), Some(Rect::new(184, 0, 16, 47)));
+        assert_eq!(layout.get(Region::Status), Some(Rect::new(0, 47, 200, 3)));
+        assert!(layout.get(Region::Files).is_some());
+        assert!(layout.get(Region::Keypad).is_some());
+    }
+
+    #[test]
+    fn test_small_terminal_collapses_panels() {
+        let layout = AppLayout::compute(Rect::new(0, 0, 60, 14), RendererKind::Auto, 64, 32);
+        assert_eq!(layout.get(Region::Status), None);
+        assert_eq!(layout.get(Region::Files), None);
+        assert_eq!(layout.get(Region::Keypad), None);
+        assert!(layout.get(Region::Disassembly).is_some());
+        assert!(layout.get(Region::Screen).is_some());
+    }
+
+    #[test]
+    fn test_show_instead_of() {
+        let mut layout = AppLayout::compute(Rect::new(0, 0, 60, 14), RendererKind::Auto, 64, 32);
+        let screen = layout.get(Region::Screen);
+        layout.show_instead_of(Region::Files, Region::Screen);
+        assert_eq!(layout.get(Region::Files), screen);
+        assert_eq!(layout.get(Region::Screen), None);
+    }
+
+    #[test]
+    fn test_regions_do_not_overlap() {
+        for (width, height) in [(80, 24), (120, 40), (200, 60), (40, 12)] {
+            let layout = AppLayout::compute(Rect::new(0, 0, width, height), RendererKind::Auto, 64, 32);
+            let regions: Vec<Rect> = layout.regions.values().copied().collect();
+            for (i, a) in regions.iter().enumerate() {
+                for b in &regions[i + 1..] {
+                    assert!(!a.intersects(*b), "{a:?} overlaps {b:?} in {width}x{height}");
+                }
+            }
+        }
+    }
+}
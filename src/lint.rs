@@ -0,0 +1,354 @@
+use std::collections::BTreeSet;
+use std::fs;
+
+use strum::Display;
+
+use crate::asm::PROGRAM_START;
+use crate::cfg::{BasicBlock, ControlFlowGraph};
+use crate::cli::LintArgs;
+use crate::disasm::Disassembly;
+use crate::isa::{Instruction, InstructionSet};
+use crate::romdb::RomDatabase;
+use crate::symbols::SymbolMap;
+
+/// What makes an instruction behave differently across platforms
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Display)]
+#[strum(serialize_all = "kebab-case")]
+pub enum LintKind {
+    /// `SHR` or `SHL` of a register into another one
+    ShiftQuirk,
+    /// `JP V0` whose high nibble names another register than V0
+    JumpQuirk,
+    /// `LD [I]` or `LD Vx, [I]` followed by an instruction that uses I before setting it
+    MemoryQuirk,
+    /// `DRW` at known coordinates that cross the edge of the screen. The screen is only taken to
+    /// be in high resolution after a `HIGH` in the same block, as the low one is the smaller
+    SpriteEdge,
+    /// A write to memory below the program, where the font and interpreter live
+    InterpreterArea,
+    /// A write to memory holding reachable code
+    SelfModifying,
+    /// An instruction that the target platform doesn't have
+    PlatformOpcode,
+    /// Reachable bytes that no platform decodes
+    InvalidOpcode,
+}
+
+impl LintKind {
+    /// The field of [`Quirks`](crate::emulator::Quirks) that decides how the instruction behaves, if there is one
+    pub fn quirk(self) -> Option<&'static str> {
+        match self {
+            LintKind::ShiftQuirk => Some("superchip_shift"),
+            LintKind::JumpQuirk => Some("superchip_offset_jump"),
+            LintKind::MemoryQuirk => Some("superchip_memory"),
+            LintKind::PlatformOpcode => Some("superchip_opcodes"),
+            _ => None,
+        }
+    }
+}
+
+/// A warning about the instruction at `address`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    pub address: u16,
+    pub kind: LintKind,
+    pub message: String,
+}
+
+/// What the index register is known to point at, within a block
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Index {
+    Unknown,
+    Address(u16),
+    /// Somewhere in the built-in fonts
+    Font,
+}
+
+/// Known register values and index register, propagated through a block
+struct BlockState {
+    registers: [Option<u8>; 16],
+    index: Index,
+    /// Address of the `LD [I]` or `LD Vx, [I]` after which I depends on the memory quirk
+    memory_quirk: Option<u16>,
+    /// Whether a `HIGH` switched to high resolution, without a `LOW` since
+    hires: bool,
+}
+
+impl Default for BlockState {
+    fn default() -> Self {
+        Self { registers: [None; 16], index: Index::Unknown, memory_quirk: None, hires: false }
+    }
+}
+
+impl BlockState {
+    fn clobber(&mut self, registers: impl IntoIterator<Item = u8>) {
+        for register in registers {
+            self.registers[register as usize & 0xF] = None;
+        }
+    }
+
+    /// Addresses written from I by `instruction`, if I is known
+    fn written(&self, instruction: &Instruction) -> Option<(u16, u16)> {
+        use Instruction::*;
+
+        let size = match *instruction {
+            StoreRegs(x) => x as u16 + 1,
+            LdBVx(_) => 3,
+            SaveRange(x, y) => x.abs_diff(y) as u16 + 1,
+            _ => return None,
+        };
+        match self.index {
+            Index::Address(address) => Some((address, address.saturating_add(size))),
+            Index::Font => Some((0, size)),
+            Index::Unknown => None,
+        }
+    }
+}
+
+/// Range of the registers that `instruction` sets, other than through immediate values
+fn clobbered(instruction: &Instruction) -> Vec<u8> {
+    use Instruction::*;
+
+    match *instruction {
+        Or(x, _) | And(x, _) | Xor(x, _) | AddReg(x, _) | Sub(x, _) | Shr(x, _) | Subn(x, _) | Shl(x, _) => vec![x, 0xF],
+        Rnd(x, _) | LdVxDt(x) | LdVxK(x) => vec![x],
+        Drw(..) => vec![0xF],
+        LoadRegs(x) | LoadRpl(x) => (0..=x).collect(),
+        LoadRange(x, y) => (x.min(y)..=x.max(y)).collect(),
+        _ => vec![],
+    }
+}
+
+/// Whether `instruction` reads I or memory through it
+fn uses_index(instruction: &Instruction) -> bool {
+    use Instruction::*;
+    matches!(instruction, Drw(..) | AddIVx(_) | LdBVx(_) | StoreRegs(_) | LoadRegs(_) | SaveRange(..) | LoadRange(..))
+}
+
+/// Whether `instruction` sets I without reading it
+fn sets_index(instruction: &Instruction) -> bool {
+    use Instruction::*;
+    matches!(instruction, LdI(_) | LdILong(_) | LdFVx(_) | LdHfVx(_))
+}
+
+/// Warnings about the reachable code of `rom` that behaves differently across platforms, for
+/// a program meant to run on `target`, in address order
+pub fn lint(rom: &[u8], target: InstructionSet, symbols: Option<&SymbolMap>) -> Vec<Lint> {
+    // Decoding with the largest set finds the instructions of every platform
+    let disassembly = Disassembly::analyze(rom, InstructionSet::XoChip, symbols);
+    let graph = ControlFlowGraph::from_disassembly(&disassembly);
+
+    let mut lints = Vec::new();
+    for block in graph.blocks.values() {
+        lint_block(&disassembly, block, target, &mut lints);
+    }
+    for (&address, instruction) in &disassembly.code {
+        for successor in disassembly.successors(address, instruction) {
+            let is_invalid = disassembly.contains(successor) && !disassembly.is_code(successor)
+                && disassembly.decode_at(successor).is_none();
+            if is_invalid {
+                let opcode = disassembly.rom().get((successor - PROGRAM_START) as usize..)
+                    .and_then(|bytes| Some(u16::from_be_bytes([*bytes.first()?, *bytes.get(1)?])));
+                lints.push(Lint {
+                    address: successor,
+                    kind: LintKind::InvalidOpcode,
+                    message: match opcode {
+                        Some(opcode) => format!("{:04X} is not an instruction on any platform", opcode),
+                        None => "execution runs into the last byte of the ROM".to_string(),
+                    },
+                });
+            }
+        }
+    }
+    lints.sort_by_key(|lint| (lint.address, lint.kind));
+    lints.dedup();
+    lints
+}
+
+fn lint_block(disassembly: &Disassembly, block: &BasicBlock, target: InstructionSet, lints: &mut Vec<Lint>) {
+    use Instruction::*;
+
+    let mut state = BlockState::default();
+    for &(address, instruction) in &block.instructions {
+        let mut warn = |kind, message: String| lints.push(Lint { address, kind, message });
+
+        if instruction.instruction_set() > target {
+            warn(LintKind::PlatformOpcode, format!("{} needs {}, not {}", instruction, instruction.instruction_set(), target));
+        }
+        match instruction {
+            Shr(x, y) | Shl(x, y) if x != y => warn(LintKind::ShiftQuirk, format!(
+                "{} shifts V{:X} on CHIP-8 but V{:X} in place on SCHIP", instruction, y, x,
+            )),
+            JpV0(nnn) if nnn >> 8 != 0 => warn(LintKind::JumpQuirk, format!(
+                "{} adds V0 on CHIP-8 but V{:X} on SCHIP", instruction, nnn >> 8,
+            )),
+            _ => {}
+        }
+        if let Some(since) = state.memory_quirk {
+            if uses_index(&instruction) {
+                warn(LintKind::MemoryQuirk, format!(
+                    "{} relies on I after 0x{:03X}, which advances it on CHIP-8 but not on SCHIP", instruction, since,
+                ));
+            }
+        }
+        if let Drw(x, y, n) = instruction {
+            if let (Some(vx), Some(vy)) = (state.registers[x as usize], state.registers[y as usize]) {
+                let (width, height) = if state.hires { (128, 64) } else { (64, 32) };
+                let (column, row) = (vx as u16 % width, vy as u16 % height);
+                let (sprite_width, sprite_height) = if n == 0 { (16, 16) } else { (8, n as u16) };
+                if column + sprite_width > width || row + sprite_height > height {
+                    warn(LintKind::SpriteEdge, format!(
+                        "the sprite at ({}, {}) crosses the edge of the screen, which some platforms clip and others wrap",
+                        column, row,
+                    ));
+                }
+            }
+        }
+        if let Some((start, end)) = state.written(&instruction) {
+            if start < PROGRAM_START {
+                warn(LintKind::InterpreterArea, format!(
+                    "{} writes 0x{:03X}-0x{:03X}, below the program where interpreters keep the font and their own data",
+                    instruction, start, end - 1,
+                ));
+            }
+            if let Some(code) = (start.max(PROGRAM_START)..end).find(|address| disassembly.is_code(*address)) {
+                warn(LintKind::SelfModifying, format!("{} overwrites the code at 0x{:03X}", instruction, code));
+            }
+        }
+
+        state.clobber(clobbered(&instruction));
+        match instruction {
+            LdImm(x, nn) => state.registers[x as usize] = Some(nn),
+            AddImm(x, nn) => state.registers[x as usize] = state.registers[x as usize].map(|vx| vx.wrapping_add(nn)),
+            LdReg(x, y) => state.registers[x as usize] = state.registers[y as usize],
+            _ => {}
+        }
+        if sets_index(&instruction) {
+            state.memory_quirk = None;
+        }
+        match instruction {
+            High => state.hires = true,
+            Low => state.hires = false,
+            _ => {}
+        }
+        state.index = match instruction {
+            LdI(nnn) | LdILong(nnn) => Index::Address(nnn),
+            LdFVx(_) | LdHfVx(_) => Index::Font,
+            AddIVx(x) => match (state.index, state.registers[x as usize]) {
+                (Index::Address(address), Some(vx)) => Index::Address(address.wrapping_add(vx as u16)),
+                _ => Index::Unknown,
+            },
+            StoreRegs(_) | LoadRegs(_) => {
+                state.memory_quirk = Some(address);
+                Index::Unknown
+            }
+            _ => state.index,
+        };
+    }
+}
+
+pub fn run(args: &LintArgs) -> color_eyre::Result<()> {
+    let rom = fs::read(&args.rom)?;
+    let symbols = match &args.symbols {
+        Some(path) => Some(SymbolMap::load(path)?),
+        None => SymbolMap::load_for_rom(&args.rom)?,
+    };
+    let set = RomDatabase::profile_instruction_set(args.target, args.profile.as_deref())?;
+    let lints = lint(&rom, set, symbols.as_ref());
+    for lint in &lints {
+        println!("0x{:03X}: warning[{}]: {}", lint.address, lint.kind, lint.message);
+    }
+
+    let quirks: BTreeSet<&str> = lints.iter().filter_map(|lint| lint.kind.quirk()).collect();
+    match (lints.len(), quirks.is_empty()) {
+        (0, _) => println!("No portability issues found"),
+        (count, true) => println!("{} warnings", count),
+        (count, false) => println!("{} warnings, the program depends on the quirks {}", count, quirks.into_iter().collect::<Vec<_>>().join(", ")),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::asm;
+
+    fn kinds(source: &str, target: InstructionSet) -> Vec<(u16, LintKind)> {
+        let rom = asm::assemble_source("test.asm", source, InstructionSet::XoChip).unwrap().rom;
+        lint(&rom, target, None).into_iter().map(|lint| (lint.address, lint.kind)).collect()
+    }
+
+    #[test]
+    fn test_quirks() {
+        let lints = kinds("
+            start:
+                SHR V1, V2
+                SHL V3, V3
+                LD I, data
+                LD [I], V1
+                LD V0, [I]
+                LD I, data
+                LD V0, [I]
+                JP V0, table
+            table:
+                JP start
+            data:
+                db 0, 0
+        ", InstructionSet::Chip8);
+        assert_eq!(lints, vec![
+            (0x200, LintKind::ShiftQuirk),
+            (0x208, LintKind::MemoryQuirk),
+            (0x20E, LintKind::JumpQuirk),
+        ]);
+    }
+
+    #[test]
+    fn test_memory_writes_and_sprites() {
+        let lints = kinds("
+            start:
+                LD V0, 60
+                LD V1, 4
+                LD I, sprite
+                DRW V0, V1, 4
+                ADD V0, 224
+                DRW V0, V1, 4
+                LD I, 0x100
+                LD [I], V2
+                LD I, start
+                LD B, V0
+                HIGH
+                JP start
+            sprite:
+                db 0xFF
+        ", InstructionSet::Chip8);
+        assert_eq!(lints, vec![
+            (0x206, LintKind::SpriteEdge),
+            (0x20E, LintKind::InterpreterArea),
+            (0x212, LintKind::SelfModifying),
+            (0x214, LintKind::PlatformOpcode),
+        ]);
+
+        let lints = kinds("
+            start:
+                LD V0, 60
+                LD V1, 30
+                DRW V0, V1, 4
+                JP start
+        ", InstructionSet::Chip8);
+        assert_eq!(lints, vec![(0x204, LintKind::SpriteEdge)]);
+
+        let lints = kinds("
+            start:
+                HIGH
+                LD V0, 60
+                LD V1, 30
+                DRW V0, V1, 4
+                LOW
+                DRW V0, V1, 4
+                JP start
+        ", InstructionSet::Schip);
+        assert_eq!(lints, vec![(0x20A, LintKind::SpriteEdge)]);
+    }
+}
@@ -4,16 +4,39 @@
 
 pub mod action;
 pub mod app;
+pub mod asm;
+pub mod browser;
+pub mod cfg;
+pub mod cheats;
 pub mod cli;
 pub mod components;
 pub mod config;
+pub mod coverage;
+pub mod disasm;
+pub mod isa;
+pub mod keypad;
+pub mod layout;
+pub mod lint;
 pub mod mode;
+pub mod palette;
+pub mod patch;
+pub mod persistence;
+pub mod profile;
+pub mod profiler;
+pub mod movie;
+pub mod octo;
+pub mod random;
+pub mod renderer;
+pub mod rom;
+pub mod romdb;
+pub mod sprites;
+pub mod symbols;
 pub mod tui;
 pub mod utils;
 mod emulator;
 
 use clap::Parser;
-use cli::Cli;
+use cli::{Cli, Command};
 use color_eyre::eyre::Result;
 
 use crate::{
@@ -27,8 +50,17 @@ async fn tokio_main() -> Result<()> {
   initialize_panic_handler()?;
 
   let args = Cli::parse();
-  let mut app = App::new(args.tick_rate, args.frame_rate)?;
-  app.run().await?;
+  match args.command {
+    Some(Command::Asm(ref asm_args)) => asm::run(asm_args)?,
+    Some(Command::Disasm(ref disasm_args)) => disasm::run(disasm_args)?,
+    Some(Command::Cfg(ref cfg_args)) => cfg::run(cfg_args)?,
+    Some(Command::Patch(ref patch_args)) => patch::run(patch_args)?,
+    Some(Command::Lint(ref lint_args)) => lint::run(lint_args)?,
+    None => {
+      let mut app = App::new(args)?;
+      app.run().await?;
+    },
+  }
 
   Ok(())
 }
@@ -5,4 +5,10 @@ pub enum Mode {
   #[default]
   Home,
   SelectingFile,
+  /// An emulation error paused the emulator
+  Error,
+  /// The help overlay is open
+  Help,
+  /// The cheat search panel has the focus
+  CheatSearch,
 }
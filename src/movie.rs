@@ -0,0 +1,214 @@
+use std::fs;
+use std::path::Path;
+use color_eyre::eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use crate::cheats::Cheat;
+use crate::emulator::{Chip8Emu, EmulationErr, Quirks};
+use crate::random::RandomKind;
+use crate::rom::{self, LoadedProgram};
+use crate::romdb::RomDatabase;
+
+/// A single keypad state change, applied at the start of `frame`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputEvent {
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/// A change of the frozen cheats, applied at the start of `frame`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheatEvent {
+    pub frame: u64,
+    pub cheats: Vec<Cheat>,
+}
+
+/// Recording of every keypad input of a run, together with everything needed to reproduce it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Movie {
+    pub rom_path: String,
+    pub rom_hash: String,
+    pub quirks: Quirks,
+    pub seed: u64,
+    #[serde(default)]
+    pub random: RandomKind,
+    pub cycles_per_frame: u32,
+    pub inputs: Vec<InputEvent>,
+    /// Cheats frozen when the recording started
+    #[serde(default)]
+    pub cheats: Vec<Cheat>,
+    #[serde(default)]
+    pub cheat_changes: Vec<CheatEvent>,
+}
+
+impl Movie {
+    /// Starts a new recording for the ROM currently loaded into `emulator`
+    pub fn new(emulator: &Chip8Emu, rom_path: &str, cycles_per_frame: u32) -> Self {
+        Self {
+            rom_path: rom_path.to_string(),
+            rom_hash: emulator.get_rom_hash(),
+            quirks: emulator.get_quirks(),
+            seed: emulator.get_seed(),
+            random: emulator.get_random_kind(),
+            cycles_per_frame,
+            inputs: Vec::new(),
+            cheats: emulator.get_cheats().to_vec(),
+            cheat_changes: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        log::info!("Movie with {} inputs saved to {}", self.inputs.len(), path.display());
+        Ok(())
+    }
+
+    pub fn record(&mut self, frame: u64, key: u8, pressed: bool) {
+        self.inputs.push(InputEvent { frame, key, pressed });
+    }
+
+    pub fn record_cheats(&mut self, frame: u64, cheats: Vec<Cheat>) {
+        self.cheat_changes.push(CheatEvent { frame, cheats });
+    }
+
+    /// Loads the recorded ROM into `emulator` with the recorded seed, quirks and cheats, patched
+    /// as it was when recorded, returning the loaded program
+    pub fn prepare(&self, emulator: &mut Chip8Emu, config_dir: &Path, database: &RomDatabase) -> Result<LoadedProgram> {
+        let program = rom::load_program(Path::new(&self.rom_path), config_dir, database)?;
+        let hash = crate::emulator::rom_hash(&program.rom);
+        if hash != self.rom_hash {
+            return Err(eyre!(
+                "ROM {} does not match the movie: expected SHA-1 {}, found {}",
+                self.rom_path, self.rom_hash, hash
+            ));
+        }
+        emulator.set_seed(self.seed);
+        emulator.set_random_kind(self.random);
+        emulator.set_quirks(self.quirks);
+        emulator.load_rom(program.rom.clone()).map_err(|err| eyre!(String::from(err)))?;
+        emulator.set_cheats(self.cheats.clone());
+        Ok(program)
+    }
+}
+
+/// Feeds the inputs and cheats of a [`Movie`] back into an emulator frame by frame
+pub struct MoviePlayer {
+    movie: Movie,
+    position: usize,
+    cheat_position: usize,
+}
+
+impl MoviePlayer {
+    pub fn new(movie: Movie) -> Self { Self { movie, position: 0, cheat_position: 0 } }
+
+    pub fn movie(&self) -> &Movie { &self.movie }
+
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.movie.inputs.len() && self.cheat_position >= self.movie.cheat_changes.len()
+    }
+
+    /// Applies every input and cheat change recorded for the current frame of `emulator`
+    pub fn apply(&mut self, emulator: &mut Chip8Emu) -> Result<(), EmulationErr> {
+        while let Some(change) = self.movie.cheat_changes.get(self.cheat_position) {
+            if change.frame > emulator.get_frame() {
+                break
+            }
+            emulator.set_cheats(change.cheats.clone());
+            self.cheat_position += 1;
+        }
+        while let Some(input) = self.movie.inputs.get(self.position) {
+            if input.frame > emulator.get_frame() {
+                break
+            }
+            emulator.set_key(input.key, input.pressed)?;
+            self.position += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    // Draws random font digits at random columns until key 0 is held, then clears the screen
+    const ROM: [u8; 20] = [
+        0x63, 0x00, 0xC0, 0x0F, 0xF0, 0x29, 0xC1, 0x38, 0x62, 0x00,
+        0xD1, 0x25, 0xE3, 0x9E, 0x12, 0x02, 0x00, 0xE0, 0x12, 0x02,
+    ];
+    const CYCLES_PER_FRAME: u32 = 7;
+
+    fn run_frames(emulator: &mut Chip8Emu, frames: u64, mut on_frame: impl FnMut(&mut Chip8Emu)) -> Vec<Vec<u8>> {
+        let mut screens = Vec::new();
+        for _ in 0..frames {
+            for _ in 0..CYCLES_PER_FRAME {
+                emulator.emulate_cycle().unwrap();
+            }
+            emulator.advance_frame();
+            on_frame(emulator);
+            screens.push(emulator.screen());
+        }
+        screens
+    }
+
+    #[test]
+    fn test_replay_is_deterministic() {
+        let mut emulator = Chip8Emu::new();
+        emulator.set_random_kind(RandomKind::CosmacVip);
+        emulator.load_rom(ROM.to_vec()).unwrap();
+        let mut movie = Movie::new(&emulator, "rom.ch8", CYCLES_PER_FRAME);
+        let recorded = run_frames(&mut emulator, 12, |emulator| {
+            let frame = emulator.get_frame();
+            if frame == 4 || frame == 7 {
+                let pressed = frame == 4;
+                emulator.set_key(0, pressed).unwrap();
+                movie.record(frame, 0, pressed);
+            }
+        });
+
+        let mut replayed_emulator = Chip8Emu::new();
+        replayed_emulator.set_seed(movie.seed);
+        replayed_emulator.set_random_kind(movie.random);
+        replayed_emulator.load_rom(ROM.to_vec()).unwrap();
+        assert_eq!(replayed_emulator.get_rom_hash(), movie.rom_hash);
+        let mut player = MoviePlayer::new(movie);
+        let replayed = run_frames(&mut replayed_emulator, 12, |emulator| player.apply(emulator).unwrap());
+
+        assert!(player.is_finished());
+        assert_eq!(recorded, replayed);
+    }
+
+    #[test]
+    fn test_replay_reproduces_cheats() {
+        let mut emulator = Chip8Emu::new();
+        emulator.load_rom(ROM.to_vec()).unwrap();
+        emulator.set_cheats(vec![Cheat { address: 0x209, value: 0x08 }]);
+        let mut movie = Movie::new(&emulator, "rom.ch8", CYCLES_PER_FRAME);
+        let recorded = run_frames(&mut emulator, 12, |emulator| {
+            // Only draws the digit 0 from then on
+            if emulator.get_frame() == 5 {
+                let cheats = vec![Cheat { address: 0x203, value: 0x00 }];
+                emulator.set_cheats(cheats.clone());
+                movie.record_cheats(5, cheats);
+            }
+        });
+
+        let mut replayed_emulator = Chip8Emu::new();
+        replayed_emulator.set_seed(movie.seed);
+        replayed_emulator.load_rom(ROM.to_vec()).unwrap();
+        replayed_emulator.set_cheats(movie.cheats.clone());
+        let mut player = MoviePlayer::new(movie);
+        let replayed = run_frames(&mut replayed_emulator, 12, |emulator| player.apply(emulator).unwrap());
+
+        assert!(player.is_finished());
+        assert_eq!(player.movie().cheats, vec![Cheat { address: 0x209, value: 0x08 }]);
+        assert_eq!(recorded, replayed);
+    }
+}
@@ -0,0 +1,822 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+
+use color_eyre::eyre::eyre;
+
+use crate::asm::{AsmError, Assembly, PROGRAM_START};
+use crate::isa::Instruction;
+use crate::symbols::{SourceLine, SymbolMap};
+
+/// Extension of Octo source files
+pub const OCTO_EXTENSION: &str = "8o";
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+/// Splits Octo source on whitespace, dropping `#` comments
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (i, line) in source.lines().enumerate() {
+        let mut start = None;
+        let chars: Vec<char> = line.chars().collect();
+        for (j, &c) in chars.iter().chain([' '].iter()).enumerate() {
+            if c == '#' && start.is_none() {
+                break
+            }
+            match (c.is_whitespace(), start) {
+                (false, None) => start = Some(j),
+                (true, Some(first)) => {
+                    tokens.push_back(Token { text: chars[first..j].iter().collect(), line: i + 1, column: first + 1 });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()? as f64
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()? as f64
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn register(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix(['v', 'V'])?;
+    if digit.len() != 1 {
+        return None
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// Width of the address a fixup patches
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Width {
+    /// The low 12 bits of an instruction
+    Nnn,
+    /// The word after `F000`
+    Long,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Fixup {
+    offset: usize,
+    width: Width,
+    label: Token,
+}
+
+/// An open `begin`, `else` or `loop`, with the offsets of the jumps to patch when it closes
+#[derive(Debug, Clone, PartialEq)]
+enum Block {
+    If { jump: usize, token: Token },
+    Else { jump: usize, token: Token },
+    Loop { start: u16, breaks: Vec<usize>, token: Token },
+}
+
+/// A condition of `if` and `while`: the instructions that skip when it's true and when it's false
+struct Condition {
+    skip_if_true: Instruction,
+    skip_if_false: Instruction,
+}
+
+const RESERVED: [&str; 43] = [
+    ":", ":=", "+=", "-=", "=-", "|=", "&=", "^=", ">>=", "<<=", "==", "!=", ";", "return", "clear", "bcd", "save",
+    "load", "sprite", "jump", "jump0", "native", "i", "delay", "buzzer", "key", "-key", "if", "then", "begin", "else",
+    "end", "loop", "again", "while", "hires", "lores", "exit", "random", "hex", "bighex", "long", "plane",
+];
+
+struct Compiler<'a> {
+    file: &'a str,
+    tokens: VecDeque<Token>,
+    rom: Vec<u8>,
+    labels: BTreeMap<String, u16>,
+    constants: BTreeMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+    lines: Vec<SourceLine>,
+    /// The token of the last statement
+    statement: Token,
+}
+
+type CompileResult<T> = Result<T, AsmError>;
+
+impl<'a> Compiler<'a> {
+    fn error(&self, token: &Token, message: impl Into<String>) -> AsmError {
+        AsmError { file: self.file.into(), line: token.line, column: token.column, message: message.into() }
+    }
+
+    fn here(&self) -> CompileResult<u16> {
+        u16::try_from(PROGRAM_START as usize + self.rom.len())
+            .map_err(|_| self.error(&self.statement, "the program doesn't fit in the 64 KB address space"))
+    }
+
+    fn next(&mut self) -> CompileResult<Token> {
+        let statement = self.statement.clone();
+        self.tokens.pop_front().ok_or_else(|| self.error(&statement, "unexpected end of file"))
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str) -> CompileResult<Token> {
+        let token = self.next()?;
+        if token.text != text {
+            return Err(self.error(&token, format!("expected `{}`, found `{}`", text, token.text)))
+        }
+        Ok(token)
+    }
+
+    fn name(&mut self) -> CompileResult<Token> {
+        let token = self.next()?;
+        if RESERVED.contains(&token.text.as_str()) || register(&token.text).is_some() || parse_number(&token.text).is_some() {
+            return Err(self.error(&token, format!("`{}` can't be used as a name", token.text)))
+        }
+        if self.labels.contains_key(&token.text) || self.constants.contains_key(&token.text) {
+            return Err(self.error(&token, format!("`{}` is already defined", token.text)))
+        }
+        Ok(token)
+    }
+
+    fn register(&mut self) -> CompileResult<u8> {
+        let token = self.next()?;
+        self.register_of(&token)
+    }
+
+    fn register_of(&self, token: &Token) -> CompileResult<u8> {
+        register(&token.text)
+            .or_else(|| self.aliases.get(&token.text).copied())
+            .ok_or_else(|| self.error(token, format!("expected a register, found `{}`", token.text)))
+    }
+
+    fn is_register(&self, text: &str) -> bool {
+        register(text).is_some() || self.aliases.contains_key(text)
+    }
+
+    /// A number or constant
+    fn value_of(&self, token: &Token) -> Option<f64> {
+        parse_number(&token.text).or_else(|| self.constants.get(&token.text).copied())
+    }
+
+    fn ranged(&mut self, min: i64, max: i64, what: &str) -> CompileResult<i64> {
+        let token = self.next()?;
+        let value = self.value_of(&token)
+            .ok_or_else(|| self.error(&token, format!("expected a {}, found `{}`", what, token.text)))?
+            .floor() as i64;
+        if value < min || value > max {
+            return Err(self.error(&token, format!("{} {} is out of range ({} to {})", what, value, min, max)))
+        }
+        Ok(value)
+    }
+
+    fn byte(&mut self) -> CompileResult<u8> {
+        self.ranged(-128, 255, "byte").map(|value| value as u8)
+    }
+
+    fn nibble(&mut self) -> CompileResult<u8> {
+        self.ranged(0, 15, "nibble").map(|value| value as u8)
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> CompileResult<()> {
+        self.lines.push(SourceLine { address: self.here()?, file: self.file.to_string(), line: self.statement.line });
+        self.rom.extend(instruction.to_bytes());
+        Ok(())
+    }
+
+    /// Emits `instruction`, whose address is given by the next token. Labels that aren't defined
+    /// yet are patched at the end
+    fn emit_address(&mut self, instruction: impl Fn(u16) -> Instruction, width: Width) -> CompileResult<()> {
+        let token = self.next()?;
+        let max = if width == Width::Long { 0xFFFF } else { 0xFFF };
+        let address = match self.value_of(&token).or_else(|| self.labels.get(&token.text).map(|&address| address as f64)) {
+            Some(value) if value < 0.0 || value > max as f64 => {
+                return Err(self.error(&token, format!("address {} is out of range", value)))
+            }
+            Some(value) => value as u16,
+            None => {
+                self.fixups.push(Fixup { offset: self.rom.len(), width, label: token });
+                0
+            }
+        };
+        self.emit(instruction(address))?;
+        Ok(())
+    }
+
+    /// Emits a jump to be patched when its block closes, returning its offset
+    fn emit_jump(&mut self) -> CompileResult<usize> {
+        let offset = self.rom.len();
+        self.emit(Instruction::Jp(0))?;
+        Ok(offset)
+    }
+
+    /// Checks that `address`, referenced by `token`, fits in `width`
+    fn in_range(&self, token: &Token, width: Width, address: u16) -> CompileResult<u16> {
+        match width {
+            Width::Nnn if address > 0xFFF => Err(self.error(token, format!("address {} is out of range", address))),
+            _ => Ok(address),
+        }
+    }
+
+    /// Writes `address`, which `token` refers to, into the instruction at `offset`
+    fn patch(&mut self, offset: usize, width: Width, address: u16, token: &Token) -> CompileResult<()> {
+        let address = self.in_range(token, width, address)?;
+        match width {
+            Width::Nnn => {
+                self.rom[offset] = (self.rom[offset] & 0xF0) | (address >> 8) as u8 & 0x0F;
+                self.rom[offset + 1] = address as u8;
+            }
+            Width::Long => self.rom[offset + 2..offset + 4].copy_from_slice(&address.to_be_bytes()),
+        }
+        Ok(())
+    }
+
+    fn condition(&mut self) -> CompileResult<Condition> {
+        use Instruction::*;
+
+        let x = self.register()?;
+        let op = self.next()?;
+        let (skip_if_true, skip_if_false) = match op.text.as_str() {
+            "key" => (Skp(x), Sknp(x)),
+            "-key" => (Sknp(x), Skp(x)),
+            "==" | "!=" => {
+                let (equal, not_equal) = if self.peek().is_some_and(|text| self.is_register(text)) {
+                    let y = self.register()?;
+                    (SeReg(x, y), SneReg(x, y))
+                } else {
+                    let nn = self.byte()?;
+                    (SeImm(x, nn), SneImm(x, nn))
+                };
+                if op.text == "==" { (equal, not_equal) } else { (not_equal, equal) }
+            }
+            _ => return Err(self.error(&op, format!("unsupported condition `{}`", op.text))),
+        };
+        Ok(Condition { skip_if_true, skip_if_false })
+    }
+
+    /// `if <condition> then <statement>` or `if <condition> begin ... [else ...] end`
+    fn if_statement(&mut self) -> CompileResult<()> {
+        let condition = self.condition()?;
+        let token = self.next()?;
+        match token.text.as_str() {
+            // The next statement runs only when the condition is true
+            "then" => self.emit(condition.skip_if_false)?,
+            "begin" => {
+                self.emit(condition.skip_if_true)?;
+                let jump = self.emit_jump()?;
+                self.blocks.push(Block::If { jump, token });
+            }
+            _ => return Err(self.error(&token, format!("expected `then` or `begin`, found `{}`", token.text))),
+        }
+        Ok(())
+    }
+
+    fn assignment(&mut self, x: u8) -> CompileResult<()> {
+        use Instruction::*;
+
+        let op = self.next()?;
+        let operand = self.peek().map(str::to_string).unwrap_or_default();
+        if self.is_register(&operand) {
+            let y = self.register()?;
+            let instruction = match op.text.as_str() {
+                ":=" => LdReg(x, y),
+                "|=" => Or(x, y),
+                "&=" => And(x, y),
+                "^=" => Xor(x, y),
+                "+=" => AddReg(x, y),
+                "-=" => Sub(x, y),
+                "=-" => Subn(x, y),
+                ">>=" => Shr(x, y),
+                "<<=" => Shl(x, y),
+                _ => return Err(self.error(&op, format!("unknown operator `{}`", op.text))),
+            };
+            self.emit(instruction)?;
+            return Ok(())
+        }
+        let instruction = match (op.text.as_str(), operand.as_str()) {
+            (":=", "random") => {
+                self.next()?;
+                Rnd(x, self.byte()?)
+            }
+            (":=", "delay") => {
+                self.next()?;
+                LdVxDt(x)
+            }
+            (":=", "key") => {
+                self.next()?;
+                LdVxK(x)
+            }
+            (":=", _) => LdImm(x, self.byte()?),
+            ("+=", _) => AddImm(x, self.byte()?),
+            ("-=", _) => AddImm(x, self.byte()?.wrapping_neg()),
+            _ => return Err(self.error(&op, format!("unknown operator `{}`", op.text))),
+        };
+        self.emit(instruction)?;
+        Ok(())
+    }
+
+    fn i_statement(&mut self) -> CompileResult<()> {
+        use Instruction::*;
+
+        let op = self.next()?;
+        match op.text.as_str() {
+            "+=" => {
+                let x = self.register()?;
+                self.emit(AddIVx(x))?;
+            }
+            ":=" => match self.peek() {
+                Some("hex") => {
+                    self.next()?;
+                    let x = self.register()?;
+                    self.emit(LdFVx(x))?;
+                }
+                Some("bighex") => {
+                    self.next()?;
+                    let x = self.register()?;
+                    self.emit(LdHfVx(x))?;
+                }
+                Some("long") => {
+                    self.next()?;
+                    self.emit_address(LdILong, Width::Long)?;
+                }
+                _ => self.emit_address(LdI, Width::Nnn)?,
+            },
+            _ => return Err(self.error(&op, format!("unknown operator `{}`", op.text))),
+        }
+        Ok(())
+    }
+
+    /// `save vx` or the XO-CHIP `save vx - vy`
+    fn register_range(&mut self, single: fn(u8) -> Instruction, range: fn(u8, u8) -> Instruction) -> CompileResult<()> {
+        let x = self.register()?;
+        if self.peek() == Some("-") {
+            self.next()?;
+            let y = self.register()?;
+            self.emit(range(x, y))?;
+        } else {
+            self.emit(single(x))?;
+        }
+        Ok(())
+    }
+
+    /// Tokens up to the `}` matching an opened `{`
+    fn braced(&mut self) -> CompileResult<Vec<Token>> {
+        let mut depth = 1;
+        let mut tokens = vec![];
+        loop {
+            let token = self.next()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(tokens)
+                    }
+                }
+                _ => {}
+            }
+            tokens.push(token);
+        }
+    }
+
+    /// Evaluates a `:calc` expression. Like Octo, operators have no precedence and are evaluated
+    /// from right to left
+    fn calc(&self, tokens: &[Token], position: &mut usize) -> CompileResult<f64> {
+        let lhs = self.calc_term(tokens, position)?;
+        let Some(op) = tokens.get(*position) else { return Ok(lhs) };
+        if op.text == ")" {
+            return Ok(lhs)
+        }
+        *position += 1;
+        let rhs = self.calc(tokens, position)?;
+        let (a, b) = (lhs as i64, rhs as i64);
+        Ok(match op.text.as_str() {
+            "+" => lhs + rhs,
+            "-" => lhs - rhs,
+            "*" => lhs * rhs,
+            "/" if rhs == 0.0 => return Err(self.error(op, "division by zero")),
+            "/" => lhs / rhs,
+            "%" if b == 0 => return Err(self.error(op, "division by zero")),
+            "%" => (a % b) as f64,
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" => a.checked_shl(b as u32).unwrap_or(0) as f64,
+            ">>" => a.checked_shr(b as u32).unwrap_or(0) as f64,
+            "min" => lhs.min(rhs),
+            "max" => lhs.max(rhs),
+            "<" => (lhs < rhs) as i64 as f64,
+            ">" => (lhs > rhs) as i64 as f64,
+            "<=" => (lhs <= rhs) as i64 as f64,
+            ">=" => (lhs >= rhs) as i64 as f64,
+            "==" => (lhs == rhs) as i64 as f64,
+            "!=" => (lhs != rhs) as i64 as f64,
+            _ => return Err(self.error(op, format!("unknown operator `{}`", op.text))),
+        })
+    }
+
+    fn calc_term(&self, tokens: &[Token], position: &mut usize) -> CompileResult<f64> {
+        let Some(token) = tokens.get(*position) else {
+            let last = tokens.last().unwrap_or(&self.statement);
+            return Err(self.error(last, "expected a value"))
+        };
+        *position += 1;
+        Ok(match token.text.as_str() {
+            "(" => {
+                let value = self.calc(tokens, position)?;
+                match tokens.get(*position) {
+                    Some(close) if close.text == ")" => *position += 1,
+                    _ => return Err(self.error(token, "unclosed `(`")),
+                }
+                value
+            }
+            "-" => -self.calc_term(tokens, position)?,
+            "~" => !(self.calc_term(tokens, position)? as i64) as f64,
+            "!" => (self.calc_term(tokens, position)? == 0.0) as i64 as f64,
+            "abs" => self.calc_term(tokens, position)?.abs(),
+            "sqrt" => self.calc_term(tokens, position)?.sqrt(),
+            "floor" => self.calc_term(tokens, position)?.floor(),
+            "ceil" => self.calc_term(tokens, position)?.ceil(),
+            "HERE" => self.here()? as f64,
+            text => self.value_of(token)
+                .or_else(|| self.labels.get(text).map(|&address| address as f64))
+                .ok_or_else(|| self.error(token, format!("undefined name `{}`", text)))?,
+        })
+    }
+
+    /// A `{ ... }` expression
+    fn braced_value(&mut self) -> CompileResult<f64> {
+        let tokens = self.braced()?;
+        let mut position = 0;
+        let value = self.calc(&tokens, &mut position)?;
+        if let Some(token) = tokens.get(position) {
+            return Err(self.error(token, format!("unexpected `{}`", token.text)))
+        }
+        Ok(value)
+    }
+
+    fn directive(&mut self, token: &Token) -> CompileResult<()> {
+        match token.text.as_str() {
+            ":" => {
+                let name = self.name()?;
+                // Octo starts with a jump to `main`, dropped when `main` comes first
+                if name.text == "main" && self.rom.len() == 2 && self.fixups.first().is_some_and(|fixup| fixup.label.text == "main") {
+                    self.rom.clear();
+                    self.lines.clear();
+                    self.fixups.remove(0);
+                }
+                let address = self.here()?;
+                self.labels.insert(name.text, address);
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.next()?;
+                let value = self.value_of(&value)
+                    .ok_or_else(|| self.error(&value, format!("expected a number or constant, found `{}`", value.text)))?;
+                self.constants.insert(name.text, value);
+            }
+            ":calc" => {
+                let name = self.name()?;
+                self.expect("{")?;
+                let value = self.braced_value()?;
+                self.constants.insert(name.text, value);
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let x = self.register()?;
+                self.aliases.insert(name.text, x);
+            }
+            ":macro" => {
+                let name = self.name()?;
+                let mut params = vec![];
+                while self.peek().is_some_and(|text| text != "{") {
+                    params.push(self.next()?.text);
+                }
+                self.expect("{")?;
+                let body = self.braced()?;
+                self.macros.insert(name.text, Macro { params, body });
+            }
+            ":byte" => {
+                let value = if self.peek() == Some("{") {
+                    self.next()?;
+                    self.braced_value()?.floor() as i64
+                } else {
+                    self.ranged(-128, 255, "byte")?
+                };
+                self.lines.push(SourceLine { address: self.here()?, file: self.file.to_string(), line: token.line });
+                self.rom.push(value as u8);
+            }
+            _ => return Err(self.error(token, format!("unknown directive `{}`", token.text))),
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, token: Token) -> CompileResult<()> {
+        use Instruction::*;
+
+        self.statement = token.clone();
+        match token.text.as_str() {
+            text if text.starts_with(':') => self.directive(&token)?,
+            ";" | "return" => self.emit(Ret)?,
+            "clear" => self.emit(Cls)?,
+            "hires" => self.emit(High)?,
+            "lores" => self.emit(Low)?,
+            "exit" => self.emit(Exit)?,
+            "scroll-left" => self.emit(Scl)?,
+            "scroll-right" => self.emit(Scr)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(Scd(n))?;
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(Scu(n))?;
+            }
+            "audio" => self.emit(Audio)?,
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(Plane(n))?;
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(LdBVx(x))?;
+            }
+            "save" => self.register_range(StoreRegs, SaveRange)?,
+            "load" => self.register_range(LoadRegs, LoadRange)?,
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(StoreRpl(x))?;
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(LoadRpl(x))?;
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(Drw(x, y, n))?;
+            }
+            "jump" => self.emit_address(Jp, Width::Nnn)?,
+            "jump0" => self.emit_address(JpV0, Width::Nnn)?,
+            "native" => self.emit_address(Sys, Width::Nnn)?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.emit(match token.text.as_str() {
+                    "delay" => LdDtVx(x),
+                    "buzzer" => LdStVx(x),
+                    _ => Pitch(x),
+                })?;
+            }
+            "i" => self.i_statement()?,
+            "if" => self.if_statement()?,
+            "else" => {
+                let Some(Block::If { jump, .. }) = self.blocks.pop() else {
+                    return Err(self.error(&token, "`else` without `if ... begin`"))
+                };
+                let end = self.emit_jump()?;
+                self.patch(jump, Width::Nnn, self.here()?, &token)?;
+                self.blocks.push(Block::Else { jump: end, token });
+            }
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump, .. } | Block::Else { jump, .. }) => self.patch(jump, Width::Nnn, self.here()?, &token)?,
+                _ => return Err(self.error(&token, "`end` without `begin`")),
+            },
+            "loop" => self.blocks.push(Block::Loop { start: self.here()?, breaks: vec![], token }),
+            "while" => {
+                if !self.blocks.iter().any(|block| matches!(block, Block::Loop { .. })) {
+                    return Err(self.error(&token, "`while` outside of a loop"))
+                }
+                let condition = self.condition()?;
+                // Leaves the loop when the condition is false
+                self.emit(condition.skip_if_true)?;
+                let jump = self.emit_jump()?;
+                if let Some(Block::Loop { breaks, .. }) = self.blocks.iter_mut().rev().find(|block| matches!(block, Block::Loop { .. })) {
+                    breaks.push(jump);
+                }
+            }
+            "again" => {
+                let Some(Block::Loop { start, breaks, .. }) = self.blocks.pop() else {
+                    return Err(self.error(&token, "`again` without `loop`"))
+                };
+                let start = self.in_range(&token, Width::Nnn, start)?;
+                self.emit(Jp(start))?;
+                for jump in breaks {
+                    self.patch(jump, Width::Nnn, self.here()?, &token)?;
+                }
+            }
+            text if self.is_register(text) => {
+                let x = self.register_of(&token)?;
+                self.assignment(x)?;
+            }
+            text if self.macros.contains_key(text) => {
+                let Macro { params, body } = self.macros[text].clone();
+                let mut args = HashMap::new();
+                for param in params {
+                    args.insert(param, self.next()?.text);
+                }
+                for mut body_token in body.into_iter().rev() {
+                    if let Some(arg) = args.get(&body_token.text) {
+                        body_token.text = arg.clone();
+                    }
+                    self.tokens.push_front(body_token);
+                }
+            }
+            _ if self.value_of(&token).is_some() => {
+                // A bare number is a data byte
+                self.tokens.push_front(token.clone());
+                self.directive(&Token { text: ":byte".to_string(), ..token })?;
+            }
+            _ => {
+                // A bare name calls the subroutine of that name
+                self.tokens.push_front(token);
+                self.emit_address(Call, Width::Nnn)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn compile(mut self) -> CompileResult<Assembly> {
+        let start = Token { text: "main".to_string(), line: 1, column: 1 };
+        self.fixups.push(Fixup { offset: 0, width: Width::Nnn, label: start });
+        self.emit(Instruction::Jp(0))?;
+
+        while let Some(token) = self.tokens.pop_front() {
+            self.statement(token)?;
+        }
+        if let Some(block) = self.blocks.last() {
+            let (Block::If { token, .. } | Block::Else { token, .. } | Block::Loop { token, .. }) = block;
+            return Err(self.error(token, format!("`{}` is never closed", token.text)))
+        }
+        for fixup in std::mem::take(&mut self.fixups) {
+            let Some(&address) = self.labels.get(&fixup.label.text) else {
+                return Err(self.error(&fixup.label, format!("undefined name `{}`", fixup.label.text)))
+            };
+            self.patch(fixup.offset, fixup.width, address, &fixup.label)?;
+        }
+
+        let symbols = SymbolMap {
+            labels: self.labels,
+            constants: self.constants.into_iter().map(|(name, value)| (name, value as i64)).collect(),
+            lines: self.lines,
+        };
+        Ok(Assembly { rom: self.rom, symbols })
+    }
+}
+
+/// Compiles the Octo program `source`, naming `file` in errors and source lines
+pub fn compile(file: &str, source: &str) -> Result<Assembly, AsmError> {
+    Compiler {
+        file,
+        tokens: tokenize(source),
+        rom: vec![],
+        labels: BTreeMap::new(),
+        constants: BTreeMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        fixups: vec![],
+        blocks: vec![],
+        lines: vec![],
+        statement: Token { text: String::new(), line: 1, column: 1 },
+    }.compile()
+}
+
+pub fn compile_file(path: &Path) -> color_eyre::Result<Assembly> {
+    let source = fs::read_to_string(path)?;
+    Ok(compile(&path.display().to_string(), &source)?)
+}
+
+/// Whether the file at `path` is Octo source rather than a ROM
+pub fn is_octo_source(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case(OCTO_EXTENSION))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn rom(source: &str) -> Vec<u8> {
+        compile("test.8o", source).unwrap().rom
+    }
+
+    fn error(source: &str) -> String {
+        compile("test.8o", source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_statements() {
+        assert_eq!(rom("
+: main
+    clear
+    v0 := 5
+    v1 += v0
+    v2 -= 1
+    i := logo
+    sprite v0 v1 5
+    i := hex v2
+    save v3
+    delay := v0
+    loop again
+: logo
+    0xFF 0x81
+"), vec![
+            0x00, 0xE0, 0x60, 0x05, 0x81, 0x04, 0x72, 0xFF, 0xA2, 0x14, 0xD0, 0x15, 0xF2, 0x29, 0xF3, 0x55,
+            0xF0, 0x15, 0x12, 0x12, 0xFF, 0x81,
+        ]);
+    }
+
+    #[test]
+    fn test_jump_to_main() {
+        assert_eq!(rom(": sub return : main sub"), vec![0x12, 0x04, 0x00, 0xEE, 0x22, 0x02]);
+    }
+
+    #[test]
+    fn test_control_flow() {
+        assert_eq!(rom("
+: main
+    if v0 == 3 then v1 := 1
+    if v0 key begin
+        v1 := 2
+    else
+        v1 := 3
+    end
+    loop
+        while v2 != v3
+        v2 += 1
+    again
+"), vec![
+            // if then
+            0x40, 0x03, 0x61, 0x01,
+            // if begin else end
+            0xE0, 0x9E, 0x12, 0x0C, 0x61, 0x02, 0x12, 0x0E, 0x61, 0x03,
+            // loop while again
+            0x92, 0x30, 0x12, 0x16, 0x72, 0x01, 0x12, 0x0E,
+        ]);
+    }
+
+    #[test]
+    fn test_const_alias_calc_and_macros() {
+        let assembly = compile("test.8o", "
+:const SPEED 3
+:alias x v4
+:calc HALF { 64 / 2 - SPEED }
+:macro move reg amount { reg += amount }
+: main
+    x := HALF
+    move x SPEED
+    :byte { 1 + 2 * 3 }
+").unwrap();
+        // Right to left without precedence, so 64 / (2 - 3) and 1 + (2 * 3)
+        assert_eq!(assembly.rom, vec![0x64, 0xC0, 0x74, 0x03, 0x07]);
+        assert_eq!(assembly.symbols.constants["HALF"], -64);
+        assert_eq!(assembly.symbols.labels["main"], 0x200);
+        assert_eq!(assembly.symbols.lines[1], SourceLine { address: 0x202, file: "test.8o".to_string(), line: 5 });
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(error(": main\n  v0 := 300"), "test.8o:2:9: byte 300 is out of range (-128 to 255)");
+        assert_eq!(error(": main\n  jump nowhere"), "test.8o:2:8: undefined name `nowhere`");
+        assert_eq!(error(": main\n  loop\n v0 := 1"), "test.8o:2:3: `loop` is never closed");
+        assert_eq!(error(": start"), "test.8o:1:1: undefined name `main`");
+        assert_eq!(error(": main : main"), "test.8o:1:10: `main` is already defined");
+    }
+
+    #[test]
+    fn test_address_range() {
+        let filler = "0 ".repeat(0xE00);
+        assert_eq!(error(&format!(": main\n  jump far\n{}\n: far", filler)), "test.8o:2:8: address 4098 is out of range");
+        assert_eq!(error(&format!(": main\n{}\n  loop\n  again", filler)), "test.8o:4:3: address 4096 is out of range");
+
+        let memory = "0 ".repeat(0x10000 - PROGRAM_START as usize);
+        assert_eq!(rom(&format!(": main\n{}", memory)).len(), 0xFE00);
+        assert_eq!(error(&format!(": main\n{}\n: tail", memory)), "test.8o:3:1: the program doesn't fit in the 64 KB address space");
+    }
+}
@@ -0,0 +1,115 @@
+use ratatui::style::{Color, Style};
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter, IntoEnumIterator};
+
+/// Colours of the emulator screen
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Display, EnumIter)]
+pub enum Palette {
+    /// White on black
+    #[default]
+    Classic,
+    Amber,
+    GreenPhosphor,
+    /// The default colours of the Octo IDE
+    Octo,
+    /// The four shades of the original Game Boy
+    GameBoy,
+    /// Colours picked by a ROM, in the order of [`Palette::colors`]
+    #[strum(disabled)]
+    Custom([Color; 4]),
+}
+
+impl Palette {
+    /// Colours indexed by the XO-CHIP plane bits of a pixel: background, plane 1, plane 2 and
+    /// both planes. Single-plane screens only use the first two
+    pub fn colors(self) -> [Color; 4] {
+        match self {
+            Palette::Classic => [
+                Color::Rgb(0x00, 0x00, 0x00),
+                Color::Rgb(0xFF, 0xFF, 0xFF),
+                Color::Rgb(0xAA, 0xAA, 0xAA),
+                Color::Rgb(0x55, 0x55, 0x55),
+            ],
+            Palette::Amber => [
+                Color::Rgb(0x1A, 0x0F, 0x00),
+                Color::Rgb(0xFF, 0xB0, 0x00),
+                Color::Rgb(0xCC, 0x70, 0x00),
+                Color::Rgb(0x66, 0x38, 0x00),
+            ],
+            Palette::GreenPhosphor => [
+                Color::Rgb(0x00, 0x14, 0x00),
+                Color::Rgb(0x33, 0xFF, 0x33),
+                Color::Rgb(0x00, 0xAA, 0x00),
+                Color::Rgb(0x00, 0x55, 0x00),
+            ],
+            Palette::Octo => [
+                Color::Rgb(0x99, 0x66, 0x00),
+                Color::Rgb(0xFF, 0xCC, 0x00),
+                Color::Rgb(0xFF, 0x66, 0x00),
+                Color::Rgb(0x66, 0x22, 0x00),
+            ],
+            Palette::GameBoy => [
+                Color::Rgb(0x9B, 0xBC, 0x0F),
+                Color::Rgb(0x0F, 0x38, 0x0F),
+                Color::Rgb(0x30, 0x62, 0x30),
+                Color::Rgb(0x8B, 0xAC, 0x0F),
+            ],
+            Palette::Custom(colors) => colors,
+        }
+    }
+
+    pub fn background(self) -> Color { self.colors()[0] }
+
+    pub fn foreground(self) -> Color { self.colors()[1] }
+
+    /// Colour of a pixel lit with brightness `level`, blending the foreground into the background
+    pub fn shade(self, level: u8) -> Color {
+        match (level, self.background(), self.foreground()) {
+            (0, background, _) => background,
+            (u8::MAX, _, foreground) => foreground,
+            (level, Color::Rgb(br, bg, bb), Color::Rgb(fr, fg, fb)) => {
+                let blend = |from: u8, to: u8| {
+                    (from as i32 + (to as i32 - from as i32) * level as i32 / u8::MAX as i32) as u8
+                };
+                Color::Rgb(blend(br, fr), blend(bg, fg), blend(bb, fb))
+            }
+            (_, _, foreground) => foreground,
+        }
+    }
+
+    /// Style of a monochrome screen: lit pixels in the foreground, unlit in the background
+    pub fn style(self) -> Style {
+        Style::default().fg(self.foreground()).bg(self.background())
+    }
+
+    /// The palette after this one, wrapping around. Custom palettes are followed by the first one
+    pub fn next(self) -> Self {
+        Self::iter().cycle().skip_while(|palette| *palette != self).nth(1).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_shade() {
+        assert_eq!(Palette::Classic.shade(0), Color::Rgb(0x00, 0x00, 0x00));
+        assert_eq!(Palette::Classic.shade(0x80), Color::Rgb(0x80, 0x80, 0x80));
+        assert_eq!(Palette::Classic.shade(0xFF), Color::Rgb(0xFF, 0xFF, 0xFF));
+    }
+
+    #[test]
+    fn test_next_cycles_through_all() {
+        let mut palette = Palette::Classic;
+        let mut seen = vec![];
+        for _ in Palette::iter() {
+            seen.push(palette);
+            palette = palette.next();
+        }
+        assert_eq!(palette, Palette::Classic);
+        assert_eq!(seen, Palette::iter().collect::<Vec<_>>());
+    }
+}
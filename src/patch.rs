@@ -0,0 +1,443 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use color_eyre::eyre::eyre;
+use serde::{Deserialize, Serialize};
+
+use crate::cli::{PatchArgs, PatchCommand};
+use crate::emulator::rom_hash;
+use crate::rom::MAX_PROGRAM_SIZE;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+/// Longest record of an IPS patch
+const IPS_MAX_RECORD: usize = 0xFFFF;
+const BPS_MAGIC: &[u8] = b"BPS1";
+/// Size of the source, target and patch checksums closing a BPS patch
+const BPS_FOOTER_SIZE: usize = 12;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PatchFormat {
+    Ips,
+    Bps,
+}
+
+impl PatchFormat {
+    /// Every format, in the order patches next to a ROM are applied
+    pub const ALL: [PatchFormat; 2] = [PatchFormat::Ips, PatchFormat::Bps];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            PatchFormat::Ips => "ips",
+            PatchFormat::Bps => "bps",
+        }
+    }
+
+    /// The format of the patch file at `path`, by its extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        Self::ALL.into_iter().find(|format| format.extension() == extension)
+    }
+}
+
+impl fmt::Display for PatchFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+/// A patch listed in a ROM profile
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeclaredPatch {
+    /// Relative to the directory of the ROM
+    pub path: PathBuf,
+    /// SHA-1 the patched ROM must have
+    #[serde(default)]
+    pub sha1: Option<String>,
+}
+
+/// Applies `patch`, in the format given by its header, to `rom`
+pub fn apply(rom: &[u8], patch: &[u8]) -> color_eyre::Result<Vec<u8>> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(rom, patch)
+    } else {
+        Err(eyre!("not an IPS or BPS patch"))
+    }
+}
+
+/// The patch turning `source` into `target`
+pub fn create(source: &[u8], target: &[u8], format: PatchFormat) -> color_eyre::Result<Vec<u8>> {
+    match format {
+        PatchFormat::Ips => create_ips(source, target),
+        PatchFormat::Bps => Ok(create_bps(source, target)),
+    }
+}
+
+/// Applies the patch file at `path` to `rom`, naming the file in errors
+pub fn apply_file(rom: &[u8], path: &Path) -> color_eyre::Result<Vec<u8>> {
+    apply(rom, &fs::read(path)?).map_err(|err| eyre!("{}: {}", path.display(), err))
+}
+
+/// The IPS and BPS patches named after the ROM at `rom_path`, e.g. `pong.ips` for `pong.ch8`,
+/// in the order they are applied
+pub fn adjacent(rom_path: &Path) -> Vec<(PatchFormat, PathBuf)> {
+    PatchFormat::ALL.into_iter()
+        .map(|format| (format, rom_path.with_extension(format.extension())))
+        .filter(|(_, path)| path.exists())
+        .collect()
+}
+
+/// Applies the patches of a ROM profile in order, checking the SHA-1 of the result of those
+/// that give one
+pub fn apply_declared(rom: Vec<u8>, rom_path: &Path, patches: &[DeclaredPatch]) -> color_eyre::Result<Vec<u8>> {
+    let directory = rom_path.parent().unwrap_or(Path::new(""));
+    let mut rom = rom;
+    for declared in patches {
+        let path = directory.join(&declared.path);
+        rom = apply_file(&rom, &path)?;
+        if let Some(expected) = &declared.sha1 {
+            let hash = rom_hash(&rom);
+            if !hash.eq_ignore_ascii_case(expected) {
+                return Err(eyre!("{} gave a ROM with SHA-1 {} instead of {}", path.display(), hash, expected))
+            }
+        }
+        log::info!("Applied the patch {}", path.display());
+    }
+    Ok(rom)
+}
+
+/// Reads a big-endian number of `size` bytes at `*offset`, moving past it
+fn read_be(patch: &[u8], offset: &mut usize, size: usize) -> color_eyre::Result<usize> {
+    let bytes = patch.get(*offset..*offset + size).ok_or_else(|| eyre!("the patch ends early"))?;
+    *offset += size;
+    Ok(bytes.iter().fold(0, |value, &byte| value << 8 | byte as usize))
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> color_eyre::Result<Vec<u8>> {
+    let mut output = rom.to_vec();
+    let mut offset = IPS_MAGIC.len();
+    loop {
+        if patch.get(offset..offset + IPS_EOF.len()) == Some(IPS_EOF) {
+            offset += IPS_EOF.len();
+            break
+        }
+        let address = read_be(patch, &mut offset, 3)?;
+        let size = read_be(patch, &mut offset, 2)?;
+        let data = match size {
+            // Run-length encoded record
+            0 => {
+                let count = read_be(patch, &mut offset, 2)?;
+                vec![read_be(patch, &mut offset, 1)? as u8; count]
+            }
+            _ => {
+                let data = patch.get(offset..offset + size).ok_or_else(|| eyre!("the patch ends early"))?;
+                offset += size;
+                data.to_vec()
+            }
+        };
+        if output.len() < address + data.len() {
+            output.resize(address + data.len(), 0);
+        }
+        output[address..address + data.len()].copy_from_slice(&data);
+    }
+    // An optional size to truncate to follows the end marker
+    if patch.len() >= offset + 3 {
+        let size = read_be(patch, &mut offset, 3)?;
+        output.truncate(size);
+    }
+    Ok(output)
+}
+
+fn create_ips(source: &[u8], target: &[u8]) -> color_eyre::Result<Vec<u8>> {
+    let mut patch = IPS_MAGIC.to_vec();
+    let differs = |i: usize| source.get(i) != target.get(i);
+    let mut i = 0;
+    while i < target.len() {
+        if !differs(i) {
+            i += 1;
+            continue
+        }
+        let start = i;
+        while i < target.len() && i - start < IPS_MAX_RECORD && differs(i) {
+            i += 1;
+        }
+        // The offset `EOF` would read as the end of the patch
+        if start == 0x454F46 || start > 0xFFFFFF {
+            return Err(eyre!("the ROM is too large for an IPS patch"))
+        }
+        patch.extend_from_slice(&(start as u32).to_be_bytes()[1..]);
+        patch.extend_from_slice(&((i - start) as u16).to_be_bytes());
+        patch.extend_from_slice(&target[start..i]);
+    }
+    patch.extend_from_slice(IPS_EOF);
+    if target.len() < source.len() {
+        patch.extend_from_slice(&(target.len() as u32).to_be_bytes()[1..]);
+    }
+    Ok(patch)
+}
+
+fn read_varint(patch: &[u8], offset: &mut usize) -> color_eyre::Result<usize> {
+    let invalid = || eyre!("invalid number in the patch");
+    let mut value = 0usize;
+    let mut shift = 1usize;
+    // Longer numbers can't fit in a usize
+    for _ in 0..usize::BITS / 7 {
+        let byte = *patch.get(*offset).ok_or_else(|| eyre!("the patch ends early"))?;
+        *offset += 1;
+        let bits = ((byte & 0x7F) as usize).checked_mul(shift).ok_or_else(invalid)?;
+        value = value.checked_add(bits).ok_or_else(invalid)?;
+        if byte & 0x80 != 0 {
+            return Ok(value)
+        }
+        shift = shift.checked_mul(0x80).ok_or_else(invalid)?;
+        value = value.checked_add(shift).ok_or_else(invalid)?;
+    }
+    Err(invalid())
+}
+
+fn write_varint(patch: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            patch.push(0x80 | byte);
+            return
+        }
+        patch.push(byte);
+        value -= 1;
+    }
+}
+
+fn read_crc(patch: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([patch[offset], patch[offset + 1], patch[offset + 2], patch[offset + 3]])
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> color_eyre::Result<Vec<u8>> {
+    if patch.len() < BPS_MAGIC.len() + BPS_FOOTER_SIZE {
+        return Err(eyre!("the patch ends early"))
+    }
+    let footer = patch.len() - BPS_FOOTER_SIZE;
+    if crc32fast::hash(&patch[..patch.len() - 4]) != read_crc(patch, footer + 8) {
+        return Err(eyre!("the patch is damaged"))
+    }
+    if crc32fast::hash(rom) != read_crc(patch, footer) {
+        return Err(eyre!("the patch is for a different ROM"))
+    }
+
+    let mut offset = BPS_MAGIC.len();
+    let source_size = read_varint(patch, &mut offset)?;
+    let target_size = read_varint(patch, &mut offset)?;
+    let metadata_size = read_varint(patch, &mut offset)?;
+    offset = offset.saturating_add(metadata_size);
+    if source_size != rom.len() {
+        return Err(eyre!("the patch is for a ROM of {} bytes", source_size))
+    }
+    // The sizes come from the patch, so they are checked before anything is allocated
+    if source_size > MAX_PROGRAM_SIZE || target_size > MAX_PROGRAM_SIZE {
+        return Err(eyre!("the patch is for ROMs larger than the {} bytes that fit in memory", MAX_PROGRAM_SIZE))
+    }
+
+    let mut output = Vec::with_capacity(target_size);
+    let (mut source_offset, mut target_offset) = (0isize, 0isize);
+    let out_of_range = || eyre!("the patch reads outside of the ROM");
+    while offset < footer {
+        let data = read_varint(patch, &mut offset)?;
+        let length = (data >> 2) + 1;
+        if length > target_size - output.len() {
+            return Err(eyre!("the patch writes past the {} bytes of the patched ROM", target_size))
+        }
+        match data & 3 {
+            // Source read
+            0 => {
+                let bytes = rom.get(output.len()..output.len() + length).ok_or_else(out_of_range)?;
+                output.extend_from_slice(bytes);
+            }
+            // Target read
+            1 => {
+                let bytes = patch.get(offset..offset + length).filter(|_| offset + length <= footer)
+                    .ok_or_else(|| eyre!("the patch ends early"))?;
+                output.extend_from_slice(bytes);
+                offset += length;
+            }
+            // Source copy
+            2 => {
+                let relative = read_varint(patch, &mut offset)?;
+                let delta = (relative >> 1) as isize;
+                source_offset += if relative & 1 != 0 { -delta } else { delta };
+                let start = usize::try_from(source_offset).map_err(|_| out_of_range())?;
+                output.extend_from_slice(rom.get(start..start + length).ok_or_else(out_of_range)?);
+                source_offset += length as isize;
+            }
+            // Target copy, which may overlap what it writes
+            _ => {
+                let relative = read_varint(patch, &mut offset)?;
+                let delta = (relative >> 1) as isize;
+                target_offset += if relative & 1 != 0 { -delta } else { delta };
+                for _ in 0..length {
+                    let start = usize::try_from(target_offset).map_err(|_| out_of_range())?;
+                    let byte = *output.get(start).ok_or_else(out_of_range)?;
+                    output.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if output.len() != target_size {
+        return Err(eyre!("the patch gave {} bytes instead of {}", output.len(), target_size))
+    }
+    if crc32fast::hash(&output) != read_crc(patch, footer + 4) {
+        return Err(eyre!("the patched ROM doesn't match the checksum of the patch"))
+    }
+    Ok(output)
+}
+
+/// A BPS patch made of source and target reads only, which is plenty for ROMs of a few
+/// kilobytes
+fn create_bps(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut patch = BPS_MAGIC.to_vec();
+    write_varint(&mut patch, source.len());
+    write_varint(&mut patch, target.len());
+    write_varint(&mut patch, 0);
+
+    let same = |i: usize| source.get(i) == target.get(i);
+    let mut i = 0;
+    while i < target.len() {
+        let start = i;
+        let is_source_read = same(i);
+        while i < target.len() && same(i) == is_source_read {
+            i += 1;
+        }
+        let length = i - start;
+        if is_source_read {
+            write_varint(&mut patch, (length - 1) << 2);
+        } else {
+            write_varint(&mut patch, (length - 1) << 2 | 1);
+            patch.extend_from_slice(&target[start..i]);
+        }
+    }
+
+    patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+    patch.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+    let checksum = crc32fast::hash(&patch);
+    patch.extend_from_slice(&checksum.to_le_bytes());
+    patch
+}
+
+/// Runs the `patch` subcommand
+pub fn run(args: &PatchArgs) -> color_eyre::Result<()> {
+    match &args.command {
+        PatchCommand::Create(create_args) => {
+            let format = create_args.format
+                .or_else(|| PatchFormat::from_path(&create_args.output))
+                .unwrap_or(PatchFormat::Bps);
+            let source = fs::read(&create_args.original)?;
+            let target = fs::read(&create_args.modified)?;
+            fs::write(&create_args.output, create(&source, &target, format)?)?;
+        }
+        PatchCommand::Apply(apply_args) => {
+            let rom = fs::read(&apply_args.rom)?;
+            let patched = apply(&rom, &fs::read(&apply_args.patch)?)?;
+            if let Some(expected) = &apply_args.sha1 {
+                let hash = rom_hash(&patched);
+                if !hash.eq_ignore_ascii_case(expected) {
+                    return Err(eyre!("the patched ROM has SHA-1 {} instead of {}", hash, expected))
+                }
+            }
+            fs::write(&apply_args.output, patched)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn roms() -> Vec<(Vec<u8>, Vec<u8>)> {
+        let source: Vec<u8> = (0..=255).cycle().take(600).collect();
+        let mut changed = source.clone();
+        changed[3] = 0xAA;
+        changed[100..110].fill(0);
+        let mut longer = changed.clone();
+        longer.extend_from_slice(&[1, 2, 3]);
+        vec![
+            (source.clone(), changed),
+            (source.clone(), longer),
+            (source.clone(), source[..500].to_vec()),
+            (source.clone(), source.clone()),
+            (vec![], vec![0x12, 0x00]),
+        ]
+    }
+
+    #[test]
+    fn test_round_trips() {
+        for format in PatchFormat::ALL {
+            for (source, target) in roms() {
+                let patch = create(&source, &target, format).unwrap();
+                assert_eq!(apply(&source, &patch).unwrap(), target, "{} patch", format);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ips_run_length_records() {
+        // 4 bytes of 0xFF at 0x0002
+        let patch = b"PATCH\x00\x00\x02\x00\x00\x00\x04\xFFEOF";
+        assert_eq!(apply(&[0; 8], patch).unwrap(), vec![0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0]);
+    }
+
+    #[test]
+    fn test_bps_checks_checksums() {
+        let (source, target) = roms().remove(0);
+        let patch = create(&source, &target, PatchFormat::Bps).unwrap();
+        let err = apply(&target, &patch).unwrap_err();
+        assert_eq!(err.to_string(), "the patch is for a different ROM");
+
+        let mut damaged = patch.clone();
+        damaged[8] ^= 1;
+        assert_eq!(apply(&source, &damaged).unwrap_err().to_string(), "the patch is damaged");
+        assert!(apply(&source, b"NOPE").is_err());
+    }
+
+    /// A BPS patch of `source` with `body` between its header and footer
+    fn bps_patch(source: &[u8], target_size: usize, body: &[u8]) -> Vec<u8> {
+        let mut patch = BPS_MAGIC.to_vec();
+        write_varint(&mut patch, source.len());
+        write_varint(&mut patch, target_size);
+        write_varint(&mut patch, 0);
+        patch.extend_from_slice(body);
+        patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+        patch.extend_from_slice(&0u32.to_le_bytes());
+        let checksum = crc32fast::hash(&patch);
+        patch.extend_from_slice(&checksum.to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn test_bps_bounds_sizes_from_the_patch() {
+        let source = [1, 2, 3, 4];
+        let err = apply(&source, &bps_patch(&source, usize::MAX >> 8, &[])).unwrap_err();
+        assert_eq!(err.to_string(), "the patch is for ROMs larger than the 3584 bytes that fit in memory");
+
+        // A target copy of a huge length after a single target read
+        let mut body = vec![0x81, 0xAA];
+        write_varint(&mut body, (usize::MAX >> 10) << 2 | 3);
+        write_varint(&mut body, 0);
+        let err = apply(&source, &bps_patch(&source, 8, &body)).unwrap_err();
+        assert_eq!(err.to_string(), "the patch writes past the 8 bytes of the patched ROM");
+
+        // Nine continuation bytes and a last one whose bits don't fit in a usize
+        let mut huge = vec![0; 9];
+        huge.push(0x82);
+        let err = read_varint(&huge, &mut 0).unwrap_err();
+        assert_eq!(err.to_string(), "invalid number in the patch");
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+use crate::renderer::Framebuffer;
+
+/// Brightness of a fully lit pixel
+pub const FULL: u8 = u8::MAX;
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Display)]
+pub enum PersistenceMode {
+    /// Only the latest frame is shown
+    #[default]
+    Off,
+    /// A pixel stays lit while it was lit in any of the last frames
+    Or,
+    /// Cleared pixels fade out over the next frames
+    Fade,
+}
+
+/// Display filter that simulates the phosphor persistence of a CRT, hiding the flicker of
+/// sprites that are erased and redrawn every frame
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Persistence {
+    #[serde(default)]
+    pub mode: PersistenceMode,
+    /// Number of frames that are blended, including the latest one
+    #[serde(default = "default_frames")]
+    pub frames: usize,
+}
+
+fn default_frames() -> usize {
+    3
+}
+
+impl Default for Persistence {
+    fn default() -> Self {
+        Self { mode: PersistenceMode::default(), frames: default_frames() }
+    }
+}
+
+/// Framebuffer with a brightness per pixel, from 0 (off) to [`FULL`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShadedFrame {
+    pub width: usize,
+    pub height: usize,
+    pub levels: Vec<u8>,
+}
+
+impl ShadedFrame {
+    pub fn level(&self, x: usize, y: usize) -> u8 {
+        if x >= self.width || y >= self.height {
+            return 0
+        }
+        self.levels[y * self.width + x]
+    }
+}
+
+impl From<&Framebuffer> for ShadedFrame {
+    fn from(framebuffer: &Framebuffer) -> Self {
+        let levels = (0..framebuffer.height)
+            .flat_map(|y| (0..framebuffer.width).map(move |x| (x, y)))
+            .map(|(x, y)| if framebuffer.pixel(x, y) { FULL } else { 0 })
+            .collect();
+        Self { width: framebuffer.width, height: framebuffer.height, levels }
+    }
+}
+
+/// Blends the last frames according to a [`Persistence`] setting
+#[derive(Debug, Clone, Default)]
+pub struct PhosphorFilter {
+    persistence: Persistence,
+    history: VecDeque<Framebuffer>,
+}
+
+impl PhosphorFilter {
+    pub fn new(persistence: Persistence) -> Self {
+        Self { persistence, history: VecDeque::new() }
+    }
+
+    pub fn set_persistence(&mut self, persistence: Persistence) {
+        self.persistence = persistence;
+        self.history.clear();
+    }
+
+    /// Adds the latest frame to the history
+    pub fn push(&mut self, framebuffer: Framebuffer) {
+        let frames = self.persistence.frames.max(1);
+        // A change of resolution makes older frames meaningless
+        if self.history.front().is_some_and(|latest| latest.data.len() != framebuffer.data.len()) {
+            self.history.clear();
+        }
+        self.history.push_front(framebuffer);
+        self.history.truncate(frames);
+    }
+
+    /// The blended frame, or none before the first frame was pushed
+    pub fn output(&self) -> Option<ShadedFrame> {
+        let latest = self.history.front()?;
+        let mut frame = ShadedFrame::from(latest);
+        if self.persistence.mode == PersistenceMode::Off {
+            return Some(frame)
+        }
+
+        let frames = self.persistence.frames.max(1);
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                // Age of the most recent frame in which the pixel was lit
+                let Some(age) = self.history.iter().position(|old| old.pixel(x, y)) else {
+                    continue
+                };
+                frame.levels[y * frame.width + x] = match self.persistence.mode {
+                    PersistenceMode::Off | PersistenceMode::Or => FULL,
+                    PersistenceMode::Fade => (FULL as usize * (frames - age) / frames) as u8,
+                };
+            }
+        }
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn frame_with_pixel(lit: bool) -> Framebuffer {
+        let mut data = vec![0x00; 8 * 32];
+        if lit {
+            data[0] = 0x80;
+        }
+        Framebuffer::from_data(data)
+    }
+
+    #[test]
+    fn test_off_shows_latest_frame() {
+        let mut filter = PhosphorFilter::new(Persistence::default());
+        filter.push(frame_with_pixel(true));
+        filter.push(frame_with_pixel(false));
+        assert_eq!(filter.output().unwrap().level(0, 0), 0);
+    }
+
+    #[test]
+    fn test_or_keeps_recent_pixels() {
+        let mut filter = PhosphorFilter::new(Persistence { mode: PersistenceMode::Or, frames: 2 });
+        filter.push(frame_with_pixel(true));
+        filter.push(frame_with_pixel(false));
+        assert_eq!(filter.output().unwrap().level(0, 0), FULL);
+        filter.push(frame_with_pixel(false));
+        assert_eq!(filter.output().unwrap().level(0, 0), 0);
+    }
+
+    #[test]
+    fn test_fade_dims_cleared_pixels() {
+        let mut filter = PhosphorFilter::new(Persistence { mode: PersistenceMode::Fade, frames: 3 });
+        filter.push(frame_with_pixel(true));
+        assert_eq!(filter.output().unwrap().level(0, 0), FULL);
+        filter.push(frame_with_pixel(false));
+        assert_eq!(filter.output().unwrap().level(0, 0), 170);
+        filter.push(frame_with_pixel(false));
+        assert_eq!(filter.output().unwrap().level(0, 0), 85);
+        filter.push(frame_with_pixel(false));
+        assert_eq!(filter.output().unwrap().level(0, 0), 0);
+    }
+}
@@ -0,0 +1,98 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::emulator::Quirks;
+use crate::keypad::KeypadOverride;
+use crate::palette::Palette;
+use crate::patch::DeclaredPatch;
+use crate::persistence::Persistence;
+
+/// Directory of the profiles inside the config directory
+pub const PROFILES_DIR: &str = "profiles";
+
+/// Settings tuned for a single ROM. They take precedence over the ROM database and the config
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RomProfile {
+    pub quirks: Option<Quirks>,
+    pub cycles_per_frame: Option<u32>,
+    pub palette: Option<Palette>,
+    pub persistence: Option<Persistence>,
+    pub keypad: Option<KeypadOverride>,
+    /// Applied in order when the ROM is loaded
+    pub patches: Option<Vec<DeclaredPatch>>,
+}
+
+impl RomProfile {
+    /// Path of the profile named `key`, a ROM hash or filename
+    pub fn path(config_dir: &Path, key: &str) -> PathBuf {
+        config_dir.join(PROFILES_DIR).join(format!("{}.json5", key))
+    }
+
+    /// Loads the profile of a ROM, looked up by the SHA-1 `hash` of the ROM first and by its
+    /// `filename` second
+    pub fn load(config_dir: &Path, hash: &str, filename: &str) -> color_eyre::Result<Option<Self>> {
+        for key in [hash, filename] {
+            let path = Self::path(config_dir, key);
+            if path.exists() {
+                let profile = json5::from_str(&fs::read_to_string(&path)?)?;
+                log::info!("Loaded the ROM profile {}", path.display());
+                return Ok(Some(profile))
+            }
+        }
+        Ok(None)
+    }
+
+    /// Writes the profile for the ROM with SHA-1 `hash`, returning its path
+    pub fn save(&self, config_dir: &Path, hash: &str) -> color_eyre::Result<PathBuf> {
+        let path = Self::path(config_dir, hash);
+        fs::create_dir_all(config_dir.join(PROFILES_DIR))?;
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::keypad::KeypadPreset;
+    use crate::persistence::PersistenceMode;
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("chip8-profile-test-{}", std::process::id()));
+        let profile = RomProfile {
+            quirks: Some(Quirks { superchip_shift: true, ..Quirks::default() }),
+            cycles_per_frame: Some(30),
+            palette: Some(Palette::Amber),
+            persistence: Some(Persistence { mode: PersistenceMode::Fade, frames: 4 }),
+            keypad: Some(KeypadOverride { preset: Some(KeypadPreset::Dvorak), ..KeypadOverride::default() }),
+            patches: Some(vec![DeclaredPatch { path: "fix.bps".into(), sha1: Some("0123abcd".to_string()) }]),
+        };
+        profile.save(&dir, "abc").unwrap();
+        let loaded = RomProfile::load(&dir, "abc", "pong.ch8").unwrap();
+        let by_name = RomProfile::load(&dir, "def", "abc").unwrap();
+        let missing = RomProfile::load(&dir, "def", "pong.ch8").unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded, Some(profile.clone()));
+        assert_eq!(by_name, Some(profile));
+        assert_eq!(missing, None);
+    }
+
+    #[test]
+    fn test_partial_profile() {
+        let profile: RomProfile = json5::from_str(r#"{
+            // Only the settings that differ
+            quirks: { superchip_memory: true },
+            cycles_per_frame: 12,
+        }"#).unwrap();
+        assert_eq!(profile.quirks, Some(Quirks { superchip_memory: true, ..Quirks::default() }));
+        assert_eq!(profile.cycles_per_frame, Some(12));
+        assert_eq!(profile.palette, None);
+    }
+}
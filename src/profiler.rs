@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::Write as _;
+
+use crate::asm::PROGRAM_START;
+
+/// Extensions of the exported profiles, after the name of the ROM
+pub const FLAT_PROFILE_EXTENSION: &str = "profile.txt";
+pub const FOLDED_PROFILE_EXTENSION: &str = "folded";
+
+/// Number of frames whose instruction counts are kept
+const FRAME_HISTORY: usize = 3600;
+/// Bytes after an `LD Vx, DT` still taken as part of the loop waiting for the delay timer
+const DELAY_LOOP_SIZE: u16 = 8;
+/// Entries of the flat profile
+const FLAT_PROFILE_ENTRIES: usize = 40;
+
+/// Where the cycles of a run go: executions per address and per call stack, instructions per
+/// frame and cycles spent waiting for a key or for the delay timer
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    executions: Vec<u64>,
+    /// Entries of the subroutines called and not returned from, outermost first
+    call_stack: Vec<u16>,
+    /// Cycles executed with each call stack at the top
+    stacks: HashMap<Vec<u16>, u64>,
+    cycles: u64,
+    cycles_in_frame: u32,
+    /// Instructions of the last frames, oldest first
+    frames: VecDeque<u32>,
+    frame_count: u64,
+    key_wait_cycles: u64,
+    delay_wait_cycles: u64,
+    /// Address of the `LD Vx, DT` of the loop waiting for the delay timer, if in one
+    delay_loop: Option<u16>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self {
+            executions: vec![0; 0x1000],
+            call_stack: vec![PROGRAM_START],
+            stacks: HashMap::new(),
+            cycles: 0,
+            cycles_in_frame: 0,
+            frames: VecDeque::new(),
+            frame_count: 0,
+            key_wait_cycles: 0,
+            delay_wait_cycles: 0,
+            delay_loop: None,
+        }
+    }
+}
+
+impl Profiler {
+    /// Records the execution of `opcode` at `address`, after which the program counter is
+    /// `next`. `delay_timer` is the value of the delay timer before the instruction
+    pub fn record(&mut self, address: u16, opcode: u16, next: u16, delay_timer: u8) {
+        self.cycles += 1;
+        self.cycles_in_frame += 1;
+        if let Some(executions) = self.executions.get_mut(address as usize) {
+            *executions += 1;
+        }
+        match self.stacks.get_mut(self.call_stack.as_slice()) {
+            Some(cycles) => *cycles += 1,
+            None => { self.stacks.insert(self.call_stack.clone(), 1); },
+        }
+
+        // FX0A runs again until a key is released
+        if opcode & 0xF0FF == 0xF00A && next == address {
+            self.key_wait_cycles += 1;
+        }
+        if opcode & 0xF0FF == 0xF007 {
+            self.delay_loop = if delay_timer > 0 { Some(address) } else { None };
+        }
+        match self.delay_loop {
+            Some(start) if (start..start + DELAY_LOOP_SIZE).contains(&address) => self.delay_wait_cycles += 1,
+            _ => self.delay_loop = None,
+        }
+
+        match opcode {
+            0x2000..=0x2FFF => self.call_stack.push(opcode & 0x0FFF),
+            // A return without a call leaves the entry point on the stack
+            0x00EE if self.call_stack.len() > 1 => { self.call_stack.pop(); },
+            _ => {}
+        }
+    }
+
+    /// Ends the current frame
+    pub fn end_frame(&mut self) {
+        self.frames.push_back(self.cycles_in_frame);
+        if self.frames.len() > FRAME_HISTORY {
+            self.frames.pop_front();
+        }
+        self.cycles_in_frame = 0;
+        self.frame_count += 1;
+    }
+
+    /// Executions of every address of memory
+    pub fn executions(&self) -> &[u64] {
+        &self.executions
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Smallest, mean and largest number of instructions of the recent frames
+    pub fn instructions_per_frame(&self) -> Option<(u32, f64, u32)> {
+        let min = *self.frames.iter().min()?;
+        let max = *self.frames.iter().max()?;
+        let mean = self.frames.iter().map(|&count| count as f64).sum::<f64>() / self.frames.len() as f64;
+        Some((min, mean, max))
+    }
+
+    /// Cycles executed in each subroutine itself and in it and the subroutines it calls
+    pub fn subroutines(&self) -> BTreeMap<u16, (u64, u64)> {
+        let mut subroutines: BTreeMap<u16, (u64, u64)> = BTreeMap::new();
+        for (stack, &cycles) in &self.stacks {
+            if let Some(&top) = stack.last() {
+                subroutines.entry(top).or_default().0 += cycles;
+            }
+            // Recursive subroutines count once per stack
+            let mut seen = stack.clone();
+            seen.sort_unstable();
+            seen.dedup();
+            for entry in seen {
+                subroutines.entry(entry).or_default().1 += cycles;
+            }
+        }
+        subroutines
+    }
+
+    /// Call stacks and their cycles in the folded format of flamegraph tools, e.g.
+    /// `start;sub_2A0 1234`. Subroutines are named by `name`
+    pub fn to_folded(&self, name: impl Fn(u16) -> String) -> String {
+        let mut lines: Vec<String> = self.stacks.iter()
+            .map(|(stack, cycles)| {
+                let frames: Vec<String> = stack.iter().map(|&entry| name(entry)).collect();
+                format!("{} {}", frames.join(";"), cycles)
+            })
+            .collect();
+        lines.sort();
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    /// Summary of the run followed by the busiest subroutines and addresses. Subroutines are
+    /// named by `name`
+    pub fn to_flat(&self, name: impl Fn(u16) -> String) -> String {
+        let percent = |cycles: u64| 100.0 * cycles as f64 / self.cycles.max(1) as f64;
+        let mut text = String::new();
+        let _ = writeln!(text, "Cycles: {}", self.cycles);
+        let _ = writeln!(text, "Frames: {}", self.frame_count);
+        if let Some((min, mean, max)) = self.instructions_per_frame() {
+            let _ = writeln!(text, "Instructions per frame: min {} mean {:.1} max {}", min, mean, max);
+        }
+        let _ = writeln!(text, "Waiting for a key (FX0A): {} cycles, {:.1}%", self.key_wait_cycles, percent(self.key_wait_cycles));
+        let _ = writeln!(text, "Waiting for the delay timer: {} cycles, {:.1}%", self.delay_wait_cycles, percent(self.delay_wait_cycles));
+
+        let _ = writeln!(text, "\n{:>12} {:>6} {:>12} {:>6}  subroutine", "self", "%", "total", "%");
+        let mut subroutines: Vec<_> = self.subroutines().into_iter().collect();
+        subroutines.sort_by_key(|(entry, (own, _))| (std::cmp::Reverse(*own), *entry));
+        for (entry, (own, total)) in subroutines {
+            let _ = writeln!(text, "{:>12} {:>6.2} {:>12} {:>6.2}  {}", own, percent(own), total, percent(total), name(entry));
+        }
+
+        let _ = writeln!(text, "\n{:>12} {:>6}  address", "executions", "%");
+        let mut addresses: Vec<(u16, u64)> = self.executions.iter().enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(address, &count)| (address as u16, count))
+            .collect();
+        addresses.sort_by_key(|&(address, count)| (std::cmp::Reverse(count), address));
+        for (address, count) in addresses.into_iter().take(FLAT_PROFILE_ENTRIES) {
+            let _ = writeln!(text, "{:>12} {:>6.2}  0x{:03X}", count, percent(count), address);
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn name(address: u16) -> String {
+        format!("sub_{:03X}", address)
+    }
+
+    #[test]
+    fn test_counts_per_subroutine() {
+        let mut profiler = Profiler::default();
+        // 0x200: CALL 0x300, 0x300: CLS, 0x302: RET, 0x202: JP 0x202
+        profiler.record(0x200, 0x2300, 0x300, 0);
+        profiler.record(0x300, 0x00E0, 0x302, 0);
+        profiler.record(0x302, 0x00EE, 0x202, 0);
+        profiler.end_frame();
+        profiler.record(0x202, 0x1202, 0x202, 0);
+        profiler.end_frame();
+
+        assert_eq!(profiler.cycles(), 4);
+        assert_eq!(profiler.executions()[0x300], 1);
+        assert_eq!(profiler.instructions_per_frame(), Some((1, 2.0, 3)));
+        assert_eq!(profiler.subroutines(), BTreeMap::from([(0x200, (2, 4)), (0x300, (2, 2))]));
+        assert_eq!(profiler.to_folded(name), "sub_200 2\nsub_200;sub_300 2\n");
+    }
+
+    #[test]
+    fn test_counts_waiting() {
+        let mut profiler = Profiler::default();
+        // 0x200: LD V0, K waiting, then done
+        profiler.record(0x200, 0xF00A, 0x200, 0);
+        profiler.record(0x200, 0xF00A, 0x202, 0);
+        // 0x202: LD V0, DT, 0x204: SE V0, 0, 0x206: JP 0x202
+        for delay_timer in [2, 2, 2, 1, 1, 1] {
+            profiler.record(0x202, 0xF007, 0x204, delay_timer);
+            profiler.record(0x204, 0x3000, 0x206, delay_timer);
+            profiler.record(0x206, 0x1202, 0x202, delay_timer);
+        }
+        profiler.record(0x202, 0xF007, 0x204, 0);
+        profiler.record(0x204, 0x3000, 0x208, 0);
+
+        assert_eq!(profiler.key_wait_cycles, 1);
+        assert_eq!(profiler.delay_wait_cycles, 18);
+    }
+}
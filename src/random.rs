@@ -0,0 +1,113 @@
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+/// Source of the random bytes used by the 0xCXNN opcode
+pub trait RandomSource: Send {
+    /// Restarts the sequence from `seed`
+    fn reseed(&mut self, seed: u64);
+
+    /// Returns the next random byte. `memory` is the emulator memory, for sources that mix it in
+    fn next_byte(&mut self, memory: &[u8]) -> u8;
+
+    /// Called once per 60Hz frame
+    fn on_frame(&mut self) {}
+}
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Display, clap::ValueEnum)]
+pub enum RandomKind {
+    /// Seeded general purpose PRNG
+    #[default]
+    Prng,
+    /// Mimics the random routine of the original COSMAC VIP interpreter
+    CosmacVip,
+}
+
+impl RandomKind {
+    pub fn create(self, seed: u64) -> Box<dyn RandomSource> {
+        let mut source: Box<dyn RandomSource> = match self {
+            RandomKind::Prng => Box::new(Prng::default()),
+            RandomKind::CosmacVip => Box::new(CosmacVipRandom::default()),
+        };
+        source.reseed(seed);
+        source
+    }
+}
+
+pub struct Prng {
+    rng: StdRng,
+}
+
+impl Default for Prng {
+    fn default() -> Self { Self { rng: StdRng::seed_from_u64(0) } }
+}
+
+impl RandomSource for Prng {
+    fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    fn next_byte(&mut self, _memory: &[u8]) -> u8 {
+        self.rng.gen()
+    }
+}
+
+/// Modelled on the VIP interpreter routine: a 16-bit counter (R9 on the VIP) that also advances
+/// every frame is incremented, its low byte indexes a page of code and the byte read there is
+/// added to its high byte, which becomes the new high byte. This emulator has no interpreter code
+/// in low memory, so the first page of the program (0x200..0x2FF) is read instead
+#[derive(Default)]
+pub struct CosmacVipRandom {
+    r9: u16,
+}
+
+impl RandomSource for CosmacVipRandom {
+    fn reseed(&mut self, seed: u64) {
+        self.r9 = seed as u16;
+    }
+
+    fn next_byte(&mut self, memory: &[u8]) -> u8 {
+        self.r9 = self.r9.wrapping_add(1);
+        let [high, low] = self.r9.to_be_bytes();
+        let code = memory.get(0x200 + low as usize).copied().unwrap_or_default();
+        let result = code.wrapping_add(high);
+        self.r9 = (result as u16) << 8 | low as u16;
+        result
+    }
+
+    fn on_frame(&mut self) {
+        self.r9 = self.r9.wrapping_add(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::{assert_eq, assert_ne};
+
+    use super::*;
+
+    fn sequence(kind: RandomKind, seed: u64, memory: &[u8]) -> Vec<u8> {
+        let mut source = kind.create(seed);
+        (0..64).map(|_| source.next_byte(memory)).collect()
+    }
+
+    #[test]
+    fn test_prng_is_seeded() {
+        let memory = vec![0x00; 4096];
+        assert_eq!(sequence(RandomKind::Prng, 42, &memory), sequence(RandomKind::Prng, 42, &memory));
+        assert_ne!(sequence(RandomKind::Prng, 42, &memory), sequence(RandomKind::Prng, 43, &memory));
+    }
+
+    #[test]
+    fn test_cosmac_vip_reads_program_page() {
+        let mut memory = vec![0x00; 4096];
+        memory[0x201] = 0x10;
+        memory[0x202] = 0x20;
+        let mut source = RandomKind::CosmacVip.create(0x0500);
+        assert_eq!(source.next_byte(&memory), 0x15);
+        assert_eq!(source.next_byte(&memory), 0x35);
+        source.on_frame();
+        assert_eq!(source.next_byte(&memory), 0x35);
+    }
+}
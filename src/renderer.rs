@@ -0,0 +1,192 @@
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+use crate::palette::Palette;
+use crate::persistence::ShadedFrame;
+
+/// Monochrome framebuffer of the emulator, one bit per pixel and 8 pixels per byte
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+}
+
+impl Framebuffer {
+    /// Wraps the data of an `Action::Redraw`. 1024 bytes are a SUPER-CHIP hires screen
+    pub fn from_data(data: Vec<u8>) -> Self {
+        let (width, height) = if data.len() == 128 / 8 * 64 { (128, 64) } else { (64, 32) };
+        Self { width, height, data }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        if x >= self.width || y >= self.height {
+            return false
+        }
+        let byte = self.data.get(y * self.width / 8 + x / 8).copied().unwrap_or(0);
+        byte & (0x80 >> (x % 8)) != 0
+    }
+}
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Display)]
+pub enum RendererKind {
+    /// Picks the least dense renderer that still fits the available area
+    #[default]
+    Auto,
+    /// Every pixel is two `█` characters wide
+    Block,
+    /// Two vertical pixels per character using `▀▄█`
+    HalfBlock,
+    /// 2x4 pixels per character using braille patterns
+    Braille,
+}
+
+impl RendererKind {
+    /// Renderers in order of preference, from the most faithful to the densest
+    const PREFERENCE: [RendererKind; 3] = [RendererKind::Block, RendererKind::HalfBlock, RendererKind::Braille];
+
+    /// Size in characters (columns, rows) needed to render a `width` x `height` framebuffer
+    pub fn size(self, width: usize, height: usize) -> (u16, u16) {
+        let (columns, rows) = match self {
+            RendererKind::Auto | RendererKind::Block => (width * 2, height),
+            RendererKind::HalfBlock => (width, height.div_ceil(2)),
+            RendererKind::Braille => (width.div_ceil(2), height.div_ceil(4)),
+        };
+        (columns as u16, rows as u16)
+    }
+
+    /// Resolves `Auto` to the least dense renderer that fits into `columns` x `rows`
+    pub fn resolve(self, width: usize, height: usize, columns: u16, rows: u16) -> RendererKind {
+        if self != RendererKind::Auto {
+            return self
+        }
+        Self::PREFERENCE.into_iter()
+            .find(|kind| {
+                let (needed_columns, needed_rows) = kind.size(width, height);
+                needed_columns <= columns && needed_rows <= rows
+            })
+            .unwrap_or(RendererKind::Braille)
+    }
+
+    /// Renders the frame into one line per row of characters, coloured with `palette`
+    pub fn render(self, frame: &ShadedFrame, palette: Palette) -> Vec<Line<'static>> {
+        let (columns, rows) = self.size(frame.width, frame.height);
+        let level = |x: usize, y: usize| frame.level(x, y);
+        let background = palette.shade(0);
+        (0..rows as usize)
+            .map(|row| {
+                let cells = (0..columns as usize).map(|column| match self {
+                    RendererKind::Auto | RendererKind::Block => {
+                        let level = level(column / 2, row);
+                        if level > 0 { ('█', palette.shade(level), background) } else { (' ', background, background) }
+                    }
+                    RendererKind::HalfBlock => {
+                        match (level(column, row * 2), level(column, row * 2 + 1)) {
+                            (0, 0) => (' ', background, background),
+                            (top, bottom) if top == bottom => ('█', palette.shade(top), background),
+                            (top, bottom) => ('▀', palette.shade(top), palette.shade(bottom)),
+                        }
+                    }
+                    RendererKind::Braille => {
+                        let brightest = (0..8).map(|dot| level(column * 2 + dot % 2, row * 4 + dot / 2)).max();
+                        let pattern = braille(|dx, dy| level(column * 2 + dx, row * 4 + dy) > 0);
+                        (pattern, palette.shade(brightest.unwrap_or(0)), background)
+                    }
+                });
+                line_from_cells(cells)
+            })
+            .collect()
+    }
+}
+
+/// Joins runs of characters with the same colours into spans
+fn line_from_cells(cells: impl Iterator<Item = (char, Color, Color)>) -> Line<'static> {
+    let mut spans: Vec<Span> = Vec::new();
+    let mut current = String::new();
+    let mut current_style: Option<Style> = None;
+    for (symbol, fg, bg) in cells {
+        let style = Style::default().fg(fg).bg(bg);
+        if current_style != Some(style) {
+            if let Some(previous) = current_style {
+                spans.push(Span::styled(std::mem::take(&mut current), previous));
+            }
+            current_style = Some(style);
+        }
+        current.push(symbol);
+    }
+    if let Some(style) = current_style {
+        spans.push(Span::styled(current, style));
+    }
+    Line::from(spans)
+}
+
+/// Braille pattern of a 2x4 cell, `pixel(dx, dy)` giving the pixel at the offset in the cell
+fn braille(pixel: impl Fn(usize, usize) -> bool) -> char {
+    // Bit of every dot of the braille cell, indexed by [dy][dx]
+    const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+    let mut pattern = 0;
+    for (dy, row) in DOTS.iter().enumerate() {
+        for (dx, dot) in row.iter().enumerate() {
+            if pixel(dx, dy) {
+                pattern |= dot;
+            }
+        }
+    }
+    char::from_u32(0x2800 + pattern).unwrap_or(' ')
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn checkerboard() -> ShadedFrame {
+        let data = (0..32).flat_map(|row| vec![if row % 2 == 0 { 0xAA } else { 0x55 }; 8]).collect();
+        ShadedFrame::from(&Framebuffer::from_data(data))
+    }
+
+    fn render_text(kind: RendererKind, frame: &ShadedFrame) -> Vec<String> {
+        kind.render(frame, Palette::Classic).iter()
+            .map(|line| line.spans.iter().map(|span| span.content.as_ref()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_renderer_sizes() {
+        let frame = checkerboard();
+        for kind in RendererKind::PREFERENCE {
+            let lines = render_text(kind, &frame);
+            let (columns, rows) = kind.size(64, 32);
+            assert_eq!(lines.len(), rows as usize);
+            assert!(lines.iter().all(|line| line.chars().count() == columns as usize));
+        }
+    }
+
+    #[test]
+    fn test_half_block() {
+        let lines = RendererKind::HalfBlock.render(&checkerboard(), Palette::Classic);
+        // Lit top pixels use the foreground, lit bottom pixels the background colour
+        assert_eq!(lines[0].spans[0].content, "▀");
+        assert_eq!(lines[0].spans[0].style.fg, Some(Palette::Classic.foreground()));
+        assert_eq!(lines[0].spans[1].content, "▀");
+        assert_eq!(lines[0].spans[1].style.bg, Some(Palette::Classic.foreground()));
+    }
+
+    #[test]
+    fn test_braille() {
+        let lines = render_text(RendererKind::Braille, &checkerboard());
+        // Dots 1, 3, 5 and 8 of the pattern
+        assert!(lines[0].starts_with("⢕⢕"));
+    }
+
+    #[test]
+    fn test_auto_picks_fitting_renderer() {
+        assert_eq!(RendererKind::Auto.resolve(64, 32, 128, 32), RendererKind::Block);
+        assert_eq!(RendererKind::Auto.resolve(64, 32, 100, 32), RendererKind::HalfBlock);
+        assert_eq!(RendererKind::Auto.resolve(128, 64, 100, 32), RendererKind::Braille);
+        assert_eq!(RendererKind::HalfBlock.resolve(64, 32, 200, 40), RendererKind::HalfBlock);
+    }
+}
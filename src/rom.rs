@@ -0,0 +1,135 @@
+use std::fs;
+use std::path::Path;
+
+use color_eyre::eyre::eyre;
+
+use crate::asm::PROGRAM_START;
+use crate::emulator::rom_hash;
+use crate::octo;
+use crate::patch::{self, PatchFormat};
+use crate::profile::RomProfile;
+use crate::romdb::RomDatabase;
+use crate::symbols::SymbolMap;
+
+/// Bytes of memory from the start of programs to the end of the CHIP-8 memory
+pub const MAX_PROGRAM_SIZE: usize = 0x1000 - PROGRAM_START as usize;
+
+/// A program read from disk with every patch applied
+#[derive(Debug, Clone, Default)]
+pub struct LoadedProgram {
+    pub rom: Vec<u8>,
+    /// From the compiler, or from the symbol map next to a ROM
+    pub symbols: Option<SymbolMap>,
+    /// SHA-1 of the program before patching, which the ROM database and the profile describe
+    pub hash: String,
+    pub profile: Option<RomProfile>,
+    /// Problems that left the program loaded without its profile or some of its patches
+    pub warnings: Vec<String>,
+}
+
+/// Reads the program at `path`, compiling it if it's Octo source
+fn read_program(path: &Path) -> color_eyre::Result<(Vec<u8>, Option<SymbolMap>)> {
+    if octo::is_octo_source(path) {
+        let assembly = octo::compile_file(path)?;
+        return Ok((assembly.rom, Some(assembly.symbols)))
+    }
+    let symbols = SymbolMap::load_for_rom(path).unwrap_or_else(|err| {
+        log::error!("Failed to read the symbols of {}: {}", path.display(), err);
+        None
+    });
+    Ok((fs::read(path)?, symbols))
+}
+
+/// Reads the program at `path` with its profile from `config_dir`, then applies the patches
+/// next to a ROM and those its profile declares. An IPS patch next to a ROM has no checksum, so
+/// it is only applied to a ROM whose SHA-1 is in `database` or names a profile. BPS patches
+/// check the ROM they are for themselves. A patch that fails to apply is left out with a warning
+pub fn load_program(path: &Path, config_dir: &Path, database: &RomDatabase) -> color_eyre::Result<LoadedProgram> {
+    let (mut rom, symbols) = read_program(path)?;
+    let hash = rom_hash(&rom);
+    let filename = path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+    let mut warnings = Vec::new();
+    let profile = RomProfile::load(config_dir, &hash, &filename).unwrap_or_else(|err| {
+        warnings.push(format!("Failed to read the profile of {}: {}", filename, err));
+        None
+    });
+
+    if !octo::is_octo_source(path) {
+        let is_known = database.lookup(&hash).is_some() || RomProfile::path(config_dir, &hash).exists();
+        for (format, patch_path) in patch::adjacent(path) {
+            if format == PatchFormat::Ips && !is_known {
+                warnings.push(format!(
+                    "Skipped {}: IPS patches have no checksum and the SHA-1 of {} is neither in the ROM database nor in a profile",
+                    patch_path.display(), filename,
+                ));
+                continue
+            }
+            match patch::apply_file(&rom, &patch_path) {
+                Ok(patched) => {
+                    rom = patched;
+                    log::info!("Applied the patch {}", patch_path.display());
+                }
+                Err(err) => warnings.push(format!("Failed to apply {}: {}", patch_path.display(), err)),
+            }
+        }
+    }
+    if let Some(patches) = profile.as_ref().and_then(|profile| profile.patches.as_ref()) {
+        match patch::apply_declared(rom.clone(), path, patches) {
+            Ok(patched) => rom = patched,
+            Err(err) => warnings.push(format!("Failed to patch {}: {}", filename, err)),
+        }
+    }
+
+    if rom.len() > MAX_PROGRAM_SIZE {
+        return Err(eyre!("{} is {} bytes, more than the {} that fit in memory", path.display(), rom.len(), MAX_PROGRAM_SIZE))
+    }
+    Ok(LoadedProgram { rom, symbols, hash, profile, warnings })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::patch::DeclaredPatch;
+
+    #[test]
+    fn test_load_program_patches() {
+        let dir = std::env::temp_dir().join(format!("chip8-rom-test-{}", std::process::id()));
+        let config_dir = dir.join("config");
+        fs::create_dir_all(&config_dir).unwrap();
+        let rom_path = dir.join("game.ch8");
+        let original = vec![0x12, 0x00];
+        fs::write(&rom_path, &original).unwrap();
+        fs::write(dir.join("game.ips"), patch::create(&original, &[0x12, 0x02], PatchFormat::Ips).unwrap()).unwrap();
+        let database = RomDatabase::default();
+
+        let unknown = load_program(&rom_path, &config_dir, &database).unwrap();
+
+        let hash = rom_hash(&original);
+        fs::write(dir.join("grow.ips"), patch::create(&[0x12, 0x02], &vec![1; MAX_PROGRAM_SIZE + 1], PatchFormat::Ips).unwrap()).unwrap();
+        let profile = RomProfile {
+            patches: Some(vec![DeclaredPatch { path: "grow.ips".into(), sha1: None }]),
+            ..RomProfile::default()
+        };
+        profile.save(&config_dir, &hash).unwrap();
+        let too_large = load_program(&rom_path, &config_dir, &database).unwrap_err();
+
+        RomProfile::default().save(&config_dir, &hash).unwrap();
+        let known = load_program(&rom_path, &config_dir, &database).unwrap();
+
+        // A patch for another revision of the ROM
+        fs::write(dir.join("game.bps"), patch::create(&[0x12, 0x04], &[0x12, 0x06], PatchFormat::Bps).unwrap()).unwrap();
+        let mismatched = load_program(&rom_path, &config_dir, &database).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(unknown.rom, original);
+        assert_eq!(unknown.warnings.len(), 1);
+        assert!(too_large.to_string().contains("more than the 3584 that fit in memory"));
+        assert_eq!(known.rom, vec![0x12, 0x02]);
+        assert_eq!(known.hash, hash);
+        assert!(known.warnings.is_empty());
+        assert_eq!(mismatched.rom, vec![0x12, 0x02]);
+        assert_eq!(mismatched.warnings.len(), 1);
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use color_eyre::eyre::eyre;
+use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
+
+use crate::emulator::Quirks;
+use crate::isa::InstructionSet;
+use crate::palette::Palette;
+
+/// Programs of the bundled database, in the format of the community chip-8-database
+const PROGRAMS: &str = include_str!("../database/programs.json");
+const PLATFORMS: &str = include_str!("../database/platforms.json");
+/// Name of the file in the config directory whose programs are added over the bundled ones
+pub const USER_PROGRAMS_FILE: &str = "programs.json";
+
+/// Behaviours that differ between CHIP-8 platforms, as named by the database
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PlatformQuirks {
+    pub shift: bool,
+    pub memory_increment_by_x: bool,
+    pub memory_leave_i_unchanged: bool,
+    pub wrap: bool,
+    pub jump: bool,
+    pub vblank: bool,
+    pub logic: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Platform {
+    pub id: String,
+    pub name: String,
+    /// Instructions executed per frame
+    pub default_tickrate: u32,
+    pub quirks: PlatformQuirks,
+}
+
+impl Platform {
+    /// Instruction set the programs of the platform are decoded with
+    pub fn instruction_set(&self) -> InstructionSet {
+        match self.id.as_str() {
+            "xochip" => InstructionSet::XoChip,
+            "superchip1" | "superchip" | "megachip8" => InstructionSet::Schip,
+            _ => InstructionSet::Chip8,
+        }
+    }
+
+    /// Emulator quirks of the platform, with the quirks of the database taking precedence
+    fn emulator_quirks(&self, quirks: PlatformQuirks) -> Quirks {
+        Quirks {
+            superchip_opcodes: self.instruction_set() > InstructionSet::Chip8,
+            superchip_shift: quirks.shift,
+            superchip_offset_jump: quirks.jump,
+            superchip_memory: quirks.memory_leave_i_unchanged,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RomColors {
+    /// Background first, then the colours of the lit planes, as `#rrggbb`
+    pub pixels: Vec<String>,
+}
+
+/// A single version of a program
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Rom {
+    pub file: Option<String>,
+    pub description: Option<String>,
+    /// Platforms the ROM runs on, best one first
+    pub platforms: Vec<String>,
+    /// Quirks that differ from those of the platform
+    pub quirky_platforms: HashMap<String, PlatformQuirks>,
+    /// Keypad keys of the controls, e.g. `"up": 5`
+    pub keys: HashMap<String, u8>,
+    pub tickrate: Option<u32>,
+    pub colors: Option<RomColors>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Program {
+    pub title: String,
+    pub description: Option<String>,
+    pub authors: Vec<String>,
+    pub release: Option<String>,
+    /// Versions of the program keyed by the SHA-1 of their bytes
+    pub roms: HashMap<String, Rom>,
+}
+
+/// Everything the database knows about a ROM, resolved into emulator settings
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RomMetadata {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub release: Option<String>,
+    pub description: Option<String>,
+    pub platform: Option<String>,
+    pub quirks: Option<Quirks>,
+    /// Instructions executed per frame
+    pub tickrate: Option<u32>,
+    pub palette: Option<Palette>,
+    /// Keypad keys of the controls, sorted by name
+    pub keys: Vec<(String, u8)>,
+}
+
+fn parse_color(raw: &str) -> Option<Color> {
+    let hex = raw.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some(Color::Rgb((value >> 16) as u8, (value >> 8) as u8, value as u8))
+}
+
+impl RomColors {
+    /// Missing plane colours repeat the last given one
+    fn palette(&self) -> Option<Palette> {
+        let colors: Vec<Color> = self.pixels.iter().map(|raw| parse_color(raw)).collect::<Option<_>>()?;
+        let (&background, planes) = colors.split_first()?;
+        let &last = planes.last()?;
+        let plane = |i: usize| planes.get(i).copied().unwrap_or(last);
+        Some(Palette::Custom([background, plane(0), plane(1), plane(2)]))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RomDatabase {
+    programs: Vec<Program>,
+    platforms: Vec<Platform>,
+    /// Index into `programs` of every known ROM hash
+    hashes: HashMap<String, usize>,
+}
+
+impl RomDatabase {
+    pub fn new(programs: Vec<Program>, platforms: Vec<Platform>) -> Self {
+        let mut database = Self { programs: vec![], platforms, hashes: HashMap::new() };
+        database.add_programs(programs);
+        database
+    }
+
+    pub fn bundled() -> Self {
+        Self::new(
+            serde_json::from_str(PROGRAMS).expect("The bundled programs are valid"),
+            serde_json::from_str(PLATFORMS).expect("The bundled platforms are valid"),
+        )
+    }
+
+    /// The bundled database with the programs of `config_dir` added over it
+    pub fn load(config_dir: &Path) -> color_eyre::Result<Self> {
+        let mut database = Self::bundled();
+        let path = config_dir.join(USER_PROGRAMS_FILE);
+        if path.exists() {
+            database.add_programs(serde_json::from_str(&fs::read_to_string(path)?)?);
+        }
+        Ok(database)
+    }
+
+    /// Adds `programs`, replacing the known programs of their ROMs
+    pub fn add_programs(&mut self, programs: Vec<Program>) {
+        for program in programs {
+            for hash in program.roms.keys() {
+                self.hashes.insert(hash.to_lowercase(), self.programs.len());
+            }
+            self.programs.push(program);
+        }
+    }
+
+    pub fn platform(&self, id: &str) -> Option<&Platform> {
+        self.platforms.iter().find(|platform| platform.id == id)
+    }
+
+    /// The bundled platform `id`, as given to the `--profile` option of the subcommands
+    pub fn profile(id: &str) -> color_eyre::Result<Platform> {
+        let database = Self::bundled();
+        match database.platform(id) {
+            Some(platform) => Ok(platform.clone()),
+            None => {
+                let ids: Vec<&str> = database.platforms.iter().map(|platform| platform.id.as_str()).collect();
+                Err(eyre!("Unknown profile {}, expected one of {}", id, ids.join(", ")))
+            }
+        }
+    }
+
+    /// The instruction set of the platform `profile` if there is one, or else `target`
+    pub fn profile_instruction_set(target: InstructionSet, profile: Option<&str>) -> color_eyre::Result<InstructionSet> {
+        match profile {
+            Some(id) => Ok(Self::profile(id)?.instruction_set()),
+            None => Ok(target),
+        }
+    }
+
+    /// Looks up the ROM with SHA-1 `hash`
+    pub fn lookup(&self, hash: &str) -> Option<RomMetadata> {
+        let program = &self.programs[*self.hashes.get(&hash.to_lowercase())?];
+        let rom = program.roms.iter()
+            .find(|(rom_hash, _)| rom_hash.eq_ignore_ascii_case(hash))
+            .map(|(_, rom)| rom)?;
+
+        let platform = rom.platforms.first().and_then(|id| self.platform(id));
+        let quirks = platform.map(|platform| {
+            let quirks = rom.quirky_platforms.get(&platform.id).copied().unwrap_or(platform.quirks);
+            platform.emulator_quirks(quirks)
+        });
+        let mut keys: Vec<(String, u8)> = rom.keys.iter().map(|(name, key)| (name.clone(), *key)).collect();
+        keys.sort();
+
+        Some(RomMetadata {
+            title: program.title.clone(),
+            authors: program.authors.clone(),
+            release: program.release.clone(),
+            description: rom.description.clone().or_else(|| program.description.clone()),
+            platform: platform.map(|platform| platform.name.clone()),
+            quirks,
+            tickrate: rom.tickrate.or(platform.map(|platform| platform.default_tickrate)),
+            palette: rom.colors.as_ref().and_then(RomColors::palette),
+            keys,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_bundled_lookup() {
+        let database = RomDatabase::bundled();
+        let metadata = database.lookup("1BA58656810B67FD131EB9AF3E3987863BF26C90").unwrap();
+        assert_eq!(metadata.title, "IBM Logo");
+        assert_eq!(metadata.platform.as_deref(), Some("Cosmac VIP"));
+        assert_eq!(metadata.tickrate, Some(15));
+        assert_eq!(metadata.quirks, Some(Quirks::default()));
+        assert_eq!(database.lookup("0000000000000000000000000000000000000000"), None);
+    }
+
+    #[test]
+    fn test_rom_settings_override_platform() {
+        let programs: Vec<Program> = serde_json::from_str(r##"[{
+            "title": "Game",
+            "authors": ["Someone"],
+            "roms": { "abc": {
+                "platforms": ["superchip"],
+                "quirkyPlatforms": { "superchip": { "shift": false, "jump": true } },
+                "tickrate": 50,
+                "keys": { "up": 5, "a": 6 },
+                "colors": { "pixels": ["#000000", "#FF8000"] }
+            } }
+        }]"##).unwrap();
+        let mut database = RomDatabase::bundled();
+        database.add_programs(programs);
+
+        let metadata = database.lookup("abc").unwrap();
+        assert_eq!(metadata.platform.as_deref(), Some("SUPER-CHIP 1.1"));
+        assert_eq!(metadata.quirks, Some(Quirks {
+            superchip_opcodes: true,
+            superchip_shift: false,
+            superchip_offset_jump: true,
+            superchip_memory: false,
+        }));
+        assert_eq!(metadata.tickrate, Some(50));
+        assert_eq!(metadata.keys, vec![("a".to_string(), 6), ("up".to_string(), 5)]);
+        let orange = Color::Rgb(0xFF, 0x80, 0x00);
+        assert_eq!(metadata.palette, Some(Palette::Custom([Color::Rgb(0, 0, 0), orange, orange, orange])));
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// Extensions of the exported sprites, after the name of the ROM
+pub const SPRITES_PNG_EXTENSION: &str = "sprites.png";
+pub const SPRITES_SOURCE_EXTENSION: &str = "sprites.asm";
+
+/// Number of distinct sprites kept in the recent history
+const RECENT_SPRITES: usize = 16;
+/// Side of the square cell every sprite is drawn in on an exported sheet
+const TILE_SIZE: usize = 16;
+/// Pixels between the cells of an exported sheet
+const TILE_GAP: usize = 1;
+const TILES_PER_ROW: usize = 8;
+
+/// A sprite drawn by `DXYN`: `height` rows starting at `address`, 8 pixels wide or 16 for the
+/// 16x16 sprites of SCHIP
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Sprite {
+    pub address: u16,
+    pub height: u8,
+    pub wide: bool,
+}
+
+impl Sprite {
+    pub fn width(&self) -> usize {
+        if self.wide { 16 } else { 8 }
+    }
+
+    pub fn bytes_per_row(&self) -> usize {
+        self.width() / 8
+    }
+
+    /// Number of bytes of memory the sprite is read from
+    pub fn size(&self) -> usize {
+        self.height as usize * self.bytes_per_row()
+    }
+
+    /// Rows of the sprite in `memory`, most significant bit leftmost. Bytes past the end of
+    /// memory read as 0
+    pub fn rows(&self, memory: &[u8]) -> Vec<u16> {
+        let byte = |offset: usize| memory.get(self.address as usize + offset).copied().unwrap_or(0) as u16;
+        (0..self.height as usize)
+            .map(|row| match self.wide {
+                true => byte(row * 2) << 8 | byte(row * 2 + 1),
+                false => byte(row) << 8,
+            })
+            .collect()
+    }
+
+    fn label(&self) -> String {
+        format!("sprite_{:03X}_{}x{}", self.address, self.width(), self.height)
+    }
+}
+
+/// The sprites drawn since the ROM was loaded
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpriteLog {
+    /// Newest first, without duplicates
+    recent: VecDeque<Sprite>,
+    /// Every sprite with its rows when it was first drawn
+    session: BTreeMap<Sprite, Vec<u16>>,
+}
+
+impl SpriteLog {
+    /// Records that `sprite` was drawn from `memory`
+    pub fn record(&mut self, sprite: Sprite, memory: &[u8]) {
+        if self.recent.front() != Some(&sprite) {
+            self.recent.retain(|recent| *recent != sprite);
+            self.recent.push_front(sprite);
+            self.recent.truncate(RECENT_SPRITES);
+        }
+        self.session.entry(sprite).or_insert_with(|| sprite.rows(memory));
+    }
+
+    pub fn recent(&self) -> Vec<Sprite> {
+        self.recent.iter().copied().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.session.is_empty()
+    }
+
+    /// The sprites of the session as assembler `db` lines, each under a label named after its
+    /// address and size
+    pub fn to_source(&self) -> String {
+        let mut source = String::new();
+        for (sprite, rows) in &self.session {
+            let _ = writeln!(source, "{}:", sprite.label());
+            for row in rows {
+                let bytes = match sprite.wide {
+                    true => format!("0b{:08b}, 0b{:08b}", row >> 8, row & 0xFF),
+                    false => format!("0b{:08b}", row >> 8),
+                };
+                let _ = writeln!(source, "    db {}", bytes);
+            }
+        }
+        source
+    }
+
+    /// The sprites of the session as a sheet of 16x16 tiles, white on black, with the width and
+    /// height of the sheet
+    pub fn to_tiles(&self) -> (Vec<u8>, usize, usize) {
+        let columns = self.session.len().clamp(1, TILES_PER_ROW);
+        let rows = self.session.len().div_ceil(TILES_PER_ROW).max(1);
+        let width = columns * (TILE_SIZE + TILE_GAP) - TILE_GAP;
+        let height = rows * (TILE_SIZE + TILE_GAP) - TILE_GAP;
+        let mut pixels = vec![0x00; width * height];
+        for (i, (sprite, sprite_rows)) in self.session.iter().enumerate() {
+            let left = i % TILES_PER_ROW * (TILE_SIZE + TILE_GAP);
+            let top = i / TILES_PER_ROW * (TILE_SIZE + TILE_GAP);
+            // Sprites taller than a tile are cut
+            for (y, row) in sprite_rows.iter().take(TILE_SIZE).enumerate() {
+                for x in 0..sprite.width() {
+                    if row & (0x8000 >> x) != 0 {
+                        pixels[(top + y) * width + left + x] = 0xFF;
+                    }
+                }
+            }
+        }
+        (pixels, width, height)
+    }
+
+    /// Writes the tile sheet of the session to a grayscale PNG
+    pub fn save_png(&self, path: &Path) -> color_eyre::Result<()> {
+        let (pixels, width, height) = self.to_tiles();
+        let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width as u32, height as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&pixels)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_records_sprites() {
+        let mut memory = vec![0x00; 0x300];
+        memory[0x200..0x203].copy_from_slice(&[0xF0, 0x90, 0xF0]);
+        let small = Sprite { address: 0x200, height: 3, wide: false };
+        let wide = Sprite { address: 0x200, height: 1, wide: true };
+
+        let mut log = SpriteLog::default();
+        log.record(small, &memory);
+        log.record(wide, &memory);
+        log.record(small, &memory);
+        assert_eq!(log.recent(), vec![small, wide]);
+
+        // The rows are kept as they were first drawn
+        memory[0x200] = 0x00;
+        log.record(small, &memory);
+        assert_eq!(log.to_source(), "\
+sprite_200_16x1:
+    db 0b11110000, 0b10010000
+sprite_200_8x3:
+    db 0b11110000
+    db 0b10010000
+    db 0b11110000
+");
+        let (pixels, width, height) = log.to_tiles();
+        assert_eq!((width, height), (33, 16));
+        assert_eq!(&pixels[..16], &[0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0xFF, 0, 0, 0xFF, 0, 0, 0, 0]);
+        assert_eq!(&pixels[17..25], &[0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00]);
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Extension of the symbol map written next to an assembled ROM
+pub const SYMBOLS_EXTENSION: &str = "sym";
+
+/// The source line that produced the bytes at `address`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceLine {
+    pub address: u16,
+    pub file: String,
+    pub line: usize,
+}
+
+/// Names and source lines of the addresses of an assembled ROM
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SymbolMap {
+    pub labels: BTreeMap<String, u16>,
+    pub constants: BTreeMap<String, i64>,
+    /// Sorted by address
+    pub lines: Vec<SourceLine>,
+}
+
+impl SymbolMap {
+    /// Path of the symbol map of the ROM at `rom`
+    pub fn path_for(rom: &Path) -> PathBuf {
+        rom.with_extension(SYMBOLS_EXTENSION)
+    }
+
+    pub fn load(path: &Path) -> color_eyre::Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Loads the symbol map next to the ROM at `rom`, if there is one
+    pub fn load_for_rom(rom: &Path) -> color_eyre::Result<Option<Self>> {
+        let path = Self::path_for(rom);
+        if !path.exists() {
+            return Ok(None)
+        }
+        Self::load(&path).map(Some)
+    }
+
+    pub fn save(&self, path: &Path) -> color_eyre::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// The label of every labelled address. Of several labels of an address the first by name
+    /// is kept
+    pub fn labels_by_address(&self) -> BTreeMap<u16, String> {
+        let mut labels = BTreeMap::new();
+        for (name, &address) in &self.labels {
+            labels.entry(address).or_insert_with(|| name.clone());
+        }
+        labels
+    }
+
+    /// The source line of the instruction or data at `address`
+    pub fn line_at(&self, address: u16) -> Option<&SourceLine> {
+        let i = self.lines.partition_point(|line| line.address <= address);
+        self.lines[..i].last()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_lookups() {
+        let symbols = SymbolMap {
+            labels: BTreeMap::from([
+                ("start".to_string(), 0x200),
+                ("loop".to_string(), 0x204),
+                ("again".to_string(), 0x204),
+            ]),
+            constants: BTreeMap::new(),
+            lines: vec![
+                SourceLine { address: 0x200, file: "a.asm".to_string(), line: 2 },
+                SourceLine { address: 0x204, file: "a.asm".to_string(), line: 5 },
+            ],
+        };
+        assert_eq!(symbols.labels_by_address(), BTreeMap::from([
+            (0x200, "start".to_string()),
+            (0x204, "again".to_string()),
+        ]));
+        assert_eq!(symbols.line_at(0x202).map(|line| line.line), Some(2));
+        assert_eq!(symbols.line_at(0x204).map(|line| line.line), Some(5));
+        assert_eq!(symbols.line_at(0x100), None);
+    }
+}
@@ -8,7 +8,8 @@ use crossterm::{
   cursor,
   event::{
     DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture, Event as CrosstermEvent,
-    KeyEvent, KeyEventKind, MouseEvent,
+    KeyEvent, KeyEventKind, KeyboardEnhancementFlags, MouseEvent, PopKeyboardEnhancementFlags,
+    PushKeyboardEnhancementFlags,
   },
   terminal::{EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -39,6 +40,7 @@ pub enum Event {
   FocusLost,
   Paste(String),
   Key(KeyEvent),
+  KeyRelease(KeyEvent),
   Mouse(MouseEvent),
   Resize(u16, u16),
 }
@@ -53,6 +55,8 @@ pub struct Tui {
   pub tick_rate: f64,
   pub mouse: bool,
   pub paste: bool,
+  /// Whether the terminal reports key releases through the kitty keyboard protocol
+  pub keyboard_enhancement: bool,
 }
 
 impl Tui {
@@ -65,7 +69,19 @@ impl Tui {
     let task = tokio::spawn(async {});
     let mouse = false;
     let paste = false;
-    Ok(Self { terminal, task, cancellation_token, event_rx, event_tx, frame_rate, tick_rate, mouse, paste })
+    let keyboard_enhancement = false;
+    Ok(Self {
+      terminal,
+      task,
+      cancellation_token,
+      event_rx,
+      event_tx,
+      frame_rate,
+      tick_rate,
+      mouse,
+      paste,
+      keyboard_enhancement,
+    })
   }
 
   pub fn tick_rate(mut self, tick_rate: f64) -> Self {
@@ -113,8 +129,10 @@ impl Tui {
               Some(Ok(evt)) => {
                 match evt {
                   CrosstermEvent::Key(key) => {
-                    if key.kind == KeyEventKind::Press {
-                      _event_tx.send(Event::Key(key)).unwrap();
+                    match key.kind {
+                      KeyEventKind::Press => _event_tx.send(Event::Key(key)).unwrap(),
+                      KeyEventKind::Release => _event_tx.send(Event::KeyRelease(key)).unwrap(),
+                      KeyEventKind::Repeat => {},
                     }
                   },
                   CrosstermEvent::Mouse(mouse) => {
@@ -177,6 +195,10 @@ impl Tui {
     if self.paste {
       crossterm::execute!(io(), EnableBracketedPaste)?;
     }
+    self.keyboard_enhancement = crossterm::terminal::supports_keyboard_enhancement().unwrap_or(false);
+    if self.keyboard_enhancement {
+      crossterm::execute!(io(), PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES))?;
+    }
     self.start();
     Ok(())
   }
@@ -185,6 +207,9 @@ impl Tui {
     self.stop()?;
     if crossterm::terminal::is_raw_mode_enabled()? {
       self.flush()?;
+      if self.keyboard_enhancement {
+        crossterm::execute!(io(), PopKeyboardEnhancementFlags)?;
+      }
       if self.paste {
         crossterm::execute!(io(), DisableBracketedPaste)?;
       }